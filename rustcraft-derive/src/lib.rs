@@ -36,13 +36,22 @@ pub fn derive_sized_prot(input: TokenStream) -> TokenStream {
                     }
                 }
             ));
+        } else if let Fields::Unit = data.fields {
+            let name = input.ident;
+            return TokenStream::from(quote!(
+                impl crate::protocol_types::traits::SizedProt for #name {
+                    fn prot_size(&self) -> usize {
+                         0
+                    }
+                }
+            ));
         }
     }
 
     TokenStream::from(
-        syn::Error::new(
-            input.ident.span(),
-            "Only structs with named fields can derive `SizedProt`",
+        syn::Error::new_spanned(
+            &input,
+            "`SizedProt` can only be derived for structs, use `ProtEnum` for enums with unit variants",
         )
         .to_compile_error(),
     )
@@ -66,7 +75,7 @@ pub fn derive_write_prot_packet(input: TokenStream) -> TokenStream {
                         &self,
                         stream: &mut (impl AsyncWrite + Unpin + Send),
                         connection: Arc<RwLock<ConnectionInfo>>,
-                    ) -> Result<(), String> {
+                    ) -> Result<(), crate::err::ProtoError> {
                     match self {
                         #(#match_arms)*
                     }
@@ -76,11 +85,11 @@ pub fn derive_write_prot_packet(input: TokenStream) -> TokenStream {
         ));
     }
     TokenStream::from(
-        syn::Error::new(
-            input.ident.span(),
-            "Only structs with named fields can derive `WriteProt`",
+        syn::Error::new_spanned(
+            &input,
+            "`WriteProtPacket` can only be derived for enums whose variants each wrap a packet",
         )
-            .to_compile_error(),
+        .to_compile_error(),
     )
 }
 
@@ -100,7 +109,7 @@ pub fn derive_write_prot(input: TokenStream) -> TokenStream {
             return TokenStream::from(quote!(
                 #[async_trait]
                 impl crate::protocol_types::traits::WriteProt for #name {
-                    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+                    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), crate::err::ProtoError> {
                         #(#field_vals)*
                         Ok(())
                     }
@@ -115,19 +124,29 @@ pub fn derive_write_prot(input: TokenStream) -> TokenStream {
             return TokenStream::from(quote!(
                 #[async_trait]
                 impl crate::protocol_types::traits::WriteProt for #name {
-                    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+                    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), crate::err::ProtoError> {
                         #(#field_vals)*
                         Ok(())
                     }
                 }
             ));
+        } else if let Fields::Unit = data.fields {
+            let name = input.ident;
+            return TokenStream::from(quote!(
+                #[async_trait]
+                impl crate::protocol_types::traits::WriteProt for #name {
+                    async fn write(&self, _stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), crate::err::ProtoError> {
+                        Ok(())
+                    }
+                }
+            ));
         }
     }
 
     TokenStream::from(
-        syn::Error::new(
-            input.ident.span(),
-            "Only structs with named fields can derive `WriteProt`",
+        syn::Error::new_spanned(
+            &input,
+            "`WriteProt` can only be derived for structs, use `ProtEnum` for enums with unit variants",
         )
         .to_compile_error(),
     )
@@ -150,7 +169,7 @@ pub fn derive_read_prot(input: TokenStream) -> TokenStream {
             return TokenStream::from(quote!(
                 #[async_trait]
                 impl crate::protocol_types::traits::ReadProt for #name {
-                    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> where Self: Sized {
+                    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, crate::err::ProtoError> where Self: Sized {
                         Ok( #name {
                                 #(#field_vals)*
                         })
@@ -166,21 +185,182 @@ pub fn derive_read_prot(input: TokenStream) -> TokenStream {
             return TokenStream::from(quote!(
                 #[async_trait]
                 impl crate::protocol_types::traits::ReadProt for #name {
-                    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> where Self: Sized {
+                    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, crate::err::ProtoError> where Self: Sized {
                         Ok( #name (
                                 #(#field_vals)*
                         ))
                     }
                 }
             ));
+        } else if let Fields::Unit = data.fields {
+            let name = input.ident;
+            return TokenStream::from(quote!(
+                #[async_trait]
+                impl crate::protocol_types::traits::ReadProt for #name {
+                    async fn read(_stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, crate::err::ProtoError> where Self: Sized {
+                        Ok(#name)
+                    }
+                }
+            ));
+        }
+    }
+
+    TokenStream::from(
+        syn::Error::new_spanned(
+            &input,
+            "`ReadProt` can only be derived for structs, use `ProtEnum` for enums with unit variants",
+        )
+        .to_compile_error(),
+    )
+}
+
+/// This derives `ReadProt`, `WriteProt` and `SizedProt` for enums with only unit variants,
+/// which are sent as a VarInt tag holding the variant's discriminant
+#[proc_macro_derive(ProtEnum)]
+pub fn derive_prot_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    if let syn::Data::Enum(ref data) = input.data {
+        if data.variants.iter().all(|variant| matches!(variant.fields, Fields::Unit)) {
+            let name = input.ident;
+            let variants = data.variants.iter().map(|variant| &variant.ident).collect::<Vec<_>>();
+            return TokenStream::from(quote!(
+                impl #name {
+                    fn prot_tag(&self) -> i32 {
+                        match self {
+                            #(#name::#variants => #name::#variants as i32,)*
+                        }
+                    }
+                }
+
+                #[async_trait]
+                impl crate::protocol_types::traits::ReadProt for #name {
+                    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, crate::err::ProtoError> where Self: Sized {
+                        let tag = crate::protocol_types::primitives::VarInt::read(stream).await?.value;
+                        #(if tag == #name::#variants as i32 {
+                            return Ok(#name::#variants);
+                        })*
+                        Err(crate::err::ProtoError::InvalidEnumTag(stringify!(#name), tag.to_string()))
+                    }
+                }
+
+                #[async_trait]
+                impl crate::protocol_types::traits::WriteProt for #name {
+                    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), crate::err::ProtoError> {
+                        crate::protocol_types::primitives::VarInt::from(self.prot_tag()).write(stream).await
+                    }
+                }
+
+                impl crate::protocol_types::traits::SizedProt for #name {
+                    fn prot_size(&self) -> usize {
+                        crate::protocol_types::primitives::VarInt::from(self.prot_tag()).prot_size()
+                    }
+                }
+            ));
         }
     }
 
     TokenStream::from(
-        syn::Error::new(
-            input.ident.span(),
-            "Only structs with named fields can derive `ReadProt`",
+        syn::Error::new_spanned(
+            &input,
+            "`ProtEnum` can only be derived for enums with unit variants",
         )
         .to_compile_error(),
     )
 }
+
+/// This derives a clientbound packet from a struct with named fields, the attribute
+/// `#[packet(id = 0x50, state = Play)]` gives its id and the connection state it is sent in.
+/// Generates `id()`, a constructor taking all fields in order, `SizedProt` including the id,
+/// `Display` and `WriteProtPacket`, like the `packet!` macro does for packets without a handler.
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let mut id: Option<syn::LitInt> = None;
+    let mut state: Option<syn::Ident> = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("packet")) {
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("state") {
+                state = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `id` or `state`"))
+            }
+        });
+        if let Err(e) = parsed {
+            return TokenStream::from(e.to_compile_error());
+        }
+    }
+    let (Some(id), Some(state)) = (id, state) else {
+        return TokenStream::from(
+            syn::Error::new_spanned(&input, "`Packet` requires `#[packet(id = ..., state = ...)]`")
+                .to_compile_error(),
+        );
+    };
+
+    if let syn::Data::Struct(ref data) = input.data {
+        if let Fields::Named(ref fields) = data.fields {
+            let names = fields.named.iter().map(|field| &field.ident).collect::<Vec<_>>();
+            let types = fields.named.iter().map(|field| &field.ty).collect::<Vec<_>>();
+            let name = &input.ident;
+            return TokenStream::from(quote!(
+                impl #name {
+                    pub(crate) const STATE: crate::connection::ConnectionState = crate::connection::ConnectionState::#state;
+
+                    pub(crate) fn new(#(#names: #types,)*) -> Self {
+                        Self {
+                            #(#names,)*
+                        }
+                    }
+                }
+
+                impl crate::protocol_types::traits::ClientPacket for #name {
+                    fn id() -> u8 {
+                        #id
+                    }
+                }
+
+                impl crate::protocol_types::traits::SizedProt for #name {
+                    fn prot_size(&self) -> usize {
+                        use crate::protocol_types::traits::ClientPacket;
+                        crate::protocol_types::primitives::VarInt::from(Self::id() as usize).prot_size()
+                            #(+ self.#names.prot_size())*
+                    }
+                }
+
+                impl std::fmt::Display for #name {
+                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                        write!(f, "{}", stringify!(#name))
+                    }
+                }
+
+                #[async_trait::async_trait]
+                impl crate::protocol_types::traits::WriteProtPacket for #name {
+                    async fn write(
+                        &self,
+                        stream: &mut (impl tokio::io::AsyncWrite + Unpin + Send),
+                        connection: std::sync::Arc<std::sync::RwLock<crate::connection::ConnectionInfo>>,
+                    ) -> Result<(), crate::err::ProtoError> {
+                        use crate::protocol_types::traits::{ClientPacket, SizedProt, WriteProt};
+                        let size = crate::protocol_types::primitives::VarInt::from(self.prot_size());
+                        let len = self.prot_size() + size.prot_size();
+                        let mut buf: Vec<u8> = connection.write().unwrap().buffer_pool.take(len);
+                        size.write(&mut buf).await?;
+                        crate::protocol_types::primitives::VarInt::from(Self::id() as usize).write(&mut buf).await?;
+                        #(self.#names.write(&mut buf).await?;)*
+                        crate::protocol_util::send_packet_buf(stream, &connection, buf, len).await
+                    }
+                }
+            ));
+        }
+    }
+
+    TokenStream::from(
+        syn::Error::new_spanned(&input, "`Packet` can only be derived for structs with named fields")
+            .to_compile_error(),
+    )
+}
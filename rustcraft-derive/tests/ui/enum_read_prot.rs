@@ -0,0 +1,9 @@
+use rustcraft_derive::ReadProt;
+
+#[derive(ReadProt)]
+enum Weather {
+    Clear,
+    Rain(u8),
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rustcraft_derive::SizedProt;
+
+#[derive(SizedProt)]
+enum Weather {
+    Clear,
+    Rain,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rustcraft_derive::ProtEnum;
+
+#[derive(ProtEnum)]
+enum Weather {
+    Clear,
+    Rain(u8),
+}
+
+fn main() {}
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub struct Player {
     pub username: String,
     pub uuid: String,
+    pub brand: Option<String>,
+    pub send_queue_depth: usize,
     pub position: Position,
 }
 
@@ -18,6 +18,7 @@ fn players_list(props: &PlayersListProps) -> Html {
                   <div class="card-body">
                     <h5 class="card-title">{&player.username}</h5>
                     <p class="card-text">{&player.uuid}</p>
+                    <p class="card-text">{"Brand: "}{player.brand.as_deref().unwrap_or("unknown")}</p>
                   </div>
                   <ul class="list-group list-group-flush">
                     <li class="list-group-item">{"x-Position: "}{format!("{:.2}", &player.position.x)}</li>
@@ -26,6 +27,7 @@ fn players_list(props: &PlayersListProps) -> Html {
                     <li class="list-group-item">{"Yaw: "}{format!("{:.2}", &player.position.yaw)}</li>
                     <li class="list-group-item">{"Pitch: "}{format!("{:.2}", &player.position.pitch)}</li>
                     <li class="list-group-item">{"On ground: "}{&player.position.on_ground}</li>
+                    <li class="list-group-item">{"Send queue: "}{player.send_queue_depth}</li>
 
                   </ul>
                   <div class="card-body">
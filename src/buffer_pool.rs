@@ -0,0 +1,124 @@
+use std::env;
+
+// number of buffers kept around per connection if PACKET_BUFFER_POOL_SIZE isn't set
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/**
+ * A small pool of byte buffers that outbound packets are serialized into.
+ * Buffers are cleared and handed out again instead of allocating a fresh one for every packet.
+ * Each connection owns its own pool, which is guarded by the connection's lock.
+ */
+pub(crate) struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+    // only counted for tests that check buffers are reused
+    #[cfg(test)]
+    allocations: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max_buffers: usize) -> Self {
+        Self {
+            buffers: Vec::with_capacity(max_buffers),
+            max_buffers,
+            #[cfg(test)]
+            allocations: 0,
+        }
+    }
+
+    /**
+     * Creates a pool whose size is read from the PACKET_BUFFER_POOL_SIZE environment variable.
+     */
+    pub(crate) fn from_env() -> Self {
+        let max_buffers = env::var("PACKET_BUFFER_POOL_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_POOL_SIZE);
+        Self::new(max_buffers)
+    }
+
+    /**
+     * Returns an empty buffer with at least the given capacity, reusing a pooled one if possible.
+     */
+    pub(crate) fn take(&mut self, capacity: usize) -> Vec<u8> {
+        match self.buffers.pop() {
+            Some(mut buf) => {
+                if buf.capacity() < capacity {
+                    #[cfg(test)]
+                    {
+                        self.allocations += 1;
+                    }
+                    buf.reserve(capacity);
+                }
+                buf
+            }
+            None => {
+                #[cfg(test)]
+                {
+                    self.allocations += 1;
+                }
+                Vec::with_capacity(capacity)
+            }
+        }
+    }
+
+    /**
+     * Returns a buffer to the pool. It is dropped if the pool is already full.
+     */
+    pub(crate) fn give(&mut self, mut buf: Vec<u8>) {
+        if self.buffers.len() < self.max_buffers {
+            buf.clear();
+            self.buffers.push(buf);
+        }
+    }
+
+    /**
+     * The number of times the pool had to allocate or grow a buffer.
+     */
+    #[cfg(test)]
+    pub(crate) fn allocations(&self) -> usize {
+        self.allocations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, RwLock};
+
+    use crate::connection::ConnectionInfo;
+    use crate::packets::client::{ClientPackets, PlayKeepAlive};
+    use crate::protocol_types::traits::WriteProtPacket;
+
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused() {
+        let mut pool = BufferPool::new(2);
+        let mut buf = pool.take(16);
+        buf.extend_from_slice(&[1, 2, 3]);
+        pool.give(buf);
+        let buf = pool.take(16);
+        assert!(buf.is_empty());
+        assert_eq!(pool.allocations(), 1);
+    }
+
+    #[test]
+    fn full_pool_drops_buffers() {
+        let mut pool = BufferPool::new(1);
+        let (a, b) = (pool.take(8), pool.take(8));
+        pool.give(a);
+        pool.give(b);
+        assert_eq!(pool.buffers.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn packet_writes_reuse_buffers() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let mut out = vec![];
+        for id in 0..1000 {
+            let packet = ClientPackets::PlayKeepAlive(PlayKeepAlive::new(id));
+            packet.write(&mut out, connection.clone()).await.unwrap();
+        }
+        assert_eq!(connection.read().unwrap().buffer_pool.allocations(), 1);
+    }
+}
@@ -0,0 +1,381 @@
+// Reading and writing of the Anvil region file format (https://minecraft.wiki/w/Region_file_format).
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_nbt::io::Flavor;
+use async_nbt::{NbtCompound, NbtList, NbtTag};
+use tokio::fs;
+
+use crate::chunk::packed_array::PackedArray;
+use crate::chunk::palette::{PaletteKind, PaletteValue, PalettedContainer};
+use crate::chunk::section::ChunkSection;
+use crate::chunk::{ceil_log2, ChunkCol, ChunkColRef, COLUMN_HEIGHT, MIN_SECTION_Y, SECTION_EDGE};
+use crate::data::global_palette::{BlockStateName, GlobalPalette};
+use crate::protocol_types::compound::Position;
+
+const SECTOR_SIZE: usize = 4096;
+const REGION_CHUNKS: usize = 32;
+const COMPRESSION_ZLIB: u8 = 2;
+const DATA_VERSION: i32 = 3578; // 1.20.2
+
+// Collects the values of a section into a palette (in order of first appearance) and the palette indices.
+fn build_palette<T: Copy + Eq + std::hash::Hash>(values: impl Iterator<Item = T>) -> (Vec<T>, Vec<u64>) {
+    let mut palette = vec![];
+    let mut ids: HashMap<T, u64> = HashMap::new();
+    let mut indices = vec![];
+    for value in values {
+        let id = *ids.entry(value).or_insert_with(|| {
+            palette.push(value);
+            palette.len() as u64 - 1
+        });
+        indices.push(id);
+    }
+    (palette, indices)
+}
+
+fn pack(indices: Vec<u64>, bits: usize) -> NbtTag {
+    let packed = PackedArray::from_iter(indices, bits);
+    NbtTag::LongArray(packed.as_u64_slice().iter().map(|l| *l as i64).collect())
+}
+
+// the packed palette indices of a section, sections with a single entry in the palette have none
+fn longs(tag: Option<&NbtTag>) -> Vec<u64> {
+    match tag {
+        Some(NbtTag::LongArray(longs)) => longs.iter().map(|l| *l as u64).collect(),
+        _ => vec![],
+    }
+}
+
+fn block_positions() -> impl Iterator<Item = Position> {
+    let edge = SECTION_EDGE as i32;
+    (0..edge).flat_map(move |y| (0..edge).flat_map(move |z| (0..edge).map(move |x| Position::new(x, y, z))))
+}
+
+fn biome_positions() -> impl Iterator<Item = Position> {
+    let edge = SECTION_EDGE as i32 / 4;
+    (0..edge).flat_map(move |y| (0..edge).flat_map(move |z| (0..edge).map(move |x| Position::new(x, y, z))))
+}
+
+fn section_to_nbt(y: i32, section: &ChunkSection, palette: &GlobalPalette) -> Result<NbtCompound, String> {
+    let (block_palette, block_indices) = build_palette(block_positions().map(|pos| section.block(pos)));
+    let mut block_states = NbtCompound::new();
+    let mut palette_list = NbtList::new();
+    for state in &block_palette {
+        let name = palette
+            .block_name(*state)
+            .ok_or(format!("Unknown block state {state}"))?;
+        let mut entry = NbtCompound::new();
+        entry.insert("Name", name.name.clone());
+        if !name.properties.is_empty() {
+            let mut properties = NbtCompound::new();
+            for (key, value) in &name.properties {
+                properties.insert(key.clone(), value.clone());
+            }
+            entry.insert("Properties", properties);
+        }
+        palette_list.push(entry);
+    }
+    block_states.insert("palette", palette_list);
+    if block_palette.len() > 1 {
+        block_states.insert("data", pack(block_indices, ceil_log2(block_palette.len()).max(4)));
+    }
+
+    let (biome_palette, biome_indices) = build_palette(biome_positions().map(|pos| section.biome(pos)));
+    let mut biomes = NbtCompound::new();
+    let mut palette_list = NbtList::new();
+    for biome in &biome_palette {
+        let name = palette.biome_name(*biome).ok_or(format!("Unknown biome {biome}"))?;
+        palette_list.push(name.clone());
+    }
+    biomes.insert("palette", palette_list);
+    if biome_palette.len() > 1 {
+        biomes.insert("data", pack(biome_indices, ceil_log2(biome_palette.len())));
+    }
+
+    let mut nbt = NbtCompound::new();
+    nbt.insert("Y", y as i8);
+    nbt.insert("block_states", block_states);
+    nbt.insert("biomes", biomes);
+    Ok(nbt)
+}
+
+fn section_from_nbt(nbt: &NbtCompound, palette: &GlobalPalette) -> Result<ChunkSection, String> {
+    let block_states: &NbtCompound = nbt.get("block_states").or(Err("Section without block states"))?;
+    let palette_list: &NbtList = block_states.get("palette").or(Err("Block states without palette"))?;
+    let mut block_palette: Vec<PaletteValue> = vec![];
+    for entry in palette_list.iter() {
+        let NbtTag::Compound(entry) = entry else {
+            return Err("Block palette entry is not a compound".into());
+        };
+        let name: &String = entry.get("Name").or(Err("Block palette entry without name"))?;
+        let mut name = BlockStateName {
+            name: name.clone(),
+            properties: Default::default(),
+        };
+        if let Ok(properties) = entry.get::<_, &NbtCompound>("Properties") {
+            for (key, value) in properties.inner() {
+                if let NbtTag::String(value) = value {
+                    name.properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        block_palette.push(PaletteValue::Block(palette.block_id(&name).ok_or(format!("Unknown block {name:?}"))?));
+    }
+    let bits = ceil_log2(block_palette.len()).max(4);
+    let data = longs(block_states.inner().get("data"));
+    let blocks = PalettedContainer::from_palette(PaletteKind::Blocks, block_palette, bits, data)
+        .or_else(|err| Err(format!("Invalid block states: {err}")))?;
+
+    let biomes: &NbtCompound = nbt.get("biomes").or(Err("Section without biomes"))?;
+    let palette_list: &NbtList = biomes.get("palette").or(Err("Biomes without palette"))?;
+    let mut biome_palette: Vec<PaletteValue> = vec![];
+    for entry in palette_list.iter() {
+        let NbtTag::String(name) = entry else {
+            return Err("Biome palette entry is not a string".into());
+        };
+        biome_palette.push(PaletteValue::Biome(palette.biome_id(name).ok_or(format!("Unknown biome {name}"))?));
+    }
+    let bits = ceil_log2(biome_palette.len());
+    let data = longs(biomes.inner().get("data"));
+    let biomes = PalettedContainer::from_palette(PaletteKind::Biomes, biome_palette, bits, data)
+        .or_else(|err| Err(format!("Invalid biomes: {err}")))?;
+    Ok(ChunkSection::from_containers(blocks, biomes))
+}
+
+async fn column_to_bytes(x: i32, z: i32, column: &ChunkColRef<'_>, palette: &GlobalPalette) -> Result<Vec<u8>, String> {
+    let mut sections = NbtList::new();
+    for (i, section) in column.iter().enumerate() {
+        sections.push(section_to_nbt(i as i32 + MIN_SECTION_Y, section, palette)?);
+    }
+    let mut nbt = NbtCompound::new();
+    nbt.insert("DataVersion", DATA_VERSION);
+    nbt.insert("xPos", x);
+    nbt.insert("zPos", z);
+    nbt.insert("yPos", MIN_SECTION_Y);
+    nbt.insert("Status", "minecraft:full");
+    nbt.insert("LastUpdate", 0i64);
+    nbt.insert("sections", sections);
+
+    let mut compressed = vec![];
+    async_nbt::io::write_nbt(&mut compressed, Some(""), &nbt, Flavor::ZlibCompressed)
+        .await
+        .or_else(|err| Err(format!("NBT error: {:?}", err)))?;
+    Ok(compressed)
+}
+
+async fn column_from_bytes(compressed: &[u8], palette: &GlobalPalette) -> Result<(i32, i32, ChunkCol), String> {
+    let (nbt, _) = async_nbt::io::read_nbt(&mut Cursor::new(compressed), Flavor::ZlibCompressed, false)
+        .await
+        .or_else(|err| Err(format!("NBT error: {:?}", err)))?;
+    let x: i32 = nbt.get("xPos").or(Err("Chunk without xPos"))?;
+    let z: i32 = nbt.get("zPos").or(Err("Chunk without zPos"))?;
+    let sections: &NbtList = nbt.get("sections").or(Err("Chunk without sections"))?;
+    let mut column: Vec<(i8, ChunkSection)> = vec![];
+    for section in sections.iter() {
+        let NbtTag::Compound(section) = section else {
+            return Err("Section is not a compound".into());
+        };
+        let y: i8 = section.get("Y").or(Err("Section without Y"))?;
+        column.push((y, section_from_nbt(section, palette)?));
+    }
+    column.sort_by_key(|(y, _)| *y);
+    Ok((x, z, column.into_iter().map(|(_, section)| section).collect()))
+}
+
+// vanilla keeps the level.dat holding the world settings next to the region directory
+fn level_dat(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(path).join("level.dat")
+}
+
+/**
+ * Writes the seed that the terrain is generated from into the level.dat of the world whose region files are
+ * in the directory at `path`.
+ */
+pub(crate) async fn write_seed(path: &Path, seed: u64) -> Result<(), String> {
+    let mut settings = NbtCompound::new();
+    settings.insert("seed", seed as i64);
+    let mut data = NbtCompound::new();
+    data.insert("DataVersion", DATA_VERSION);
+    data.insert("WorldGenSettings", settings);
+    let mut nbt = NbtCompound::new();
+    nbt.insert("Data", data);
+
+    let mut compressed = vec![];
+    async_nbt::io::write_nbt(&mut compressed, Some(""), &nbt, Flavor::GzCompressed)
+        .await
+        .or_else(|err| Err(format!("NBT error: {:?}", err)))?;
+    fs::write(level_dat(path), compressed).await.or_else(|err| Err(format!("{err}")))
+}
+
+/**
+ * Reads the seed from the level.dat of the world whose region files are in the directory at `path`,
+ * None if there is no level.dat.
+ */
+pub(crate) async fn read_seed(path: &Path) -> Result<Option<u64>, String> {
+    let file_path = level_dat(path);
+    if !fs::try_exists(&file_path).await.unwrap_or(false) {
+        return Ok(None);
+    }
+    let compressed = fs::read(file_path).await.or_else(|err| Err(format!("{err}")))?;
+    let (nbt, _) = async_nbt::io::read_nbt(&mut Cursor::new(compressed), Flavor::GzCompressed, false)
+        .await
+        .or_else(|err| Err(format!("NBT error: {:?}", err)))?;
+    let seed: i64 = nbt
+        .get::<_, &NbtCompound>("Data")
+        .and_then(|data| data.get::<_, &NbtCompound>("WorldGenSettings"))
+        .and_then(|settings| settings.get("seed"))
+        .or(Err("level.dat without a seed"))?;
+    Ok(Some(seed as u64))
+}
+
+/**
+ * Writes the given columns into region files (`r.<x>.<z>.mca`) inside the directory at `path`.
+ * Columns already stored in an existing region file but not given here are kept.
+ */
+pub(crate) async fn write_regions(
+    path: &Path,
+    columns: &HashMap<(i32, i32), ChunkColRef<'_>>,
+    palette: &GlobalPalette,
+) -> Result<(), String> {
+    fs::create_dir_all(path).await.or_else(|err| Err(format!("{err}")))?;
+    let mut regions: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+    for (x, z) in columns.keys() {
+        regions.entry((x >> 5, z >> 5)).or_default().push((*x, *z));
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+
+    for ((region_x, region_z), mut chunks) in regions {
+        let file_path = path.join(format!("r.{region_x}.{region_z}.mca"));
+        let stored = if fs::try_exists(&file_path).await.unwrap_or(false) {
+            read_region_file(&file_path, palette).await?
+        } else {
+            HashMap::new()
+        };
+        chunks.extend(stored.keys().filter(|pos| !columns.contains_key(*pos)).copied());
+        chunks.sort();
+        let mut locations = vec![0u8; SECTOR_SIZE];
+        let mut timestamps = vec![0u8; SECTOR_SIZE];
+        let mut body: Vec<u8> = vec![];
+        for (x, z) in chunks {
+            let compressed = match columns.get(&(x, z)) {
+                Some(column) => column_to_bytes(x, z, column, palette).await?,
+                None => column_to_bytes(x, z, &stored[&(x, z)].iter().collect(), palette).await?,
+            };
+            let offset = 2 + body.len() / SECTOR_SIZE;
+            body.extend(((compressed.len() + 1) as u32).to_be_bytes());
+            body.push(COMPRESSION_ZLIB);
+            body.extend(compressed);
+            body.resize(body.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+            let sectors = 2 + body.len() / SECTOR_SIZE - offset;
+            if sectors > u8::MAX as usize {
+                return Err(format!("Chunk {x}, {z} is too large for a region file"));
+            }
+
+            let index = 4 * region_index(x, z);
+            locations[index..index + 3].copy_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            locations[index + 3] = sectors as u8;
+            timestamps[index..index + 4].copy_from_slice(&timestamp.to_be_bytes());
+        }
+        let mut file = locations;
+        file.extend(timestamps);
+        file.extend(body);
+        fs::write(file_path, file)
+            .await
+            .or_else(|err| Err(format!("{err}")))?;
+    }
+    Ok(())
+}
+
+/**
+ * Reads all columns from the region files inside the directory at `path`.
+ */
+pub(crate) async fn read_regions(
+    path: &Path,
+    palette: &GlobalPalette,
+) -> Result<HashMap<(i32, i32), ChunkCol>, String> {
+    let mut columns = HashMap::new();
+    let mut entries = fs::read_dir(path).await.or_else(|err| Err(format!("{err}")))?;
+    while let Some(entry) = entries.next_entry().await.or_else(|err| Err(format!("{err}")))? {
+        if entry.path().extension().and_then(|ext| ext.to_str()) != Some("mca") {
+            continue;
+        }
+        columns.extend(read_region_file(&entry.path(), palette).await?);
+    }
+    Ok(columns)
+}
+
+/**
+ * Reads the column at the given column coordinates from its region file inside the directory at `path`,
+ * None if it was never saved there.
+ */
+pub(crate) async fn read_column(path: &Path, x: i32, z: i32, palette: &GlobalPalette) -> Result<Option<ChunkCol>, String> {
+    let file_path = path.join(format!("r.{}.{}.mca", x >> 5, z >> 5));
+    if !fs::try_exists(&file_path).await.unwrap_or(false) {
+        return Ok(None);
+    }
+    let file = read_region_bytes(&file_path).await?;
+    let Some(compressed) = compressed_chunk(&file, region_index(x, z))? else {
+        return Ok(None);
+    };
+    let (_, _, column) = column_from_bytes(compressed, palette).await?;
+    if column.len() != COLUMN_HEIGHT {
+        return Err(format!("Chunk {x}, {z} has {} sections", column.len()));
+    }
+    Ok(Some(column))
+}
+
+/**
+ * Reads all columns from the single region file at `path`.
+ */
+async fn read_region_file(path: &Path, palette: &GlobalPalette) -> Result<HashMap<(i32, i32), ChunkCol>, String> {
+    let mut columns = HashMap::new();
+    let file = read_region_bytes(path).await?;
+    for index in 0..REGION_CHUNKS * REGION_CHUNKS {
+        let Some(compressed) = compressed_chunk(&file, index)? else {
+            continue;
+        };
+        let (x, z, column) = column_from_bytes(compressed, palette).await?;
+        if column.len() != COLUMN_HEIGHT {
+            return Err(format!("Chunk {x}, {z} has {} sections", column.len()));
+        }
+        columns.insert((x, z), column);
+    }
+    Ok(columns)
+}
+
+async fn read_region_bytes(path: &Path) -> Result<Vec<u8>, String> {
+    let file = fs::read(path).await.or_else(|err| Err(format!("{err}")))?;
+    if file.len() < 2 * SECTOR_SIZE {
+        return Err(format!("Region file {:?} is truncated", path));
+    }
+    Ok(file)
+}
+
+// index of a column in the location and timestamp tables of its region file
+fn region_index(x: i32, z: i32) -> usize {
+    (x as usize & (REGION_CHUNKS - 1)) + (z as usize & (REGION_CHUNKS - 1)) * REGION_CHUNKS
+}
+
+// the compressed NBT of the chunk at `index` in a region file, None if there is no chunk at that index
+fn compressed_chunk(file: &[u8], index: usize) -> Result<Option<&[u8]>, String> {
+    let location = &file[4 * index..4 * index + 4];
+    let offset = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize * SECTOR_SIZE;
+    if offset == 0 {
+        return Ok(None);
+    }
+    let header = file.get(offset..offset + 5).ok_or("Chunk offset out of bounds")?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if header[4] != COMPRESSION_ZLIB {
+        return Err(format!("Unsupported chunk compression {}", header[4]));
+    }
+    let compressed = file
+        .get(offset + 5..offset + 4 + length)
+        .ok_or("Chunk length out of bounds")?;
+    Ok(Some(compressed))
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::packets::client;
+use crate::packets::client::ClientPackets;
+use crate::protocol_types::primitives::VarLong;
+
+// diameter of the border in vanilla, just inside the farthest coordinates positions can hold
+pub(crate) const DEFAULT_DIAMETER: f64 = 59999968.0;
+const DEFAULT_WARNING_TIME: i32 = 15;
+const DEFAULT_WARNING_BLOCKS: i32 = 5;
+
+/**
+ * The border of the world as last sent to the clients. Every change returns the packet that tells the clients
+ * about it, moving the border with `lerp_size` has the clients animate it over the given duration.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WorldBorder {
+    pub(crate) center: (f64, f64),
+    // the size the border has or is moving towards
+    pub(crate) diameter: f64,
+    // seconds before the border reaches a player at which their screen turns red
+    pub(crate) warning_time: i32,
+    // distance from the border in blocks at which a player's screen turns red
+    pub(crate) warning_blocks: i32,
+}
+
+impl WorldBorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            center: (0.0, 0.0),
+            diameter: DEFAULT_DIAMETER,
+            warning_time: DEFAULT_WARNING_TIME,
+            warning_blocks: DEFAULT_WARNING_BLOCKS,
+        }
+    }
+
+    /**
+     * The packets telling a client that just joined where the border is.
+     */
+    pub(crate) fn packets(&self) -> Vec<ClientPackets> {
+        vec![
+            ClientPackets::SetBorderCenter(client::SetBorderCenter::new(self.center.0, self.center.1)),
+            ClientPackets::SetBorderSize(client::SetBorderSize::new(self.diameter)),
+            ClientPackets::SetBorderWarningDelay(client::SetBorderWarningDelay::new(self.warning_time.into())),
+            ClientPackets::SetBorderWarningDistance(client::SetBorderWarningDistance::new(self.warning_blocks.into())),
+        ]
+    }
+
+    pub(crate) fn set_center(&mut self, x: f64, z: f64) -> ClientPackets {
+        self.center = (x, z);
+        ClientPackets::SetBorderCenter(client::SetBorderCenter::new(x, z))
+    }
+
+    pub(crate) fn set_size(&mut self, diameter: f64) -> ClientPackets {
+        self.diameter = diameter;
+        ClientPackets::SetBorderSize(client::SetBorderSize::new(diameter))
+    }
+
+    /**
+     * Grows or shrinks the border to `diameter`, moving it at a constant speed so it takes `duration`.
+     */
+    pub(crate) fn lerp_size(&mut self, diameter: f64, duration: Duration) -> ClientPackets {
+        let old = self.diameter;
+        self.diameter = diameter;
+        let millis = VarLong::from(duration.as_millis() as i64);
+        ClientPackets::SetBorderLerpSize(client::SetBorderLerpSize::new(old, diameter, millis))
+    }
+
+    pub(crate) fn set_warning_time(&mut self, seconds: i32) -> ClientPackets {
+        self.warning_time = seconds;
+        ClientPackets::SetBorderWarningDelay(client::SetBorderWarningDelay::new(seconds.into()))
+    }
+
+    pub(crate) fn set_warning_distance(&mut self, blocks: i32) -> ClientPackets {
+        self.warning_blocks = blocks;
+        ClientPackets::SetBorderWarningDistance(client::SetBorderWarningDistance::new(blocks.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::connection::ConnectionInfo;
+    use crate::protocol_types::traits::WriteProtPacket;
+
+    #[tokio::test]
+    async fn lerp_size_packet() {
+        let mut border = WorldBorder::new();
+        border.set_size(100.0);
+        let packet = border.lerp_size(20.0, Duration::from_secs(30));
+        assert_eq!(border.diameter, 20.0);
+
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        let mut expected = vec![20, 0x4a];
+        expected.extend(100f64.to_be_bytes());
+        expected.extend(20f64.to_be_bytes());
+        expected.extend([0xb0, 0xea, 0x01]); // 30000 ms
+        assert_eq!(buf, expected);
+    }
+}
@@ -1,5 +1,12 @@
+use async_nbt::{NbtCompound, NbtTag};
+
+use crate::chunk::packed_array::PackedArray;
 use crate::chunk::section::ChunkSection;
+use crate::protocol_types::compound::Position;
 
+pub(crate) mod anvil;
+pub(crate) mod border;
+mod noise;
 mod palette;
 pub(crate) mod section;
 
@@ -9,8 +16,8 @@ pub(crate) mod world;
 type ChunkCol = Vec<ChunkSection>;
 type ChunkColRef<'a> = Vec<&'a ChunkSection>;
 
-type BlockState = u32;
-type Biome = u32;
+pub(crate) type BlockState = u32;
+pub(crate) type Biome = u32;
 
 const GLOBAL_PALETTE_BITS_BLOCKS: usize = 15;
 const GLOBAL_PALETTE_BITS_BIOMES: usize = 6;
@@ -20,6 +27,41 @@ const MAX_PALETTE_BITS_BLOCKS: usize = 8;
 const MAX_PALETTE_BITS_BIOMES: usize = 3;
 const SECTION_EDGE: usize = 16;
 const SECTION_BLOCKS: usize = SECTION_EDGE * SECTION_EDGE * SECTION_EDGE;
-const AIR: u32 = 0;
+pub(crate) const AIR: BlockState = 0;
+pub(crate) const STONE: BlockState = 1;
+const GRASS_BLOCK: u32 = 9;
 
 pub(crate) const COLUMN_HEIGHT: usize = 24; // 24 chunk sections
+pub(crate) const MIN_SECTION_Y: i32 = -4;
+// bits per heightmap entry, enough for every height of a column including an empty one
+const HEIGHTMAP_BITS: usize = 9;
+
+// the fewest bits that can tell `n` values apart
+fn ceil_log2(n: usize) -> usize {
+    (usize::BITS - (n.max(1) - 1).leading_zeros()) as usize
+}
+
+/**
+ * Computes the MOTION_BLOCKING heightmap of a column, which clients use e.g. to decide where rain falls.
+ * Entries are ordered by z, then x, and are one above the highest non-air block counted from the bottom of the column.
+ */
+pub(crate) fn heightmaps(column: &[ChunkSection]) -> NbtCompound {
+    let edge = SECTION_EDGE as i32;
+    let heights = (0..edge).flat_map(|z| (0..edge).map(move |x| (x, z))).map(|(x, z)| {
+        for (section_y, section) in column.iter().enumerate().rev() {
+            if section.air_count() as usize == SECTION_BLOCKS {
+                continue;
+            }
+            for y in (0..edge).rev() {
+                if section.block(Position::new(x, y, z)) != AIR {
+                    return (section_y * SECTION_EDGE) as u64 + y as u64 + 1;
+                }
+            }
+        }
+        0
+    });
+    let packed = PackedArray::from_iter(heights, HEIGHTMAP_BITS);
+    let mut nbt = NbtCompound::new();
+    nbt.insert("MOTION_BLOCKING", NbtTag::LongArray(packed.as_u64_slice().iter().map(|l| *l as i64).collect()));
+    nbt
+}
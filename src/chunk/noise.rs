@@ -0,0 +1,60 @@
+// Deterministic 2D value noise used for terrain generation.
+
+// splitmix64, mapped to [0, 1)
+fn lattice_value(seed: u64, x: i32, z: i32) -> f64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/**
+ * Samples value noise at the given coordinates, returning a value in [0, 1).
+ * The lattice points are `scale` blocks apart and interpolated smoothly in between.
+ */
+pub(crate) fn value_noise(seed: u64, x: i32, z: i32, scale: i32) -> f64 {
+    let cell_x = x.div_euclid(scale);
+    let cell_z = z.div_euclid(scale);
+    let tx = smoothstep(x.rem_euclid(scale) as f64 / scale as f64);
+    let tz = smoothstep(z.rem_euclid(scale) as f64 / scale as f64);
+    let top = lerp(
+        lattice_value(seed, cell_x, cell_z),
+        lattice_value(seed, cell_x + 1, cell_z),
+        tx,
+    );
+    let bottom = lerp(
+        lattice_value(seed, cell_x, cell_z + 1),
+        lattice_value(seed, cell_x + 1, cell_z + 1),
+        tx,
+    );
+    lerp(top, bottom, tz)
+}
+
+/**
+ * Sums several octaves of value noise, each with half the scale and amplitude of the previous one.
+ * The result is normalized to [0, 1).
+ */
+pub(crate) fn fractal_noise(seed: u64, x: i32, z: i32, scale: i32, octaves: u32) -> f64 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut max = 0.0;
+    let mut scale = scale;
+    for octave in 0..octaves {
+        total += value_noise(seed.wrapping_add(octave as u64), x, z, scale.max(1)) * amplitude;
+        max += amplitude;
+        amplitude /= 2.0;
+        scale /= 2;
+    }
+    total / max
+}
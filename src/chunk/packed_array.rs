@@ -1,5 +1,6 @@
 // Taken from https://github.com/feather-rs/feather/blob/main/feather/base/src/chunk/packed_array.rs (Apache 2.0 license)
 
+use crate::err::ProtoError;
 use crate::protocol_types::primitives::VarInt;
 use crate::protocol_types::traits::WriteProt;
 use async_trait::async_trait;
@@ -66,6 +67,10 @@ impl PackedArray {
         if index >= self.len() {
             return None;
         }
+        // zero bits per value can only hold 0, without any data to store it in
+        if self.bits_per_value == 0 {
+            return Some(0);
+        }
 
         let (u64_index, bit_index) = self.indexes(index);
 
@@ -88,6 +93,9 @@ impl PackedArray {
 
         let mask = self.mask();
         assert!(value <= mask);
+        if self.bits_per_value == 0 {
+            return;
+        }
 
         let (u64_index, bit_index) = self.indexes(index);
 
@@ -102,6 +110,9 @@ impl PackedArray {
     /// Panics if `value > self.max_value()`.
     pub fn fill(&mut self, value: u64) {
         assert!(value <= self.max_value());
+        if self.bits_per_value == 0 {
+            return;
+        }
         let mut x = 0;
         for i in 0..self.values_per_u64() {
             x |= value << (i * self.bits_per_value);
@@ -112,16 +123,19 @@ impl PackedArray {
 
     /// Returns an iterator over values in this array.
     pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
-        let values_per_u64 = self.values_per_u64();
+        // with zero bits per value there are no u64s, every value is 0
+        let values_per_u64 = if self.bits_per_value == 0 { 1 } else { self.values_per_u64() };
         let bits_per_value = self.bits_per_value() as u64;
         let mask = self.mask();
         let length = self.len();
+        let zeros = if self.bits_per_value == 0 { length } else { 0 };
 
         self.bits
             .iter()
             .flat_map(move |&u64| {
                 (0..values_per_u64).map(move |i| (u64 >> (i as u64 * bits_per_value)) & mask)
             })
+            .chain(std::iter::repeat(0).take(zeros))
             .take(length)
     }
 
@@ -140,6 +154,9 @@ impl PackedArray {
         let mut current_offset = 0;
         let mut length = 0;
 
+        if bits_per_value == 0 {
+            return Self { length: iter.count(), bits_per_value, bits };
+        }
         for value in iter {
             debug_assert!(value < 1 << bits_per_value);
             current_u64 |= value << current_offset;
@@ -207,6 +224,9 @@ impl PackedArray {
     }
 
     fn needed_u64s(&self) -> usize {
+        if self.bits_per_value == 0 {
+            return 0;
+        }
         (self.length + self.values_per_u64() - 1) / self.values_per_u64()
     }
 
@@ -224,7 +244,7 @@ impl PackedArray {
 
 #[async_trait]
 impl WriteProt for PackedArray {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         VarInt::from(self.needed_u64s()).write(stream).await?;
         for i in 0..self.bits.len() {
             self.bits[i].write(stream).await?;
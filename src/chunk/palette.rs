@@ -3,17 +3,21 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 use async_trait::async_trait;
+#[cfg(test)]
+use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 
 use crate::chunk::{
-    Biome, BlockState, GLOBAL_PALETTE_BITS_BIOMES, GLOBAL_PALETTE_BITS_BLOCKS,
+    ceil_log2, Biome, BlockState, GLOBAL_PALETTE_BITS_BIOMES, GLOBAL_PALETTE_BITS_BLOCKS,
     MAX_PALETTE_BITS_BIOMES, MAX_PALETTE_BITS_BLOCKS, MIN_PALETTE_BITS_BIOMES,
     MIN_PALETTE_BITS_BLOCKS, SECTION_BLOCKS, SECTION_EDGE,
 };
 use crate::chunk::packed_array::PackedArray;
-use crate::err::ProtError;
+use crate::err::{ProtError, ProtoError};
 use crate::protocol_types::compound::Position;
 use crate::protocol_types::primitives::VarInt;
+#[cfg(test)]
+use crate::protocol_types::traits::ReadProt;
 use crate::protocol_types::traits::WriteProt;
 
 #[derive(Debug, Copy, Clone)]
@@ -62,7 +66,8 @@ impl PaletteKind {
     fn container_length(&self) -> usize {
         match &self {
             PaletteKind::Blocks => SECTION_BLOCKS,
-            PaletteKind::Biomes => SECTION_BLOCKS / 4,
+            // one biome per 4x4x4 blocks
+            PaletteKind::Biomes => (SECTION_EDGE / 4).pow(3),
         }
     }
 
@@ -97,7 +102,8 @@ impl PaletteKind {
     fn section_blocks(&self) -> usize {
         match &self {
             PaletteKind::Blocks => SECTION_BLOCKS,
-            PaletteKind::Biomes => SECTION_BLOCKS / 4,
+            // one biome per 4x4x4 blocks
+            PaletteKind::Biomes => (SECTION_EDGE / 4).pow(3),
         }
     }
 }
@@ -127,6 +133,42 @@ impl PalettedContainer {
         }
     }
 
+    /**
+     * A container holding nothing but `value`. Its palette has just that value and the data takes up no
+     * space, as every index is 0.
+     */
+    pub(crate) fn new_single(kind: PaletteKind, value: PaletteValue) -> Self {
+        Self {
+            palette: Some(Palette::from_states(kind, vec![value])),
+            data: PackedArray::new(kind.container_length(), 0),
+            kind,
+        }
+    }
+
+    /**
+     * A container from a palette and the palette indexes packed with `bits` bits each, like the ones stored
+     * in region files. Palettes too large to send to clients are replaced by the global ids.
+     */
+    pub(crate) fn from_palette(kind: PaletteKind, states: Vec<PaletteValue>, bits: usize, longs: Vec<u64>) -> Result<Self, ProtoError> {
+        if states.len() <= 1 {
+            return Ok(Self::new_single(kind, states.first().copied().unwrap_or(kind.neutral())));
+        }
+        let mut data = PackedArray::new(kind.container_length(), bits);
+        if longs.len() != data.as_u64_slice().len() {
+            return Err(ProtoError::InvalidLength("long array", longs.len() as i32));
+        }
+        *data.as_u64_mut_vec() = longs;
+        if data.iter().any(|index| index as usize >= states.len()) {
+            return Err(ProtoError::InvalidLength("palette", states.len() as i32));
+        }
+        if states.len() > 1 << kind.max_palette_bits() {
+            let ids = data.iter().map(|index| states[index as usize].into());
+            return Ok(Self { palette: None, data: PackedArray::from_iter(ids, kind.global_palette_bits()), kind });
+        }
+        let data = data.resized(ceil_log2(states.len()).max(kind.min_palette_bits()));
+        Ok(Self { palette: Some(Palette::from_states(kind, states)), data, kind })
+    }
+
     pub(crate) fn block_index(&self, position: Position) -> Option<usize> {
         if position.x >= self.kind.section_edge() as i32
             || position.y >= self.kind.section_edge() as i32
@@ -183,10 +225,26 @@ impl PalettedContainer {
         Ok(())
     }
 
+    /**
+     * The value of every entry if the container is uniform, however its palette is laid out.
+     */
+    pub(crate) fn single_value(&self) -> Option<PaletteValue> {
+        let mut values = self.data.iter();
+        let first = values.next()?;
+        if !values.all(|value| value == first) {
+            return None;
+        }
+        Some(match &self.palette {
+            Some(palette) => palette.get(first as u32),
+            None => self.kind.int_val(first),
+        })
+    }
+
     fn resize_palette(&mut self) -> bool {
         let palette = self.palette.as_ref().unwrap();
         if palette.len() - 1 > self.data.max_value() as usize {
-            let new_size = self.data.bits_per_value() + 1;
+            // single value containers grow straight to the smallest indirect palette
+            let new_size = (self.data.bits_per_value() + 1).max(self.kind.min_palette_bits());
             if new_size > self.kind.max_palette_bits() {
                 self.data = self.data.resized(self.kind.global_palette_bits());
                 for i in 0..self.kind.section_blocks() {
@@ -204,20 +262,66 @@ impl PalettedContainer {
     }
 }
 
+// only tests read containers back, clients never send chunk data
+#[cfg(test)]
+impl PalettedContainer {
+    /**
+     * Reads a container of the given kind: the bits per value, the palette, and the long array of palette
+     * indexes. With 0 bits the whole container holds the single value that replaces the palette, beyond
+     * the maximum palette size there's no palette at all and the array holds global ids directly.
+     */
+    pub(crate) async fn read(stream: &mut (impl AsyncRead + Unpin + Send), kind: PaletteKind) -> Result<Self, ProtoError> {
+        let bits = u8::read(stream).await? as usize;
+        if bits == 0 {
+            let value = kind.int_val(VarInt::read(stream).await?.value as u64);
+            // single value containers don't need any data, whatever is sent is skipped
+            for _ in 0..VarInt::read(stream).await?.value.max(0) {
+                u64::read(stream).await?;
+            }
+            return Ok(Self::new_single(kind, value));
+        }
+        let states = if bits <= kind.max_palette_bits() {
+            let len = VarInt::read(stream).await?.value;
+            if len <= 0 || len as usize > 1 << kind.max_palette_bits() {
+                return Err(ProtoError::InvalidLength("palette", len));
+            }
+            let mut states = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                states.push(kind.int_val(VarInt::read(stream).await?.value as u64));
+            }
+            Some(states)
+        } else {
+            None
+        };
+        let bits = if states.is_some() { bits.max(kind.min_palette_bits()) } else { bits };
+
+        let mut data = PackedArray::new(kind.container_length(), bits);
+        let len = VarInt::read(stream).await?.value;
+        if len as usize != data.as_u64_slice().len() {
+            return Err(ProtoError::InvalidLength("long array", len));
+        }
+        for long in data.as_u64_mut_vec().iter_mut() {
+            *long = u64::read(stream).await?;
+        }
+        match states {
+            Some(states) => Self::from_palette(kind, states, bits, data.as_u64_slice().to_vec()),
+            None => Ok(Self { palette: None, data, kind }),
+        }
+    }
+}
+
 #[async_trait]
 impl WriteProt for PalettedContainer {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        if self.palette.as_ref().is_some_and(|pal| pal.len() == 1) {
-            // TODO support non-air single palettes
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        if let Some(value) = self.single_value() {
             0u8.write(stream).await?; // bits per value is 0 in this case
-            VarInt::from(*self.palette.as_ref().unwrap().id_to_state.get(&0).unwrap())
-                .write(stream)
-                .await?;
-        } else {
-            (self.data.bits_per_value() as u8).write(stream).await?;
-            if let Some(palette) = &self.palette {
-                palette.write(stream).await?;
-            }
+            VarInt::from(value).write(stream).await?;
+            VarInt::from(0).write(stream).await?; // the long array is empty
+            return Ok(());
+        }
+        (self.data.bits_per_value() as u8).write(stream).await?;
+        if let Some(palette) = &self.palette {
+            palette.write(stream).await?;
         }
         self.data.write(stream).await?; // long array
         Ok(())
@@ -226,7 +330,8 @@ impl WriteProt for PalettedContainer {
 
 #[derive(Clone)]
 pub(crate) struct Palette {
-    pub(crate) id_to_state: HashMap<u32, PaletteValue>,
+    // ids are handed out sequentially, so the id of a state is its index
+    pub(crate) id_to_state: Vec<PaletteValue>,
     state_to_id: HashMap<PaletteValue, u32>,
     kind: PaletteKind,
 }
@@ -234,18 +339,31 @@ pub(crate) struct Palette {
 impl Palette {
     fn new(kind: PaletteKind) -> Self {
         Self {
-            id_to_state: HashMap::from([(0, kind.neutral())]),
+            id_to_state: vec![kind.neutral()],
             state_to_id: HashMap::from([(kind.neutral(), 0)]),
             kind,
         }
     }
 
+    // palette with the given states at their index
+    fn from_states(kind: PaletteKind, states: Vec<PaletteValue>) -> Self {
+        let mut state_to_id = HashMap::new();
+        for (id, state) in states.iter().enumerate().rev() {
+            state_to_id.insert(*state, id as u32);
+        }
+        Self {
+            id_to_state: states,
+            state_to_id,
+            kind,
+        }
+    }
+
     fn index_or_insert(&mut self, state: PaletteValue) -> u32 {
         if let Some(id) = self.state_to_id.get(&state) {
             *id
         } else {
             let id = self.id_to_state.len() as u32;
-            self.id_to_state.insert(id, state);
+            self.id_to_state.push(state);
             self.state_to_id.insert(state, id);
             id
         }
@@ -253,7 +371,7 @@ impl Palette {
 
     fn get(&self, id: u32) -> PaletteValue {
         self.id_to_state
-            .get(&id)
+            .get(id as usize)
             .copied()
             .unwrap_or_else(|| self.kind.neutral())
     }
@@ -265,12 +383,9 @@ impl Palette {
 
 #[async_trait]
 impl WriteProt for Palette {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         VarInt::from(self.len()).write(stream).await?;
-
-        let mut id_to_state: Vec<(&u32, &PaletteValue)> = self.id_to_state.iter().collect();
-        id_to_state.sort_by(|a, b| a.0.cmp(b.0));
-        for (_, state) in id_to_state {
+        for state in &self.id_to_state {
             VarInt::from(*state).write(stream).await?;
         }
 
@@ -306,6 +421,19 @@ mod test {
         assert_eq!(id, 3);
     }
 
+    #[tokio::test]
+    async fn palette_written_in_id_order() {
+        let mut palette = Palette::new(PaletteKind::Blocks);
+        for state in [9, 4, 7, 1] {
+            palette.index_or_insert(PaletteValue::Block(state));
+        }
+        assert_eq!(palette.get(2), PaletteValue::Block(4));
+        assert_eq!(palette.get(7), palette.kind.neutral());
+        let mut buf = vec![];
+        palette.write(&mut buf).await.unwrap();
+        assert_eq!(buf, [5, 0, 9, 4, 7, 1]);
+    }
+
     #[test]
     fn set_block_get_block() {
         let mut container = PalettedContainer::new_blocks();
@@ -359,6 +487,87 @@ mod test {
         }
     }
 
+    async fn round_trip(container: &PalettedContainer) {
+        let mut buf = vec![];
+        container.write(&mut buf).await.unwrap();
+        let mut read_from = buf.as_slice();
+        let read = PalettedContainer::read(&mut read_from, PaletteKind::Blocks).await.unwrap();
+        assert!(read_from.is_empty());
+        for i in 0..16 {
+            for j in 0..16 {
+                for k in 0..16 {
+                    let position = Position::new(i, j, k);
+                    assert_eq!(read.get_at(position), container.get_at(position));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_written_containers() {
+        // single value
+        let mut container = PalettedContainer::new_blocks();
+        round_trip(&container).await;
+
+        // indirect
+        container.set_at(Position::new(1, 2, 3), PaletteValue::Block(1)).unwrap();
+        container.set_at(Position::new(15, 15, 15), PaletteValue::Block(9)).unwrap();
+        round_trip(&container).await;
+
+        // direct, with more states than fit in a palette
+        for i in 0..300 {
+            container.set_at(Position::new(i % 16, i / 16 % 16, i / 256), PaletteValue::Block(i as BlockState + 1)).unwrap();
+        }
+        assert!(container.palette.is_none());
+        round_trip(&container).await;
+    }
+
+    #[tokio::test]
+    async fn uniform_containers_written_as_single_value() {
+        let mut blocks = PalettedContainer::new_blocks();
+        for i in 0..16 {
+            for j in 0..16 {
+                for k in 0..16 {
+                    blocks.set_at(Position::new(i, j, k), PaletteValue::Block(5)).unwrap();
+                }
+            }
+        }
+        // air is still in the palette, but no longer in the container
+        assert_eq!(blocks.palette.as_ref().unwrap().len(), 2);
+        let mut buf = vec![];
+        blocks.write(&mut buf).await.unwrap();
+        assert_eq!(buf, [0, 5, 0]);
+
+        let mut biomes = PalettedContainer::new_biomes();
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    biomes.set_at(Position::new(i, j, k), PaletteValue::Biome(12)).unwrap();
+                }
+            }
+        }
+        let mut buf = vec![];
+        biomes.write(&mut buf).await.unwrap();
+        assert_eq!(buf, [0, 12, 0]);
+        let read = PalettedContainer::read(&mut buf.as_slice(), PaletteKind::Biomes).await.unwrap();
+        assert_eq!(read.get_at(Position::new(3, 3, 3)), PaletteValue::Biome(12));
+    }
+
+    #[tokio::test]
+    async fn read_direct_container() {
+        let mut buf = vec![];
+        15u8.write(&mut buf).await.unwrap();
+        let mut data = PackedArray::new(SECTION_BLOCKS, 15);
+        data.set(0, 20000);
+        data.set(4095, 1);
+        data.write(&mut buf).await.unwrap();
+        let container = PalettedContainer::read(&mut buf.as_slice(), PaletteKind::Blocks).await.unwrap();
+        assert!(container.palette.is_none());
+        assert_eq!(container.get_at(Position::new(0, 0, 0)), PaletteValue::Block(20000));
+        assert_eq!(container.get_at(Position::new(15, 15, 15)), PaletteValue::Block(1));
+        assert_eq!(container.get_at(Position::new(1, 0, 0)), PaletteValue::Block(0));
+    }
+
     #[test]
     fn test_multiple_positions_same_block() {
         let mut container = PalettedContainer::new_blocks();
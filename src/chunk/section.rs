@@ -1,6 +1,6 @@
-use crate::chunk::palette::{PaletteValue, PalettedContainer};
+use crate::chunk::palette::{PaletteKind, PaletteValue, PalettedContainer};
 use crate::chunk::{Biome, BlockState, AIR, SECTION_BLOCKS};
-use crate::err::ProtError;
+use crate::err::{ProtError, ProtoError};
 use crate::protocol_types::compound::Position;
 use crate::protocol_types::traits::WriteProt;
 use async_trait::async_trait;
@@ -20,6 +20,26 @@ impl ChunkSection {
             biomes: PalettedContainer::new_biomes(),
         }
     }
+    /**
+     * A section consisting of nothing but `state`, which is much faster than filling a new section.
+     */
+    pub(crate) fn filled(state: BlockState) -> Self {
+        Self {
+            air_count: if state == AIR { SECTION_BLOCKS as u16 } else { 0 },
+            blocks: PalettedContainer::new_single(PaletteKind::Blocks, PaletteValue::Block(state)),
+            biomes: PalettedContainer::new_biomes(),
+        }
+    }
+
+    /**
+     * A section of the given blocks and biomes, like those read from a region file.
+     */
+    pub(crate) fn from_containers(blocks: PalettedContainer, biomes: PalettedContainer) -> Self {
+        let mut section = Self { air_count: 0, blocks, biomes };
+        section.air_count = Self::positions().filter(|position| section.block(*position) == AIR).count() as u16;
+        section
+    }
+
     fn update_air_at(&mut self, position: Position, new: BlockState) {
         let old = self.block(position);
         if old == AIR && new != AIR {
@@ -60,6 +80,7 @@ impl ChunkSection {
         }
     }
 
+    #[cfg(test)]
     pub(crate) fn fill(&mut self, state: BlockState) {
         for i in 0..16 {
             for j in 0..16 {
@@ -73,10 +94,40 @@ impl ChunkSection {
     pub(crate) fn air_count(&self) -> u16 {
         self.air_count
     }
+
+    // section-relative positions of all blocks, in the order they are stored
+    fn positions() -> impl Iterator<Item = Position> {
+        (0..16).flat_map(|y| (0..16).flat_map(move |z| (0..16).map(move |x| Position::new(x, y, z))))
+    }
+
+    /**
+     * Returns the section-relative positions of the blocks that differ from `previous`, along with
+     * their current state. Sections are compared block by block, so the diff doesn't depend on how
+     * either palette is laid out.
+     */
+    pub(crate) fn diff(&self, previous: &ChunkSection) -> Vec<(Position, BlockState)> {
+        Self::positions()
+            .filter_map(|position| {
+                let state = self.block(position);
+                (state != previous.block(position)).then_some((position, state))
+            })
+            .collect()
+    }
+}
+
+impl PartialEq for ChunkSection {
+    fn eq(&self, other: &Self) -> bool {
+        // biomes are addressed by their 4x4x4 region, so only the first 4 positions of each axis hold one
+        self.air_count == other.air_count
+            && Self::positions().all(|position| self.block(position) == other.block(position))
+            && Self::positions()
+                .filter(|position| position.x < 4 && position.y < 4 && position.z < 4)
+                .all(|position| self.biome(position) == other.biome(position))
+    }
 }
 #[async_trait]
 impl WriteProt for ChunkSection {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         (SECTION_BLOCKS as u16 - self.air_count)
             .write(stream)
             .await?;
@@ -108,6 +159,50 @@ mod test {
         assert_eq!(chunk.air_count(), 16 * 16 * 16 - 1);
     }
 
+    #[test]
+    fn diff_yields_changed_blocks() {
+        let mut previous = ChunkSection::new();
+        previous.fill(9);
+        let mut chunk = previous.clone();
+        assert!(chunk == previous);
+        assert!(chunk.diff(&previous).is_empty());
+
+        chunk.set_block(Position::new(1, 2, 3), 1).unwrap();
+        chunk.set_block(Position::new(15, 15, 0), AIR).unwrap();
+        // setting a block to its current state isn't a change
+        chunk.set_block(Position::new(4, 4, 4), 9).unwrap();
+        assert!(chunk != previous);
+        let mut other_biome = previous.clone();
+        other_biome.set_biome(Position::new(3, 3, 3), 1).unwrap();
+        assert!(other_biome != previous);
+        assert_eq!(
+            chunk.diff(&previous),
+            vec![(Position::new(1, 2, 3), 1), (Position::new(15, 15, 0), AIR)]
+        );
+    }
+
+    #[tokio::test]
+    async fn filled_matches_fill() {
+        let mut expected = ChunkSection::new();
+        expected.fill(9);
+        let mut filled = ChunkSection::filled(9);
+        assert_eq!(filled.air_count(), 0);
+        assert!(filled == expected);
+        assert_eq!(ChunkSection::filled(AIR).air_count(), SECTION_BLOCKS as u16);
+
+        let mut buf = vec![];
+        filled.write(&mut buf).await.unwrap();
+        let mut expected_buf = vec![];
+        expected.write(&mut expected_buf).await.unwrap();
+        assert_eq!(buf, expected_buf);
+
+        // the section still takes other blocks
+        filled.set_block(Position::new(1, 1, 1), 1).unwrap();
+        expected.set_block(Position::new(1, 1, 1), 1).unwrap();
+        assert!(filled == expected);
+        assert_eq!(filled.block(Position::new(0, 0, 0)), 9);
+    }
+
     #[test]
     fn set_and_get_biome() {
         let mut chunk = ChunkSection::new();
@@ -1,12 +1,69 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+use async_nbt::NbtCompound;
 use log::{debug, error};
 use uuid::Uuid;
 
-use crate::chunk::{BlockState, ChunkCol, COLUMN_HEIGHT, SECTION_EDGE};
+use crate::chunk::{anvil, noise, BlockState, AIR, GRASS_BLOCK, STONE, ChunkCol, ChunkColRef, COLUMN_HEIGHT, MIN_SECTION_Y, SECTION_EDGE};
+use crate::chunk::border::WorldBorder;
 use crate::chunk::section::ChunkSection;
-use crate::packets::client::{BlockUpdate, ClientPackets};
-use crate::protocol_types::compound::Position;
+use crate::data::global_palette::GlobalPalette;
+use crate::data::registry::DimensionType;
+use crate::packets::client::{BlockEntityData, BlockUpdate, ClientPackets, Explosion, SoundEffect};
+use crate::protocol_types::compound::{BlockOffset, Identifier, PosRotGround, Position, SoundCategory};
+use crate::protocol_types::primitives::OptVarInt;
+
+/**
+ * The maximum distance between a player's eyes and the center of a block they interact with.
+ */
+const MAX_REACH: f64 = 6.0;
+const EYE_HEIGHT: f64 = 1.62;
+pub(crate) const TICKS_PER_SECOND: i64 = 20;
+// chunk columns sent to a player in every direction around the column they are in
+pub(crate) const VIEW_RADIUS: i32 = 3;
+const TICKS_PER_DAY: i64 = 24000;
+
+// distance in blocks at which a sound of volume 1 can be heard, louder sounds carry further
+const SOUND_RANGE: f64 = 16.0;
+// distance in blocks within which players are shown explosions
+pub(crate) const EXPLOSION_RANGE: f64 = 64.0;
+
+/**
+ * A sound from the sound event registry and how it is played.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Sound {
+    // sent by name, the server doesn't know the ids of the sound event registry
+    pub(crate) name: Identifier,
+    pub(crate) category: SoundCategory,
+    pub(crate) volume: f32,
+    pub(crate) pitch: f32,
+}
+
+impl Sound {
+    /**
+     * The sound of breaking the block with the given name, blocks without a sound of their own sound like stone.
+     */
+    pub(crate) fn block_break(block: &str) -> Self {
+        let block = block.strip_prefix("minecraft:").unwrap_or(block);
+        let group = match block {
+            "grass_block" | "short_grass" | "tall_grass" | "fern" => "grass",
+            "dirt" | "coarse_dirt" | "gravel" => "gravel",
+            "sand" | "red_sand" => "sand",
+            "glass" => "glass",
+            _ if block.ends_with("_planks") || block.ends_with("_log") => "wood",
+            _ if block.ends_with("_wool") => "wool",
+            _ => "stone",
+        };
+        Self {
+            name: Identifier::new("minecraft", &format!("block.{group}.break")).unwrap(),
+            category: SoundCategory::Block,
+            volume: 1.0,
+            pitch: 0.8,
+        }
+    }
+}
 
 pub(crate) struct WorldPlayer {
     pub(crate) uuid: Uuid,
@@ -17,13 +74,61 @@ pub(crate) struct WorldPlayer {
 pub(crate) struct World {
     chunks: HashMap<Position, ChunkSection>,
     players: HashMap<Uuid, WorldPlayer>,
+    // NBT data of block entities like command blocks, by global position
+    block_entities: HashMap<Position, NbtCompound>,
+    pub(crate) spawn: Position,
+    pub(crate) border: WorldBorder,
+    // terrain seed of generated worlds, columns that aren't loaded yet are generated from it on demand
+    seed: Option<u64>,
+    // directory of the region files that unloaded columns are saved to, columns that aren't loaded are read back
+    // from it before they are generated
+    pub(crate) region: Option<PathBuf>,
+    // ticks since the world was created
+    pub(crate) world_age: i64,
+    // ticks since the start of the current day, 0 is sunrise and 6000 is noon
+    pub(crate) time_of_day: i64,
+}
+
+/**
+ * Returns the column that the given block coordinates are in.
+ */
+pub(crate) fn column_of(x: f64, z: f64) -> (i32, i32) {
+    ((x.floor() as i32).div_euclid(SECTION_EDGE as i32), (z.floor() as i32).div_euclid(SECTION_EDGE as i32))
+}
+
+fn columns_around(center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    let mut columns = Vec::new();
+    for x in center.0 - radius..=center.0 + radius {
+        for z in center.1 - radius..=center.1 + radius {
+            columns.push((x, z));
+        }
+    }
+    columns
+}
+
+/**
+ * Returns the columns that come into view and those that go out of view when a player moves from the
+ * column `old` to the column `new`.
+ */
+pub(crate) fn view_changes(old: (i32, i32), new: (i32, i32), radius: i32) -> (Vec<(i32, i32)>, Vec<(i32, i32)>) {
+    let in_view = |center: (i32, i32), (x, z): (i32, i32)| (x - center.0).abs() <= radius && (z - center.1).abs() <= radius;
+    let entered = columns_around(new, radius).into_iter().filter(|column| !in_view(old, *column)).collect();
+    let left = columns_around(old, radius).into_iter().filter(|column| !in_view(new, *column)).collect();
+    (entered, left)
 }
 
 impl World {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             chunks: HashMap::new(),
             players: HashMap::new(),
+            block_entities: HashMap::new(),
+            spawn: Position::new(0, 0, 0),
+            border: WorldBorder::new(),
+            seed: None,
+            region: None,
+            world_age: 0,
+            time_of_day: 0,
         }
     }
 
@@ -32,21 +137,83 @@ impl World {
         for x in -3..=3 {
             for z in -3..=3 {
                 for y in 0..COLUMN_HEIGHT {
-                    let mut chunk = ChunkSection::new();
-                    if y < 3 {
-                        chunk.fill(9);
+                    let chunk = if y < 3 {
+                        let mut chunk = ChunkSection::filled(9);
                         chunk.set_block(Position::new(5, 5, 5), 1).unwrap();
-                    }
+                        chunk
+                    } else {
+                        ChunkSection::new()
+                    };
                     chunks.insert(Position::new(x, y as i32 - 4, z), chunk);
                 }
             }
         }
         Self {
             chunks,
-            players: HashMap::new(),
+            ..Self::new()
         }
     }
 
+    /**
+     * Generates a world of (2 * radius + 1)² chunk columns with hilly terrain from a value noise heightmap:
+     * stone below the surface, a layer of grass on top and air above. The same seed always yields the same terrain.
+     */
+    pub(crate) fn new_generated(seed: u64, radius: i32) -> Self {
+        let mut world = Self {
+            seed: Some(seed),
+            ..Self::new()
+        };
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                world.insert_column(x, z, Self::generate_column(seed, x, z));
+            }
+        }
+        world
+    }
+
+    /**
+     * Whether the dimension type spans exactly the sections of a chunk column. Clients size chunk columns
+     * by the dimension type they joined, so they can't read the chunks of a world that doesn't fit it.
+     */
+    pub(crate) fn fits(dimension: &DimensionType) -> bool {
+        dimension.min_y == MIN_SECTION_Y * SECTION_EDGE as i32 && dimension.height == (COLUMN_HEIGHT * SECTION_EDGE) as i32
+    }
+
+    /**
+     * Generates the terrain of the chunk column at the given column coordinates.
+     */
+    fn generate_column(seed: u64, x: i32, z: i32) -> ChunkCol {
+        let edge = SECTION_EDGE as i32;
+        let bottom = MIN_SECTION_Y * edge;
+        let heights: Vec<(i32, i32, i32)> = (0..edge)
+            .flat_map(|local_x| (0..edge).map(move |local_z| (local_x, local_z)))
+            .map(|(local_x, local_z)| (local_x, local_z, Self::terrain_height(seed, x * edge + local_x, z * edge + local_z)))
+            .collect();
+        // the sections below the lowest surface are solid stone, which is much faster than placing every block
+        let lowest = heights.iter().map(|(_, _, height)| *height).min().unwrap_or(bottom);
+        let solid = ((lowest - bottom).div_euclid(edge)).clamp(0, COLUMN_HEIGHT as i32);
+        let mut column: ChunkCol = (0..COLUMN_HEIGHT as i32)
+            .map(|section| if section < solid { ChunkSection::filled(STONE) } else { ChunkSection::new() })
+            .collect();
+        for (local_x, local_z, height) in heights {
+            for y in bottom + solid * edge..=height {
+                let state = if y == height { GRASS_BLOCK } else { STONE };
+                let section = (y.div_euclid(edge) - MIN_SECTION_Y) as usize;
+                column[section].set_block(Position::new(local_x, y.rem_euclid(edge), local_z), state).unwrap();
+            }
+        }
+        column
+    }
+
+    /**
+     * Returns the y coordinate of the surface block of generated terrain at the given block column.
+     */
+    pub(crate) fn terrain_height(seed: u64, x: i32, z: i32) -> i32 {
+        const BASE_HEIGHT: i32 = -40;
+        const AMPLITUDE: f64 = 24.0;
+        BASE_HEIGHT + (noise::fractal_noise(seed, x, z, 32, 3) * AMPLITUDE) as i32
+    }
+
     /**
      * Returns the chunk at the given chunk position.
      */
@@ -62,10 +229,11 @@ impl World {
         * Returns the chunk pos at the given global position.
     */
     fn chunk_pos_for_global_pos(&self, global_pos: Position) -> Position {
-        let offset_neg_x = if global_pos.x < 0 { -1 } else { 0 };
-        let offset_neg_y = if global_pos.y < 0 { -1 } else { 0 };
-        let offset_neg_z = if global_pos.z < 0 { -1 } else { 0 };
-        Position::new(global_pos.x / SECTION_EDGE as i32 + offset_neg_x, global_pos.y / SECTION_EDGE as i32 + offset_neg_y, global_pos.z / SECTION_EDGE as i32 + offset_neg_z)
+        Position::new(
+            global_pos.x.div_euclid(SECTION_EDGE as i32),
+            global_pos.y.div_euclid(SECTION_EDGE as i32),
+            global_pos.z.div_euclid(SECTION_EDGE as i32),
+        )
     }
 
 
@@ -73,47 +241,694 @@ impl World {
         * Returns the relative position of a block within its chunk given its global position.
     */
     fn rel_chunk_pos_for_global_pos(&self, global_pos: Position) -> Position {
-        Position::new(global_pos.x.rem_euclid(SECTION_EDGE as i32), (global_pos.y + 64).rem_euclid(SECTION_EDGE as i32), global_pos.z.rem_euclid(SECTION_EDGE as i32))
+        Position::new(global_pos.x.rem_euclid(SECTION_EDGE as i32), global_pos.y.rem_euclid(SECTION_EDGE as i32), global_pos.z.rem_euclid(SECTION_EDGE as i32))
     }
 
     /**
      * Returns a vector of pos + chunk columns that are within the radius/render distance of the given position.
      */
-    pub(crate) fn get_chunk_radius(&self, position: Position, radius: i32) -> Vec<(i32, i32, ChunkCol)> {
+    pub(crate) async fn get_chunk_radius(&mut self, position: Position, radius: i32, palette: &GlobalPalette) -> Vec<(i32, i32, ChunkCol)> {
+        let center = self.chunk_pos_for_global_pos(position);
+        self.get_columns(&columns_around((center.x, center.z), radius), palette).await
+    }
+
+    /**
+     * Returns copies of the given chunk columns, loading those that aren't loaded yet.
+     * Callers serialize and send the copies after releasing the world lock.
+     */
+    pub(crate) async fn get_columns(&mut self, positions: &[(i32, i32)], palette: &GlobalPalette) -> Vec<(i32, i32, ChunkCol)> {
         let mut chunks: Vec<(i32, i32, ChunkCol)> = Vec::new();
-        for x in -radius..=radius {
-            for z in -radius..=radius {
-                let mut column: ChunkCol = Vec::with_capacity(COLUMN_HEIGHT);
-                for y in 0..COLUMN_HEIGHT {
-                    if let Some(chunk) = self.chunk(&Position::new(position.x / SECTION_EDGE as i32 + x, y as i32 - 4, position.z / SECTION_EDGE as i32 + z)) {
-                        column.push(chunk.clone());
-                    } else {
-                        error!("Chunk not found at {}, {}, {}", position.x / SECTION_EDGE as i32 + x, y as i32 - 4, position.z / SECTION_EDGE as i32 + z);
-                    }
+        for &(x, z) in positions {
+            self.load_column(x, z, palette).await;
+            let mut column: ChunkCol = Vec::with_capacity(COLUMN_HEIGHT);
+            for y in 0..COLUMN_HEIGHT {
+                if let Some(chunk) = self.chunk(&Position::new(x, y as i32 + MIN_SECTION_Y, z)) {
+                    column.push(chunk.clone());
+                } else if !column.is_empty() {
+                    error!("Chunk not found at {}, {}, {}", x, y as i32 + MIN_SECTION_Y, z);
                 }
-                chunks.push((x, z, column));
             }
+            if column.is_empty() {
+                debug!("Column {x}, {z} isn't loaded");
+                continue;
+            }
+            chunks.push((x, z, column));
         }
         chunks
     }
 
-    pub(crate) fn set_block(&mut self, position: Position, block: BlockState) -> Option<Vec<ClientPackets>> {
+    /**
+     * Returns the block at the given global position, if its chunk is loaded.
+     */
+    pub(crate) fn block(&self, position: Position) -> Option<BlockState> {
         let chunk_pos = self.chunk_pos_for_global_pos(position);
         let rel_pos = self.rel_chunk_pos_for_global_pos(position);
-        if let Some(chunk) = self.chunk_mut(&chunk_pos) {
-            chunk.set_block(rel_pos, block).unwrap();
-            // todo generate update packets for all players who have this chunk loaded
-            Some(vec![ClientPackets::BlockUpdate(BlockUpdate::new(position, block.into()))])
-        } else {
-            None
+        self.chunk(&chunk_pos).map(|chunk| chunk.block(rel_pos))
+    }
+
+    /**
+     * Loads the column at the given column coordinates if it isn't loaded: from the region files if it was saved
+     * there, otherwise it is generated. Worlds without a seed get an empty column.
+     */
+    async fn load_column(&mut self, x: i32, z: i32, palette: &GlobalPalette) {
+        if self.chunk(&Position::new(x, MIN_SECTION_Y, z)).is_some() {
+            return;
         }
+        if let Some(region) = &self.region {
+            match anvil::read_column(region, x, z, palette).await {
+                Ok(Some(column)) => {
+                    debug!("Loading column {x}, {z} from {region:?}");
+                    self.insert_column(x, z, column);
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Couldn't read column {x}, {z} from {region:?}, generating it: {e}"),
+            }
+        }
+        debug!("Generating column {x}, {z}");
+        let column = match self.seed {
+            Some(seed) => Self::generate_column(seed, x, z),
+            None => (0..COLUMN_HEIGHT).map(|_| ChunkSection::new()).collect(),
+        };
+        self.insert_column(x, z, column);
+    }
+
+    /**
+     * Inserts the sections of a column, bottom to top.
+     */
+    pub(crate) fn insert_column(&mut self, x: i32, z: i32, column: ChunkCol) {
+        for (y, section) in column.into_iter().enumerate() {
+            self.chunks.insert(Position::new(x, y as i32 + MIN_SECTION_Y, z), section);
+        }
+    }
+
+    /**
+     * Removes the chunk at the given chunk position from memory.
+     */
+    pub(crate) fn remove_chunk(&mut self, chunk_pos: Position) -> Option<ChunkSection> {
+        self.chunks.remove(&chunk_pos)
+    }
+
+    /**
+     * Returns the loaded columns that should be unloaded to get down to `max_columns`: those without any
+     * player within `keep_radius` chunks, the ones farthest away from all players first.
+     */
+    pub(crate) fn unload_candidates(&self, max_columns: usize, keep_radius: i32) -> Vec<(i32, i32)> {
+        let mut columns: Vec<(i32, i32)> = self.chunks.keys().map(|pos| (pos.x, pos.z)).collect();
+        columns.sort();
+        columns.dedup();
+        let excess = columns.len().saturating_sub(max_columns);
+        let player_columns: Vec<Position> = self
+            .players()
+            .map(|player| self.chunk_pos_for_global_pos(player.position))
+            .collect();
+        // chebyshev distance in chunks to the closest player, like the square of chunks sent to players
+        let distance = |(x, z): (i32, i32)| {
+            player_columns
+                .iter()
+                .map(|pos| (pos.x - x).abs().max((pos.z - z).abs()))
+                .min()
+                .unwrap_or(i32::MAX)
+        };
+        // squared euclidean distance to the closest player, orders columns at the same chebyshev distance
+        let squared_distance = |(x, z): (i32, i32)| {
+            player_columns
+                .iter()
+                .map(|pos| (pos.x - x).pow(2) + (pos.z - z).pow(2))
+                .min()
+                .unwrap_or(i32::MAX)
+        };
+        let mut candidates: Vec<(i32, i32)> = columns
+            .into_iter()
+            .filter(|column| distance(*column) > keep_radius)
+            .collect();
+        candidates.sort_by_key(|column| std::cmp::Reverse(squared_distance(*column)));
+        candidates.truncate(excess);
+        candidates
+    }
+
+    /**
+     * Removes the given columns from memory and returns them, e.g. to persist them.
+     */
+    pub(crate) fn unload_columns(&mut self, columns: &[(i32, i32)]) -> HashMap<(i32, i32), ChunkCol> {
+        let mut unloaded = HashMap::new();
+        for &(x, z) in columns {
+            let column: ChunkCol = (0..COLUMN_HEIGHT)
+                .filter_map(|y| self.remove_chunk(Position::new(x, y as i32 + MIN_SECTION_Y, z)))
+                .collect();
+            if column.len() == COLUMN_HEIGHT {
+                unloaded.insert((x, z), column);
+            }
+        }
+        unloaded
+    }
+
+    /**
+     * Sets the block at the given global position. If its column isn't loaded it is loaded or generated first,
+     * worlds without a seed get an empty column. Fails for positions below or above the world.
+     */
+    pub(crate) async fn set_block(&mut self, position: Position, block: BlockState, palette: &GlobalPalette) -> Result<Vec<ClientPackets>, String> {
+        let chunk_pos = self.chunk_pos_for_global_pos(position);
+        let rel_pos = self.rel_chunk_pos_for_global_pos(position);
+        if chunk_pos.y < MIN_SECTION_Y || chunk_pos.y >= MIN_SECTION_Y + COLUMN_HEIGHT as i32 {
+            return Err(format!("Can't set a block at y {} outside of the world", position.y));
+        }
+        self.load_column(chunk_pos.x, chunk_pos.z, palette).await;
+        let chunk = self.chunk_mut(&chunk_pos).unwrap();
+        chunk.set_block(rel_pos, block).or_else(|err| Err(format!("{err}")))?;
+        // todo generate update packets for all players who have this chunk loaded
+        Ok(vec![ClientPackets::BlockUpdate(BlockUpdate::new(position, block.into()))])
     }
     
+    /**
+     * Returns whether a player standing at `player` can reach the block at `position`.
+     */
+    pub(crate) fn within_reach(player: &PosRotGround, position: Position) -> bool {
+        let dx = position.x as f64 + 0.5 - player.x;
+        let dy = position.y as f64 + 0.5 - (player.y + EYE_HEIGHT);
+        let dz = position.z as f64 + 0.5 - player.z;
+        dx * dx + dy * dy + dz * dz <= MAX_REACH * MAX_REACH
+    }
+
+    /**
+     * Breaks the block at `position` on behalf of a player standing at `player`.
+     * Blocks out of reach are left untouched and the player is sent the original block to undo their client-side prediction.
+     */
+    pub(crate) async fn dig_block(&mut self, player: &PosRotGround, position: Position, palette: &GlobalPalette) -> Vec<ClientPackets> {
+        if !Self::within_reach(player, position) {
+            debug!("Rejected dig at {:?}: out of reach", position);
+            return match self.block(position) {
+                Some(block) => vec![ClientPackets::BlockUpdate(BlockUpdate::new(position, block.into()))],
+                None => vec![],
+            };
+        }
+        self.set_block(position, AIR, palette).await.unwrap_or_default()
+    }
+
+    /**
+     * Places a block at `position` on behalf of a player standing at `player`.
+     * Placing is rejected if the position is out of reach or already occupied, in which case the player is sent the original block.
+     */
+    pub(crate) async fn place_block(&mut self, player: &PosRotGround, position: Position, block: BlockState, palette: &GlobalPalette) -> Vec<ClientPackets> {
+        let current = match self.block(position) {
+            Some(current) => current,
+            None => return vec![],
+        };
+        if current != AIR || !Self::within_reach(player, position) {
+            debug!("Rejected placing at {:?}", position);
+            return vec![ClientPackets::BlockUpdate(BlockUpdate::new(position, current.into()))];
+        }
+        self.set_block(position, block, palette).await.unwrap_or_default()
+    }
+
+    /**
+     * Removes all loaded blocks within `radius` blocks of `center`, along with their block entities.
+     * Returns the explosion packet to broadcast, which lists the removed blocks so that clients remove
+     * them all at once instead of receiving a block update for each.
+     */
+    pub(crate) fn explode(&mut self, center: Position, radius: f32) -> ClientPackets {
+        // offsets have to fit into a byte
+        let reach = (radius.floor() as i32).min(i8::MAX as i32);
+        // the affected sections as they were before the explosion, to list the removed blocks by diffing against
+        let mut previous: HashMap<Position, ChunkSection> = HashMap::new();
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    if (dx * dx + dy * dy + dz * dz) as f32 > radius * radius {
+                        continue;
+                    }
+                    let position = Position::new(center.x + dx, center.y + dy, center.z + dz);
+                    if matches!(self.block(position), None | Some(AIR)) {
+                        continue;
+                    }
+                    let chunk_pos = self.chunk_pos_for_global_pos(position);
+                    let rel_pos = self.rel_chunk_pos_for_global_pos(position);
+                    let section = self.chunk_mut(&chunk_pos).unwrap();
+                    previous.entry(chunk_pos).or_insert_with(|| section.clone());
+                    section.set_block(rel_pos, AIR).unwrap();
+                    self.block_entities.remove(&position);
+                }
+            }
+        }
+        let edge = SECTION_EDGE as i32;
+        let records: Vec<BlockOffset> = previous
+            .iter()
+            .flat_map(|(chunk_pos, section)| {
+                self.chunk(chunk_pos).unwrap().diff(section).into_iter().map(move |(rel_pos, _)| BlockOffset {
+                    x: (chunk_pos.x * edge + rel_pos.x - center.x) as i8,
+                    y: (chunk_pos.y * edge + rel_pos.y - center.y) as i8,
+                    z: (chunk_pos.z * edge + rel_pos.z - center.z) as i8,
+                })
+            })
+            .collect();
+        debug!("Explosion at {:?} removed {} blocks", center, records.len());
+        ClientPackets::Explosion(Explosion::new(
+            center.x as f64 + 0.5,
+            center.y as f64 + 0.5,
+            center.z as f64 + 0.5,
+            radius,
+            records.into(),
+            0.0,
+            0.0,
+            0.0,
+        ))
+    }
+
+    /**
+     * Saves all loaded chunks as Anvil region files into the directory at `path`, along with the seed.
+     * Columns that were saved there before and have been unloaded since are kept.
+     */
+    pub(crate) async fn save_region(&self, path: &Path, palette: &GlobalPalette) -> Result<(), String> {
+        let empty = ChunkSection::new();
+        let mut columns: HashMap<(i32, i32), ChunkColRef> = HashMap::new();
+        for pos in self.chunks.keys() {
+            columns.entry((pos.x, pos.z)).or_insert_with(|| {
+                (0..COLUMN_HEIGHT)
+                    .map(|y| self.chunk(&Position::new(pos.x, y as i32 + MIN_SECTION_Y, pos.z)).unwrap_or(&empty))
+                    .collect()
+            });
+        }
+        anvil::write_regions(path, &columns, palette).await?;
+        match self.seed {
+            Some(seed) => anvil::write_seed(path, seed).await,
+            None => Ok(()),
+        }
+    }
+
+    /**
+     * Loads a world from the Anvil region files inside the directory at `path`. Columns that were never saved
+     * are generated from the saved seed, if there is one.
+     */
+    pub(crate) async fn load_region(path: &Path, palette: &GlobalPalette) -> Result<Self, String> {
+        let mut world = Self::new();
+        world.seed = anvil::read_seed(path).await?;
+        for ((x, z), column) in anvil::read_regions(path, palette).await? {
+            world.insert_column(x, z, column);
+        }
+        Ok(world)
+    }
+
+    pub(crate) fn block_entity(&self, position: Position) -> Option<&NbtCompound> {
+        self.block_entities.get(&position)
+    }
+
+    pub(crate) fn set_block_entity(&mut self, position: Position, nbt: NbtCompound) {
+        self.block_entities.insert(position, nbt);
+    }
+
+    /**
+     * The packet that updates the block entity of type `typ` at `position` for clients, if there is one.
+     */
+    pub(crate) fn block_entity_update(&self, position: Position, typ: i32) -> Option<ClientPackets> {
+        let nbt = self.block_entity(position)?.clone();
+        Some(ClientPackets::BlockEntityData(BlockEntityData::new(position, typ.into(), nbt)))
+    }
+
+    /**
+     * Advances the world age and the time of day, which wraps around after a full day.
+     */
+    pub(crate) fn tick_time(&mut self, ticks: i64) {
+        self.world_age += ticks;
+        self.time_of_day = (self.time_of_day + ticks) % TICKS_PER_DAY;
+    }
+
     pub(crate) fn set_player(&mut self, player: WorldPlayer) {
         self.players.insert(player.uuid, player);
     }
     
+    /**
+     * Updates the position of a player in the world, players that haven't joined are ignored.
+     */
+    pub(crate) fn move_player(&mut self, uuid: Uuid, position: Position) {
+        if let Some(player) = self.players.get_mut(&uuid) {
+            player.position = position;
+        }
+    }
+
+    pub(crate) fn remove_player(&mut self, uuid: Uuid) -> Option<WorldPlayer> {
+        self.players.remove(&uuid)
+    }
+
     pub(crate) fn player(&self, uuid: Uuid) -> Option<&WorldPlayer> {
         self.players.get(&uuid)
     }
-}
\ No newline at end of file
+
+    pub(crate) fn players(&self) -> impl Iterator<Item = &WorldPlayer> {
+        self.players.values()
+    }
+
+    /**
+     * The players within `range` blocks of `position`.
+     */
+    pub(crate) fn players_near(&self, position: Position, range: f64) -> Vec<Uuid> {
+        self.players
+            .values()
+            .filter(|player| {
+                let dx = (player.position.x - position.x) as f64;
+                let dy = (player.position.y - position.y) as f64;
+                let dz = (player.position.z - position.z) as f64;
+                dx * dx + dy * dy + dz * dz <= range * range
+            })
+            .map(|player| player.uuid)
+            .collect()
+    }
+
+    /**
+     * Plays a sound at the center of the block at `position`. Returns the players close enough to hear it along
+     * with the packet to send them.
+     */
+    pub(crate) fn play_sound_at(&self, position: Position, sound: &Sound) -> (Vec<Uuid>, ClientPackets) {
+        let listeners = self.players_near(position, SOUND_RANGE * sound.volume.max(1.0) as f64);
+        let packet = SoundEffect::new(
+            OptVarInt(None),
+            Some(sound.name.clone()),
+            Some(false),
+            None,
+            sound.category,
+            position.x * 8 + 4,
+            position.y * 8 + 4,
+            position.z * 8 + 4,
+            sound.volume,
+            sound.pitch,
+            rand::random(),
+        );
+        (listeners, ClientPackets::SoundEffect(packet))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::connection::ConnectionInfo;
+    use crate::data::global_palette::load_global_palette;
+    use crate::data::registry::load_registry;
+    use crate::protocol_types::traits::WriteProtPacket;
+
+    #[test]
+    fn time_of_day_wraps() {
+        let mut world = World::new();
+        world.time_of_day = 23990;
+        world.world_age = 100;
+        world.tick_time(TICKS_PER_SECOND);
+        assert_eq!(world.time_of_day, 10);
+        assert_eq!(world.world_age, 120);
+    }
+
+    #[test]
+    fn chunk_pos_for_negative_coordinates() {
+        let world = World::new();
+        assert_eq!(world.chunk_pos_for_global_pos(Position::new(-1, 0, 0)), Position::new(-1, 0, 0));
+        assert_eq!(world.chunk_pos_for_global_pos(Position::new(-16, 0, 0)), Position::new(-1, 0, 0));
+        assert_eq!(world.chunk_pos_for_global_pos(Position::new(-17, 0, 0)), Position::new(-2, 0, 0));
+        assert_eq!(world.chunk_pos_for_global_pos(Position::new(0, -64, -16)), Position::new(0, -4, -1));
+        assert_eq!(world.rel_chunk_pos_for_global_pos(Position::new(-17, -64, -1)), Position::new(15, 0, 15));
+    }
+
+    #[tokio::test]
+    async fn chunk_radius_for_negative_coordinates() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_grass();
+        for (x, expected) in [(-1, -1), (-16, -1), (-17, -2)] {
+            let chunks = world.get_chunk_radius(Position::new(x, 0, 0), 0, &palette).await;
+            assert_eq!(chunks.len(), 1);
+            let (chunk_x, chunk_z, column) = &chunks[0];
+            assert_eq!((*chunk_x, *chunk_z), (expected, 0));
+            assert_eq!(column.len(), COLUMN_HEIGHT);
+        }
+    }
+
+    #[test]
+    fn view_changes_when_crossing_into_next_column() {
+        let (entered, left) = view_changes((0, 0), (1, 0), 2);
+        assert_eq!(entered, (-2..=2).map(|z| (3, z)).collect::<Vec<_>>());
+        assert_eq!(left, (-2..=2).map(|z| (-2, z)).collect::<Vec<_>>());
+        let (entered, left) = view_changes((0, 0), (0, 0), 2);
+        assert!(entered.is_empty() && left.is_empty());
+        assert_eq!(column_of(-0.5, 16.0), (-1, 1));
+    }
+
+    #[test]
+    fn generated_world_is_deterministic() {
+        let a = World::new_generated(42, 1);
+        let b = World::new_generated(42, 1);
+        assert_eq!(a.chunks.len(), 9 * COLUMN_HEIGHT);
+        for (pos, section) in &a.chunks {
+            assert_eq!(b.chunk(pos).unwrap().air_count(), section.air_count());
+        }
+        assert_eq!(World::terrain_height(42, 3, -7), World::terrain_height(42, 3, -7));
+    }
+
+    #[tokio::test]
+    async fn set_block_generates_unloaded_column() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_generated(42, 0);
+        let position = Position::new(40, 100, -20);
+        assert_eq!(world.block(position), None);
+        let packets = world.set_block(position, STONE, &palette).await.unwrap();
+        assert!(matches!(packets[..], [ClientPackets::BlockUpdate(_)]));
+        assert_eq!(world.block(position), Some(STONE));
+        // the rest of the column is regular terrain
+        let height = World::terrain_height(42, 41, -20);
+        assert_eq!(world.block(Position::new(41, height, -20)), Some(GRASS_BLOCK));
+        assert_eq!(world.chunks.len(), 2 * COLUMN_HEIGHT);
+
+        assert!(world.set_block(Position::new(0, 320, 0), STONE, &palette).await.is_err());
+        assert!(world.set_block(Position::new(0, -65, 0), STONE, &palette).await.is_err());
+        assert!(world.set_block(Position::new(0, 319, 0), STONE, &palette).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn get_columns_generates_unloaded_columns() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_generated(42, 0);
+        let columns = world.get_columns(&[(0, 0), (5, -5)], &palette).await;
+        assert_eq!(columns.len(), 2);
+        let (x, z, column) = &columns[1];
+        assert_eq!((*x, *z, column.len()), (5, -5, COLUMN_HEIGHT));
+        let height = World::terrain_height(42, 80, -80);
+        assert_eq!(world.block(Position::new(80, height, -80)), Some(GRASS_BLOCK));
+        assert_eq!(world.chunks.len(), 2 * COLUMN_HEIGHT);
+    }
+
+    #[tokio::test]
+    async fn explosion_removes_sphere() {
+        let mut world = World::new_grass();
+        let center = Position::new(0, -20, 0);
+        let packet = world.explode(center, 2.0);
+        for (dx, dy, dz) in [(0, 0, 0), (2, 0, 0), (0, -2, 0), (1, 1, 1), (-1, 0, 1)] {
+            assert_eq!(world.block(Position::new(dx, -20 + dy, dz)), Some(AIR));
+        }
+        for (dx, dy, dz) in [(3, 0, 0), (2, 1, 0), (1, 1, -2)] {
+            assert_eq!(world.block(Position::new(dx, -20 + dy, dz)), Some(9));
+        }
+
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        // the 33 blocks within a radius of 2 make the length of 141 take two bytes
+        assert_eq!(&buf[..3], &[0x8d, 0x01, 0x1e]);
+        assert_eq!(buf[31], 33);
+
+        // air isn't listed, only the block below the surface is removed
+        let packet = world.explode(Position::new(5, -16, 5), 1.0);
+        let ClientPackets::Explosion(explosion) = packet else { panic!("not an explosion") };
+        let mut buf = vec![];
+        explosion.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        assert_eq!(&buf[30..34], &[1, 0, -1i8 as u8, 0]);
+        assert_eq!(world.block(Position::new(5, -17, 5)), Some(AIR));
+    }
+
+    #[tokio::test]
+    async fn overworld_fits() {
+        let registry = load_registry().await.unwrap();
+        assert!(World::fits(registry.dimension_type("minecraft:overworld").unwrap()));
+        let mut shallow = registry.dimension_type("minecraft:overworld").unwrap().clone();
+        shallow.height -= SECTION_EDGE as i32;
+        assert!(!World::fits(&shallow));
+    }
+
+    #[test]
+    fn generated_column_profile() {
+        let world = World::new_generated(42, 0);
+        let height = World::terrain_height(42, 3, 7);
+        assert_eq!(height, -26);
+        assert_eq!(world.block(Position::new(3, height + 1, 7)), Some(AIR));
+        assert_eq!(world.block(Position::new(3, height, 7)), Some(GRASS_BLOCK));
+        assert_eq!(world.block(Position::new(3, height - 1, 7)), Some(STONE));
+        assert_eq!(world.block(Position::new(3, -64, 7)), Some(STONE));
+    }
+
+    fn player_at(x: f64, y: f64, z: f64) -> PosRotGround {
+        PosRotGround { x, y, z, pitch: 0.0, yaw: 0.0, on_ground: true }
+    }
+
+    #[tokio::test]
+    async fn dig_within_reach() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_grass();
+        let target = Position::new(2, -17, 0);
+        assert_eq!(world.block(target), Some(GRASS_BLOCK));
+        let packets = world.dig_block(&player_at(0.5, -16.0, 0.5), target, &palette).await;
+        assert_eq!(packets.len(), 1);
+        assert_eq!(world.block(target), Some(AIR));
+    }
+
+    #[tokio::test]
+    async fn dig_out_of_reach_is_rejected() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_grass();
+        let target = Position::new(10, -17, 0);
+        let packets = world.dig_block(&player_at(0.5, -16.0, 0.5), target, &palette).await;
+        assert_eq!(world.block(target), Some(GRASS_BLOCK));
+        assert!(matches!(packets.as_slice(), [ClientPackets::BlockUpdate(_)]));
+    }
+
+    #[tokio::test]
+    async fn place_block_only_into_air() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_grass();
+        let player = player_at(0.5, -16.0, 0.5);
+        let air = Position::new(1, -16, 0);
+        let grass = Position::new(1, -17, 0);
+        assert_eq!(world.place_block(&player, air, STONE, &palette).await.len(), 1);
+        assert_eq!(world.block(air), Some(STONE));
+        world.place_block(&player, grass, STONE, &palette).await;
+        assert_eq!(world.block(grass), Some(GRASS_BLOCK));
+        world.place_block(&player, Position::new(12, -16, 0), STONE, &palette).await;
+        assert_eq!(world.block(Position::new(12, -16, 0)), Some(AIR));
+    }
+
+    #[tokio::test]
+    async fn sound_heard_nearby() {
+        let mut world = World::new();
+        let near = Uuid::from_u128(1);
+        world.set_player(WorldPlayer { uuid: near, username: "steve".into(), position: Position::new(10, 64, 0) });
+        world.set_player(WorldPlayer { uuid: Uuid::from_u128(2), username: "alex".into(), position: Position::new(100, 64, 0) });
+        let sound = Sound::block_break("minecraft:oak_planks");
+        assert_eq!(sound.name, Identifier::new("minecraft", "block.wood.break").unwrap());
+
+        let (listeners, packet) = world.play_sound_at(Position::new(0, 64, -1), &sound);
+        assert_eq!(listeners, [near]);
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        // no registry id, followed by the name and no fixed range
+        let mut expected = vec![0x64, 0];
+        expected.push(26);
+        expected.extend(b"minecraft:block.wood.break");
+        expected.extend([0, 4]);
+        for coordinate in [4i32, 64 * 8 + 4, -4] {
+            expected.extend(coordinate.to_be_bytes());
+        }
+        expected.extend(1f32.to_be_bytes());
+        expected.extend(0.8f32.to_be_bytes());
+        // followed by the random seed
+        assert_eq!(&buf[1..buf.len() - 8], expected);
+        assert_eq!(buf[0] as usize, expected.len() + 8);
+    }
+
+    #[test]
+    fn players_near_position() {
+        let mut world = World::new();
+        let near = Uuid::from_u128(1);
+        world.set_player(WorldPlayer { uuid: near, username: "steve".into(), position: Position::new(10, 64, 0) });
+        world.set_player(WorldPlayer { uuid: Uuid::from_u128(2), username: "alex".into(), position: Position::new(100, 64, 0) });
+        assert_eq!(world.players_near(Position::new(0, 64, -1), 16.0), [near]);
+        assert!(world.players_near(Position::new(0, 0, 0), 16.0).is_empty());
+    }
+
+    #[test]
+    fn far_columns_unloaded_first() {
+        let mut world = World::new_grass();
+        world.set_player(WorldPlayer {
+            uuid: Uuid::nil(),
+            username: "steve".into(),
+            position: Position::new(-40, -16, -40),
+        });
+        // 49 columns from -3 to 3, the player stands in column (-3, -3)
+        let candidates = world.unload_candidates(47, 2);
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates.contains(&(3, 3)));
+        assert!(!candidates.contains(&(-3, -3)));
+        assert!(world.unload_candidates(49, 2).is_empty());
+        // nearby columns are kept even if the cap is exceeded
+        let candidates = world.unload_candidates(0, 2);
+        assert_eq!(candidates.len(), 49 - 9);
+        assert!(!candidates.contains(&(-1, -1)));
+        assert!(candidates.contains(&(0, -1)));
+
+        let unloaded = world.unload_columns(&[(3, 3)]);
+        assert_eq!(unloaded[&(3, 3)].len(), COLUMN_HEIGHT);
+        assert_eq!(world.block(Position::new(48, -17, 48)), None);
+        assert_eq!(world.block(Position::new(-40, -17, -40)), Some(GRASS_BLOCK));
+    }
+
+    #[tokio::test]
+    async fn unloaded_columns_stay_saved() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_grass();
+        let dir = std::env::temp_dir().join(format!("rustcraft-world-{}", rand::random::<u32>())).join("region");
+        world.save_region(&dir, &palette).await.unwrap();
+        world.unload_columns(&[(3, 3), (-3, 0)]);
+        world.save_region(&dir, &palette).await.unwrap();
+        let loaded = World::load_region(&dir, &palette).await;
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+        assert_eq!(loaded.unwrap().chunks.len(), 49 * COLUMN_HEIGHT);
+    }
+
+    #[tokio::test]
+    async fn unloaded_columns_read_back() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_generated(42, 1);
+        let dir = std::env::temp_dir().join(format!("rustcraft-world-{}", rand::random::<u32>())).join("region");
+        world.region = Some(dir.clone());
+        let position = Position::new(20, 100, 20);
+        world.set_block(position, STONE, &palette).await.unwrap();
+        world.save_region(&dir, &palette).await.unwrap();
+        world.unload_columns(&[(1, 1)]);
+        assert_eq!(world.block(position), None);
+        let columns = world.get_columns(&[(1, 1)], &palette).await;
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(world.block(position), Some(STONE));
+    }
+
+    #[tokio::test]
+    async fn seed_saved_with_region() {
+        let palette = load_global_palette().await.unwrap();
+        let mut world = World::new_generated(42, 0);
+        let dir = std::env::temp_dir().join(format!("rustcraft-world-{}", rand::random::<u32>())).join("region");
+        world.save_region(&dir, &palette).await.unwrap();
+        let level_dat = dir.parent().unwrap().join("level.dat").exists();
+        let loaded = World::load_region(&dir, &palette).await;
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+        assert!(level_dat);
+        let mut loaded = loaded.unwrap();
+        assert_eq!(loaded.seed, Some(42));
+
+        // columns that were never saved are generated like they would have been before saving
+        let columns = loaded.get_columns(&[(2, -1)], &palette).await;
+        assert_eq!(columns.len(), 1);
+        assert!(columns == world.get_columns(&[(2, -1)], &palette).await);
+    }
+
+    #[tokio::test]
+    async fn save_and_load_region() {
+        let palette = load_global_palette().await.unwrap();
+        let world = World::new_grass();
+        let dir = std::env::temp_dir().join(format!("rustcraft-world-{}", rand::random::<u32>())).join("region");
+        world.save_region(&dir, &palette).await.unwrap();
+        let loaded = World::load_region(&dir, &palette).await;
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.chunks.len(), world.chunks.len());
+        for (pos, section) in &world.chunks {
+            let loaded_section = loaded.chunk(pos).unwrap();
+            assert_eq!(loaded_section.air_count(), section.air_count());
+            for block in [Position::new(0, 0, 0), Position::new(5, 5, 5), Position::new(15, 15, 15)] {
+                assert_eq!(loaded_section.block(block), section.block(block));
+            }
+        }
+    }
+}
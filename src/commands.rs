@@ -0,0 +1,500 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::{serve, Assets};
+use crate::chunk::border::DEFAULT_DIAMETER;
+use crate::chunk::world::EXPLOSION_RANGE;
+use crate::connection::{ConnectionInfo, ConnectionState};
+use crate::data::ops::GAMEMASTER_LEVEL;
+use crate::inventory::ItemStack;
+use crate::packets::client;
+use crate::packets::client::ClientPackets;
+use crate::protocol_types::compound::{Chat, CommandNode, GameEvent, GameMode, PosRotGround, Position};
+
+// parser ids in the command argument type registry
+const GAME_PROFILE_PARSER: i32 = 7;
+const VEC3_PARSER: i32 = 10;
+const ITEM_STACK_PARSER: i32 = 14;
+
+// commands only operators may run, like in vanilla
+const OP_COMMANDS: [&str; 7] = ["tp", "spawnpoint", "gamemode", "kill", "explode", "worldborder", "give"];
+
+// power of the explosion of a block of TNT
+const TNT_POWER: f32 = 4.0;
+
+// vanilla doesn't teleport players further up or down than this
+const MAX_TELEPORT_Y: f64 = 20_000_000.0;
+
+// game modes by their name in commands
+const GAME_MODES: [(&str, GameMode); 4] = [
+    ("survival", GameMode::Survival),
+    ("creative", GameMode::Creative),
+    ("adventure", GameMode::Adventure),
+    ("spectator", GameMode::Spectator),
+];
+
+/**
+ * The command graph declared to clients so they can parse and complete commands: `/tp <location>`,
+ * `/tp <destination>`, `/gamemode <mode>`, `/kill`, `/explode` and `/give <item>`.
+ */
+pub(crate) fn command_graph() -> client::Commands {
+    let mut nodes = vec![
+        CommandNode::root(vec![
+            1.into(),
+            4.into(),
+            (5 + GAME_MODES.len() as i32).into(),
+            (6 + GAME_MODES.len() as i32).into(),
+            (7 + GAME_MODES.len() as i32).into(),
+        ]),
+        CommandNode::literal("tp", vec![2.into(), 3.into()], false),
+        CommandNode::argument("location", VEC3_PARSER.into(), vec![], true),
+        CommandNode::argument("destination", GAME_PROFILE_PARSER.into(), vec![], true),
+        CommandNode::literal("gamemode", (5..5 + GAME_MODES.len() as i32).map(Into::into).collect(), false),
+    ];
+    for (name, _) in GAME_MODES {
+        nodes.push(CommandNode::literal(name, vec![], true));
+    }
+    nodes.push(CommandNode::literal("kill", vec![], true));
+    nodes.push(CommandNode::literal("explode", vec![], true));
+    nodes.push(CommandNode::literal("give", vec![(8 + GAME_MODES.len() as i32).into()], false));
+    nodes.push(CommandNode::argument("item", ITEM_STACK_PARSER.into(), vec![], true));
+    client::Commands::new(nodes.into(), 0.into())
+}
+
+/**
+ * The names and positions of the players in the Play state, which commands can refer to.
+ */
+async fn online_players(assets: &Assets) -> Vec<(String, PosRotGround)> {
+    assets
+        .connections
+        .read()
+        .await
+        .iter()
+        .filter_map(|handle| {
+            let connection = handle.connection().read().unwrap();
+            let online = !connection.closed() && matches!(connection.state(), ConnectionState::Play);
+            online.then(|| (connection.username.clone(), connection.position.clone()))
+        })
+        .collect()
+}
+
+// a message shown to the player running a command
+fn feedback(message: &str) -> ClientPackets {
+    ClientPackets::SystemChatMessage(client::SystemChatMessage::new(Chat::new_text(message.into()), false))
+}
+
+// the message for players running a command they aren't allowed to, None if they are
+fn denied(connection: &ConnectionInfo, name: &str) -> Option<ClientPackets> {
+    if !OP_COMMANDS.contains(&name) || connection.is_op(GAMEMASTER_LEVEL) {
+        return None;
+    }
+    debug!("{} isn't allowed to run {name}", connection.username);
+    Some(feedback("You don't have permission to use this command"))
+}
+
+/**
+ * Runs a command sent by the client, without the leading slash, and returns the packets for the player running it.
+ * Commands changing the world send the change to the players it affects themselves.
+ */
+pub(crate) async fn execute(connection: &RwLock<ConnectionInfo>, assets: &Assets, command: &str) -> Vec<ClientPackets> {
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        Some("explode") => explode(connection, assets).await,
+        Some("worldborder") => worldborder(connection, assets, &parts.collect::<Vec<_>>()).await,
+        Some("give") => give(&mut connection.write().unwrap(), assets, &parts.collect::<Vec<_>>()),
+        _ => {
+            let players = online_players(assets).await;
+            run(&mut connection.write().unwrap(), &players, command)
+        }
+    }
+}
+
+/**
+ * Runs a command that only affects the player running it. `players` are the players that are online.
+ */
+fn run(connection: &mut ConnectionInfo, players: &[(String, PosRotGround)], command: &str) -> Vec<ClientPackets> {
+    let mut parts = command.split_whitespace();
+    let Some(name) = parts.next() else {
+        return vec![];
+    };
+    let args: Vec<&str> = parts.collect();
+    if let Some(denied) = denied(connection, name) {
+        return vec![denied];
+    }
+    match name {
+        "tp" => tp(connection, players, &args),
+        "nick" => nick(connection, &args),
+        "spawnpoint" => spawnpoint(connection, &args),
+        "gamemode" => gamemode(connection, &args),
+        "kill" => vec![connection.damage(connection.health)],
+        _ => {
+            debug!("Unknown command: {command}");
+            vec![]
+        }
+    }
+}
+
+/**
+ * `/tp x y z` teleports the player to the given coordinates, `/tp <player>` to another online player.
+ */
+pub(crate) fn tp(connection: &mut ConnectionInfo, players: &[(String, PosRotGround)], args: &[&str]) -> Vec<ClientPackets> {
+    if let [name] = args {
+        return match players.iter().find(|(username, _)| username.eq_ignore_ascii_case(name)) {
+            Some((_, position)) => vec![connection.teleport(position.x, position.y, position.z)],
+            None => vec![feedback("No player was found")],
+        };
+    }
+    let coords: Vec<f64> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+    match coords[..] {
+        [x, y, z] if args.len() == 3 => {
+            if !in_world(x, y, z) {
+                return vec![feedback("Invalid position for teleport")];
+            }
+            vec![connection.teleport(x, y, z)]
+        }
+        _ => vec![],
+    }
+}
+
+// whether the coordinates are finite and within the world border and the height players can be teleported to
+fn in_world(x: f64, y: f64, z: f64) -> bool {
+    let horizontal = -DEFAULT_DIAMETER / 2.0..=DEFAULT_DIAMETER / 2.0;
+    // comparisons with NaN are false, so it's never in range
+    horizontal.contains(&x) && horizontal.contains(&z) && (-MAX_TELEPORT_Y..=MAX_TELEPORT_Y).contains(&y)
+}
+
+/**
+ * `/nick [name]` sets the name shown in chat and the tab list, or resets it to the username if no name is given.
+ */
+pub(crate) fn nick(connection: &mut ConnectionInfo, args: &[&str]) -> Vec<ClientPackets> {
+    connection.display_name = if args.is_empty() {
+        None
+    } else {
+        Some(Chat::new_text(args.join(" ")))
+    };
+    vec![]
+}
+
+/**
+ * `/spawnpoint [x y z]` sets the personal spawn point of the player to the given coordinates or their current position.
+ */
+pub(crate) fn spawnpoint(connection: &mut ConnectionInfo, args: &[&str]) -> Vec<ClientPackets> {
+    let coords: Vec<i32> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+    let position = match coords[..] {
+        [x, y, z] => Position::new(x, y, z),
+        _ => connection.position.clone().into(),
+    };
+    vec![connection.set_respawn_position(position)]
+}
+
+/**
+ * `/explode` blows up the blocks around the player like a block of TNT. The explosion is sent to all players
+ * nearby instead of being returned.
+ */
+async fn explode(connection: &RwLock<ConnectionInfo>, assets: &Assets) -> Vec<ClientPackets> {
+    let center: Position = {
+        let connection = connection.read().unwrap();
+        if let Some(denied) = denied(&connection, "explode") {
+            return vec![denied];
+        }
+        connection.position.clone().into()
+    };
+    let (players, packet) = {
+        let mut world = assets.world.write().await;
+        let packet = world.explode(center, TNT_POWER);
+        (world.players_near(center, EXPLOSION_RANGE), packet)
+    };
+    serve::send_to(assets, &players, packet).await;
+    vec![]
+}
+
+/**
+ * `/worldborder set <diameter> [seconds]` resizes the border, moving it over the given number of seconds,
+ * `/worldborder center <x> <z>` moves its center and `/worldborder warning <time|distance> <value>` sets when
+ * players are warned that it's close. The change is sent to all players.
+ */
+async fn worldborder(connection: &RwLock<ConnectionInfo>, assets: &Assets, args: &[&str]) -> Vec<ClientPackets> {
+    if let Some(denied) = denied(&connection.read().unwrap(), "worldborder") {
+        return vec![denied];
+    }
+    let diameter = |arg: &str| arg.parse().ok().filter(|diameter| (1.0..=DEFAULT_DIAMETER).contains(diameter));
+    let coordinate = |arg: &str| arg.parse().ok().filter(|coordinate: &f64| coordinate.abs() <= DEFAULT_DIAMETER / 2.0);
+    let packet = {
+        let border = &mut assets.world.write().await.border;
+        match *args {
+            ["set", size] => diameter(size).map(|size| border.set_size(size)),
+            ["set", size, seconds] => match (diameter(size), seconds.parse()) {
+                (Some(size), Ok(seconds)) => Some(border.lerp_size(size, Duration::from_secs(seconds))),
+                _ => None,
+            },
+            ["center", x, z] => match (coordinate(x), coordinate(z)) {
+                (Some(x), Some(z)) => Some(border.set_center(x, z)),
+                _ => None,
+            },
+            ["warning", "time", seconds] => seconds.parse().ok().map(|seconds| border.set_warning_time(seconds)),
+            ["warning", "distance", blocks] => blocks.parse().ok().map(|blocks| border.set_warning_distance(blocks)),
+            _ => None,
+        }
+    };
+    let Some(packet) = packet else {
+        return vec![feedback("Invalid world border arguments")];
+    };
+    serve::broadcast(assets, None, packet).await;
+    vec![]
+}
+
+/**
+ * `/give <item>` puts one of the given item into the first empty slot of the player's inventory.
+ * Like in vanilla, the namespace of the item may be left out.
+ */
+fn give(connection: &mut ConnectionInfo, assets: &Assets, args: &[&str]) -> Vec<ClientPackets> {
+    if let Some(denied) = denied(connection, "give") {
+        return vec![denied];
+    }
+    let item_id = match args {
+        [item] if item.contains(':') => assets.items.item_id(item),
+        [item] => assets.items.item_id(&format!("minecraft:{item}")),
+        _ => None,
+    };
+    // there are no stacks of air
+    let Some(item_id) = item_id.filter(|item_id| *item_id != 0) else {
+        return vec![feedback("Unknown item")];
+    };
+    match connection.inventory.add(ItemStack::new(item_id, 1)) {
+        Some(slot) => vec![connection.inventory.slot_update(slot)],
+        None => vec![feedback("Your inventory is full")],
+    }
+}
+
+// the game mode with the given name, as used in commands
+fn parse_game_mode(name: &str) -> Option<GameMode> {
+    GAME_MODES.iter().find(|(mode_name, _)| *mode_name == name).map(|(_, mode)| *mode)
+}
+
+/**
+ * `/gamemode <mode>` switches the player to the given game mode and updates their abilities accordingly.
+ */
+pub(crate) fn gamemode(connection: &mut ConnectionInfo, args: &[&str]) -> Vec<ClientPackets> {
+    let Some(game_mode) = args.first().and_then(|name| parse_game_mode(name)) else {
+        return vec![feedback("Unknown game mode")];
+    };
+    connection.game_mode = game_mode;
+    if !matches!(game_mode, GameMode::Creative | GameMode::Spectator) {
+        connection.flying = false;
+    }
+    vec![
+        ClientPackets::SendGameEvent(client::SendGameEvent::new(GameEvent::SetGameMode(game_mode))),
+        ClientPackets::PlayerAbilities(client::PlayerAbilities::for_game_mode(game_mode, connection.flying)),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::chunk::AIR;
+    use crate::chunk::world::WorldPlayer;
+    use crate::serve::{ConnectionActorHandle, ConnectionActorMessage};
+    use crate::protocol_types::primitives::VarInt;
+    use crate::protocol_types::traits::{WriteProt, WriteProtPacket};
+
+    // a connection of a player allowed to run all commands
+    fn op() -> ConnectionInfo {
+        let mut connection = ConnectionInfo::new();
+        connection.op_level = GAMEMASTER_LEVEL;
+        connection
+    }
+
+    #[tokio::test]
+    async fn command_graph_bytes() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let mut buf = vec![];
+        ClientPackets::Commands(command_graph()).write(&mut buf, connection).await.unwrap();
+        let mut expected = vec![
+            0x11, // packet id
+            13, // node count
+            0x00, 5, 1, 4, 9, 10, 11, // root: flags, children 1, 4, 9, 10 and 11
+            0x01, 2, 2, 3, 2, b't', b'p', // literal "tp" with children 2 and 3
+            0x06, 0, 8, b'l', b'o', b'c', b'a', b't', b'i', b'o', b'n', 10, // executable argument using vec3
+            0x06, 0, 11, // executable argument using game_profile
+        ];
+        expected.extend(b"destination");
+        expected.extend([7]); // parser
+        expected.extend([0x01, 4, 5, 6, 7, 8, 8]); // literal "gamemode" with a child for each mode
+        expected.extend(b"gamemode");
+        for mode in ["survival", "creative", "adventure", "spectator"] {
+            expected.extend([0x05, 0, mode.len() as u8]); // executable literal without children
+            expected.extend(mode.as_bytes());
+        }
+        expected.extend([0x05, 0, 4]);
+        expected.extend(b"kill");
+        expected.extend([0x05, 0, 7]);
+        expected.extend(b"explode");
+        expected.extend([0x01, 1, 12, 4]); // literal "give" with child 12
+        expected.extend(b"give");
+        expected.extend([0x06, 0, 4]);
+        expected.extend(b"item");
+        expected.extend([14]); // item_stack parser
+        expected.push(0); // root index
+        let mut prefixed = vec![];
+        VarInt::from(expected.len()).write(&mut prefixed).await.unwrap();
+        prefixed.extend(expected);
+        assert_eq!(buf, prefixed);
+    }
+
+    #[test]
+    fn tp_teleports() {
+        let mut connection = op();
+        let res = run(&mut connection, &[], "tp 10 70.5 -3");
+        assert!(matches!(res.as_slice(), [ClientPackets::SynchronizePlayerPosition(_)]));
+        assert_eq!((connection.position.x, connection.position.y, connection.position.z), (10.0, 70.5, -3.0));
+        assert!(connection.awaiting_teleport);
+
+        assert!(run(&mut connection, &[], "tp 1 2").is_empty());
+    }
+
+    #[test]
+    fn tp_outside_world_rejected() {
+        let mut connection = op();
+        for command in ["tp nan 0 0", "tp 0 inf 0", "tp 0 0 -inf", "tp 1e300 64 0", "tp 0 64 30000000"] {
+            let res = run(&mut connection, &[], command);
+            assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]), "{command} wasn't rejected");
+        }
+        assert_eq!((connection.position.x, connection.position.y, connection.position.z), (0.0, 0.0, 0.0));
+        assert!(!connection.awaiting_teleport);
+    }
+
+    #[test]
+    fn game_mode_names() {
+        assert_eq!(parse_game_mode("survival"), Some(GameMode::Survival));
+        assert_eq!(parse_game_mode("creative"), Some(GameMode::Creative));
+        assert_eq!(parse_game_mode("adventure"), Some(GameMode::Adventure));
+        assert_eq!(parse_game_mode("spectator"), Some(GameMode::Spectator));
+        assert_eq!(parse_game_mode("hardcore"), None);
+        assert_eq!(parse_game_mode("1"), None);
+    }
+
+    #[test]
+    fn gamemode_updates_abilities() {
+        let mut connection = op();
+        let res = run(&mut connection, &[], "gamemode creative");
+        assert_eq!(connection.game_mode, GameMode::Creative);
+        match res.as_slice() {
+            [ClientPackets::SendGameEvent(_), ClientPackets::PlayerAbilities(abilities)] => {
+                assert_eq!(format!("{abilities:?}"), format!("{:?}", client::PlayerAbilities::for_game_mode(GameMode::Creative, false)));
+            }
+            _ => panic!("Expected a game event and abilities"),
+        }
+
+        connection.flying = true;
+        run(&mut connection, &[], "gamemode survival");
+        assert_eq!(connection.game_mode, GameMode::Survival);
+        assert!(!connection.flying);
+        let res = run(&mut connection, &[], "gamemode");
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+    }
+
+    #[test]
+    fn tp_to_player() {
+        let mut connection = op();
+        let bob = PosRotGround { x: 12.5, y: 65.0, z: -7.25, ..connection.position.clone() };
+        let players = [("bob".to_string(), bob)];
+        let res = run(&mut connection, &players, "tp bob");
+        assert!(matches!(res.as_slice(), [ClientPackets::SynchronizePlayerPosition(_)]));
+        assert_eq!((connection.position.x, connection.position.y, connection.position.z), (12.5, 65.0, -7.25));
+
+        let res = run(&mut connection, &players, "tp alice");
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+        assert_eq!(connection.position.x, 12.5);
+    }
+
+    #[tokio::test]
+    async fn worldborder_sent_to_all_players() {
+        let assets = Assets::for_test().await;
+        let (handle, mut receiver) = ConnectionActorHandle::detached(Arc::new(RwLock::new(ConnectionInfo::new())));
+        assets.connections.write().await.push(handle);
+        let connection = RwLock::new(op());
+
+        assert!(execute(&connection, &assets, "worldborder set 100 30").await.is_empty());
+        assert_eq!(assets.world.read().await.border.diameter, 100.0);
+        assert!(matches!(receiver.try_recv(), Ok(ConnectionActorMessage::Broadcast(None, ClientPackets::SetBorderLerpSize(_)))));
+        execute(&connection, &assets, "worldborder center 10.5 -20").await;
+        assert_eq!(assets.world.read().await.border.center, (10.5, -20.0));
+
+        for command in ["worldborder set -5", "worldborder center 0", "worldborder warning time soon", "worldborder"] {
+            let res = execute(&connection, &assets, command).await;
+            assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]), "{command} wasn't rejected");
+        }
+        let res = execute(&RwLock::new(ConnectionInfo::new()), &assets, "worldborder set 5").await;
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+        assert_eq!(assets.world.read().await.border.diameter, 100.0);
+    }
+
+    #[tokio::test]
+    async fn give_fills_inventory() {
+        let assets = Assets::for_test().await;
+        let connection = RwLock::new(op());
+        let res = execute(&connection, &assets, "give stone").await;
+        assert!(matches!(res.as_slice(), [ClientPackets::SetContainerSlot(_)]));
+        execute(&connection, &assets, "give minecraft:stone").await;
+        let stone = Some(ItemStack::new(1, 1));
+        assert_eq!(connection.read().unwrap().inventory.get(36), stone.as_ref());
+        assert_eq!(connection.read().unwrap().inventory.get(37), stone.as_ref());
+
+        for command in ["give dirt_sword", "give air", "give", "give stone 5"] {
+            let res = execute(&connection, &assets, command).await;
+            assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]), "{command} wasn't rejected");
+        }
+        let res = execute(&RwLock::new(ConnectionInfo::new()), &assets, "give stone").await;
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+    }
+
+    #[tokio::test]
+    async fn explosion_sent_to_nearby_players() {
+        let assets = Assets::for_test().await;
+        let mut handles = vec![];
+        for (id, name, x) in [(1, "near", 40), (2, "far", 100)] {
+            let mut connection = ConnectionInfo::new();
+            connection.uuid = Uuid::from_u128(id);
+            assets.world.write().await.set_player(WorldPlayer {
+                uuid: connection.uuid,
+                username: name.into(),
+                position: Position::new(x, -20, 0),
+            });
+            let (handle, receiver) = ConnectionActorHandle::detached(Arc::new(RwLock::new(connection)));
+            assets.connections.write().await.push(handle);
+            handles.push(receiver);
+        }
+        let connection = RwLock::new(op());
+        connection.write().unwrap().position = PosRotGround { y: -20.0, ..op().position };
+        assert!(execute(&connection, &assets, "explode").await.is_empty());
+        assert_eq!(assets.world.read().await.block(Position::new(0, -21, 0)), Some(AIR));
+        assert!(matches!(handles[0].try_recv(), Ok(ConnectionActorMessage::SendPacket(ClientPackets::Explosion(_)))));
+        assert!(handles[1].try_recv().is_err());
+
+        let res = execute(&RwLock::new(ConnectionInfo::new()), &assets, "explode").await;
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+    }
+
+    #[test]
+    fn op_commands_denied_without_op() {
+        let mut connection = ConnectionInfo::new();
+        for command in ["gamemode creative", "tp 10 70 -3", "kill", "spawnpoint 1 2 3"] {
+            let res = run(&mut connection, &[], command);
+            assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]), "{command} wasn't denied");
+        }
+        assert_eq!(connection.game_mode, GameMode::Survival);
+        assert_eq!(connection.position.x, 0.0);
+        assert!(!connection.is_dead());
+        assert!(connection.respawn_position.is_none());
+
+        let res = run(&mut connection, &[], "nick Steve");
+        assert!(res.is_empty());
+        assert!(connection.display_name.is_some());
+    }
+}
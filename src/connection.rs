@@ -1,14 +1,43 @@
 use std::fmt::{Debug, Formatter};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicI32, Ordering};
 
 use log::debug;
 use openssl::symm::Crypter;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+use crate::buffer_pool::BufferPool;
+use crate::err::ProtError;
+use crate::inventory::Inventory;
+use crate::keep_alive::KeepAlive;
 use crate::packets::client::ClientPackets;
-use crate::protocol_types::compound::PosRotGround;
+use crate::packets::client;
+use crate::protocol_types::compound::{Chat, GameMode, Position, PosRotGround};
 use crate::protocol_types::primitives::VarInt;
 
+/**
+ * Hands out entity ids, which are shared by all entities of the server. Players get theirs when entering Play.
+ */
+pub(crate) struct EntityIds(AtomicI32);
+
+impl EntityIds {
+    pub(crate) fn new() -> Self {
+        Self(AtomicI32::new(0))
+    }
+
+    pub(crate) fn next(&self) -> i32 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+// position updates (sent 20 times a second) a player may stay in the air without falling before they count as flying
+const MAX_AIRBORNE_UPDATES: u32 = 80;
+// health, food level and saturation of a player that just spawned
+pub(crate) const MAX_HEALTH: f32 = 20.0;
+const MAX_FOOD: i32 = 20;
+const INITIAL_SATURATION: f32 = 5.0;
+
 #[derive(Debug, Copy, Clone)]
 pub(crate) enum ConnectionState {
     Handshake,
@@ -18,18 +47,80 @@ pub(crate) enum ConnectionState {
     Play,
 }
 
+/**
+ * The state the client asks to switch to in the handshake.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum NextState {
+    Status = 1,
+    Login = 2,
+    // used by newer protocols when a client is transferred from another server
+    Transfer = 3,
+}
+
+impl TryFrom<i32> for NextState {
+    type Error = ProtError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(NextState::Status),
+            2 => Ok(NextState::Login),
+            3 => Ok(NextState::Transfer),
+            _ => Err(ProtError::InvalidNextState(value.into())),
+        }
+    }
+}
+
 pub(crate) struct ConnectionInfo {
     state: ConnectionState,
+    // address of the client, None for connections without a socket behind them
+    pub(crate) address: Option<IpAddr>,
     pub(crate) verify_token: Vec<u8>,
     pub(crate) encrypter: Option<Crypter>,
     pub(crate) decrypter: Option<Crypter>,
     pub(crate) username: String,
+    // nickname shown in chat and the tab list instead of the username
+    pub(crate) display_name: Option<Chat>,
+    // brand the client announced over the minecraft:brand channel, e.g. "vanilla"
+    pub(crate) client_brand: Option<String>,
+    // host and port a transferred client was sent to by the previous server, None for regular logins
+    pub(crate) transfer_origin: Option<(String, u16)>,
+    // uuid forwarded by a proxy that already authenticated the player
+    pub(crate) forwarded_uuid: Option<Uuid>,
     pub(crate) uuid: Uuid,
+    // permission level from the ops list, 0 for players that aren't ops
+    pub(crate) op_level: u8,
+    // assigned when entering the Play state
+    pub(crate) entity_id: i32,
     pub(crate) teleport_id: VarInt,
-    pub(crate) keep_alive_id: i64,
+    // whether the client has yet to confirm the last teleport, position updates are ignored until then
+    pub(crate) awaiting_teleport: bool,
+    // ids of teleports sent since the last confirmation, the last one being the most recent
+    outstanding_teleport_ids: Vec<VarInt>,
+    pub(crate) keep_alive: KeepAlive,
+    // id of the ping sent at the end of configuration, the transition to Play waits for its pong
+    pub(crate) configuration_ping_id: Option<i32>,
     closed: bool,
     pub(crate) position: PosRotGround,
+    // column the chunks sent to the client are centered on, None until the first chunks are sent
+    pub(crate) center_chunk: Option<(i32, i32)>,
+    // selected hotbar slot, 0 to 8
+    pub(crate) held_slot: u8,
+    pub(crate) inventory: Inventory,
+    pub(crate) game_mode: GameMode,
+    // in half hearts, the player is dead at 0
+    pub(crate) health: f32,
+    pub(crate) food: i32,
+    pub(crate) saturation: f32,
+    // consecutive position updates in the air without falling, used to detect flying
+    airborne_updates: u32,
+    // whether the client says the player is flying, only ever set if their abilities allow flying
+    pub(crate) flying: bool,
+    // personal spawn point (e.g. set by a bed), overrides the world spawn when respawning
+    pub(crate) respawn_position: Option<Position>,
     tx: Option<mpsc::Sender<ClientPackets>>,
+    // reusable buffers outbound packets are serialized into
+    pub(crate) buffer_pool: BufferPool,
 }
 impl Debug for ConnectionInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -46,14 +137,32 @@ impl ConnectionInfo {
     pub(crate) fn new() -> ConnectionInfo {
         ConnectionInfo {
             state: ConnectionState::Handshake,
+            address: None,
             verify_token: vec![0, 0, 0, 0],
             encrypter: None,
             decrypter: None,
             username: "".to_string(),
+            display_name: None,
+            client_brand: None,
+            transfer_origin: None,
+            forwarded_uuid: None,
+            entity_id: 0,
             uuid: Uuid::nil(),
+            op_level: 0,
             teleport_id: 0.into(),
-            keep_alive_id: 0,
+            awaiting_teleport: false,
+            outstanding_teleport_ids: vec![],
+            keep_alive: KeepAlive::new(),
+            configuration_ping_id: None,
             closed: false,
+            held_slot: 0,
+            inventory: Inventory::new(),
+            game_mode: GameMode::Survival,
+            health: MAX_HEALTH,
+            food: MAX_FOOD,
+            saturation: INITIAL_SATURATION,
+            airborne_updates: 0,
+            flying: false,
             position: PosRotGround {
                 x: 0.0,
                 y: 0.0,
@@ -62,7 +171,10 @@ impl ConnectionInfo {
                 yaw: 0.0,
                 on_ground: false,
             },
+            center_chunk: None,
+            respawn_position: None,
             tx: None,
+            buffer_pool: BufferPool::from_env(),
         }
     }
 
@@ -74,6 +186,127 @@ impl ConnectionInfo {
         &self.state
     }
 
+    /**
+     * Returns the name of the player as displayed to other players, which falls back to the username.
+     */
+    pub(crate) fn display_name(&self) -> Chat {
+        self.display_name
+            .clone()
+            .unwrap_or_else(|| Chat::new_text(self.username.clone()))
+    }
+
+    /**
+     * Sets the personal spawn point of the player and returns the packet informing the client about it.
+     */
+    pub(crate) fn set_respawn_position(&mut self, position: Position) -> ClientPackets {
+        self.respawn_position = Some(position);
+        ClientPackets::SetDefaultSpawnPosition(client::SetDefaultSpawnPosition::new(position, 0.0))
+    }
+
+    /**
+     * Brings the player back to life at their personal spawn point, or the world spawn if they don't have one.
+     * Returns the packets respawning the client, restoring its health and teleporting it there.
+     */
+    pub(crate) fn respawn(&mut self, world_spawn: Position) -> Vec<ClientPackets> {
+        self.health = MAX_HEALTH;
+        self.food = MAX_FOOD;
+        self.saturation = INITIAL_SATURATION;
+        let spawn = self.respawn_position.unwrap_or(world_spawn);
+        vec![
+            ClientPackets::Respawn(client::Respawn::after_death(self.game_mode)),
+            self.health_update(),
+            self.teleport(spawn.x as f64 + 0.5, spawn.y as f64, spawn.z as f64 + 0.5),
+        ]
+    }
+
+    /**
+     * Whether the player's op level, as of when they logged in, is at least the given level.
+     */
+    pub(crate) fn is_op(&self, level: u8) -> bool {
+        self.op_level >= level
+    }
+
+    pub(crate) fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /**
+     * Takes the given amount of health from the player, at most down to 0 which kills them.
+     * Returns the packet updating the health shown by the client, which shows the death screen at 0.
+     */
+    pub(crate) fn damage(&mut self, amount: f32) -> ClientPackets {
+        self.health = (self.health - amount).max(0.0);
+        if self.is_dead() {
+            debug!("{} died", self.username);
+        }
+        self.health_update()
+    }
+
+    fn health_update(&self) -> ClientPackets {
+        ClientPackets::SetHealth(client::SetHealth::new(self.health, self.food.into(), self.saturation))
+    }
+
+    /**
+     * Tracks how long the player has been in the air without falling and returns whether they are flying.
+     * Players in creative or spectator mode are allowed to fly.
+     */
+    pub(crate) fn is_flying(&mut self, new: &PosRotGround) -> bool {
+        if matches!(self.game_mode, GameMode::Creative | GameMode::Spectator) || new.on_ground || new.y < self.position.y {
+            self.airborne_updates = 0;
+            return false;
+        }
+        self.airborne_updates += 1;
+        self.airborne_updates > MAX_AIRBORNE_UPDATES
+    }
+
+    /**
+     * Moves the player to the given coordinates and returns the packet teleporting the client there.
+     */
+    pub(crate) fn teleport(&mut self, x: f64, y: f64, z: f64) -> ClientPackets {
+        self.position.x = x;
+        self.position.y = y;
+        self.position.z = z;
+        self.begin_teleport();
+        ClientPackets::SynchronizePlayerPosition(client::SynchronizePlayerPosition::new(
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            0.0,
+            0.0,
+            0u8,
+            self.teleport_id,
+        ))
+    }
+
+    /**
+     * Generates a new teleport id and ignores position updates from the client until it confirms the teleport.
+     */
+    pub(crate) fn begin_teleport(&mut self) -> VarInt {
+        self.teleport_id = rand::random::<usize>().into();
+        self.awaiting_teleport = true;
+        self.outstanding_teleport_ids.push(self.teleport_id);
+        self.teleport_id
+    }
+
+    /**
+     * Checks a teleport confirmation from the client and returns whether it confirms the most recent teleport.
+     * Confirmations of earlier teleports that were superseded are ignored, unknown ids are rejected.
+     */
+    pub(crate) fn confirm_teleport(&mut self, id: VarInt) -> Result<bool, ProtError> {
+        if !self.awaiting_teleport {
+            return Ok(false);
+        }
+        if id == self.teleport_id {
+            self.awaiting_teleport = false;
+            self.outstanding_teleport_ids.clear();
+            return Ok(true);
+        }
+        if self.outstanding_teleport_ids.contains(&id) {
+            return Ok(false);
+        }
+        Err(ProtError::TeleportIdMismatch(self.teleport_id, id))
+    }
+
     pub(crate) fn close(&mut self) {
         self.closed = true;
         self.tx = None;
@@ -83,3 +316,58 @@ impl ConnectionInfo {
         self.closed
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entity_ids_increase() {
+        let ids = EntityIds::new();
+        let first = ids.next();
+        let second = ids.next();
+        assert!(second > first);
+    }
+
+    #[test]
+    fn next_state_mapping() {
+        assert_eq!(NextState::try_from(1).unwrap(), NextState::Status);
+        assert_eq!(NextState::try_from(2).unwrap(), NextState::Login);
+        assert_eq!(NextState::try_from(3).unwrap(), NextState::Transfer);
+        assert!(matches!(NextState::try_from(4), Err(ProtError::InvalidNextState(v)) if v.value == 4));
+        assert!(matches!(NextState::try_from(0), Err(ProtError::InvalidNextState(_))));
+    }
+
+    #[test]
+    fn respawn_at_personal_spawn() {
+        let mut connection = ConnectionInfo::new();
+        connection.set_respawn_position(Position::new(10, 70, -5));
+        connection.respawn(Position::new(0, 64, 0));
+        assert_eq!(Position::from(connection.position.clone()), Position::new(10, 70, -5));
+    }
+
+    #[test]
+    fn damage_kills_at_zero_health() {
+        let mut connection = ConnectionInfo::new();
+        assert!(matches!(connection.damage(5.0), ClientPackets::SetHealth(_)));
+        assert_eq!(connection.health, 15.0);
+        assert!(!connection.is_dead());
+        connection.damage(25.0);
+        assert_eq!(connection.health, 0.0);
+        assert!(connection.is_dead());
+
+        let packets = connection.respawn(Position::new(0, 64, 0));
+        assert!(matches!(
+            packets.as_slice(),
+            [ClientPackets::Respawn(_), ClientPackets::SetHealth(_), ClientPackets::SynchronizePlayerPosition(_)]
+        ));
+        assert_eq!(connection.health, MAX_HEALTH);
+    }
+
+    #[test]
+    fn respawn_at_world_spawn() {
+        let mut connection = ConnectionInfo::new();
+        connection.respawn(Position::new(0, 64, 0));
+        assert_eq!(Position::from(connection.position.clone()), Position::new(0, 64, 0));
+    }
+}
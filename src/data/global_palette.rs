@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+use tokio::fs;
+
+use crate::chunk::{Biome, BlockState};
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub(crate) struct BlockStateName {
+    pub(crate) name: String,
+    pub(crate) properties: BTreeMap<String, String>,
+}
+
+/**
+ * Maps the numeric ids of the global palette (block states and biomes) to their names and back.
+ * Block states are read from `assets/blocks.json`, biomes from the biome registry in `assets/registry.json`.
+ */
+pub(crate) struct GlobalPalette {
+    block_names: HashMap<BlockState, BlockStateName>,
+    block_ids: HashMap<BlockStateName, BlockState>,
+    // state a block is placed in by default, by block name
+    default_states: HashMap<String, BlockState>,
+    biome_names: HashMap<Biome, String>,
+    biome_ids: HashMap<String, Biome>,
+}
+
+impl GlobalPalette {
+    pub(crate) fn block_name(&self, state: BlockState) -> Option<&BlockStateName> {
+        self.block_names.get(&state)
+    }
+
+    pub(crate) fn block_id(&self, name: &BlockStateName) -> Option<BlockState> {
+        self.block_ids.get(name).copied()
+    }
+
+    pub(crate) fn default_state(&self, block_name: &str) -> Option<BlockState> {
+        self.default_states.get(block_name).copied()
+    }
+
+    pub(crate) fn biome_name(&self, biome: Biome) -> Option<&String> {
+        self.biome_names.get(&biome)
+    }
+
+    pub(crate) fn biome_id(&self, name: &str) -> Option<Biome> {
+        self.biome_ids.get(name).copied()
+    }
+}
+
+fn parse_blocks(blocks: &Value) -> Result<HashMap<BlockState, BlockStateName>, String> {
+    let mut block_names = HashMap::new();
+    let blocks = blocks.as_object().ok_or("Block list is not an object")?;
+    for (name, block) in blocks {
+        let states = block["states"]
+            .as_array()
+            .ok_or(format!("Block {name} has no states"))?;
+        for state in states {
+            let id = state["id"]
+                .as_u64()
+                .ok_or(format!("Block state of {name} has no id"))?;
+            let mut properties = BTreeMap::new();
+            if let Some(props) = state["properties"].as_object() {
+                for (key, value) in props {
+                    properties.insert(key.clone(), value.as_str().unwrap_or_default().to_string());
+                }
+            }
+            block_names.insert(
+                id as BlockState,
+                BlockStateName {
+                    name: name.clone(),
+                    properties,
+                },
+            );
+        }
+    }
+    Ok(block_names)
+}
+
+fn parse_default_states(blocks: &Value) -> Result<HashMap<String, BlockState>, String> {
+    let mut default_states = HashMap::new();
+    let blocks = blocks.as_object().ok_or("Block list is not an object")?;
+    for (name, block) in blocks {
+        let default = block["states"]
+            .as_array()
+            .and_then(|states| states.iter().find(|state| state["default"].as_bool() == Some(true)))
+            .and_then(|state| state["id"].as_u64())
+            .ok_or(format!("Block {name} has no default state"))?;
+        default_states.insert(name.clone(), default as BlockState);
+    }
+    Ok(default_states)
+}
+
+fn parse_biomes(registry: &Value) -> Result<HashMap<Biome, String>, String> {
+    let biomes = registry["minecraft:worldgen/biome"]["value"]
+        .as_array()
+        .ok_or("Registry has no biomes")?;
+    let mut biome_names = HashMap::new();
+    for biome in biomes {
+        let name = biome["name"].as_str().ok_or("Biome without name")?;
+        let id = biome["id"].as_u64().ok_or("Biome without id")?;
+        biome_names.insert(id as Biome, name.to_string());
+    }
+    Ok(biome_names)
+}
+
+pub(crate) async fn load_global_palette() -> Result<GlobalPalette, String> {
+    let blocks = fs::read_to_string("assets/blocks.json")
+        .await
+        .or_else(|err| Err(format!("{err}")))?;
+    let blocks = serde_json::from_str::<Value>(&blocks).or_else(|err| Err(format!("{err}")))?;
+    let registry = fs::read_to_string("assets/registry.json")
+        .await
+        .or_else(|err| Err(format!("{err}")))?;
+    let registry = serde_json::from_str::<Value>(&registry).or_else(|err| Err(format!("{err}")))?;
+
+    let block_names = parse_blocks(&blocks)?;
+    let default_states = parse_default_states(&blocks)?;
+    let biome_names = parse_biomes(&registry)?;
+    Ok(GlobalPalette {
+        block_ids: block_names.iter().map(|(k, v)| (v.clone(), *k)).collect(),
+        block_names,
+        default_states,
+        biome_ids: biome_names.iter().map(|(k, v)| (v.clone(), *k)).collect(),
+        biome_names,
+    })
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use tokio::fs;
+
+/**
+ * Maps item names to the protocol ids of the items, as listed in `assets/items.json`.
+ */
+pub(crate) struct ItemRegistry {
+    item_ids: HashMap<String, i32>,
+    item_names: HashMap<i32, String>,
+}
+
+impl ItemRegistry {
+    pub(crate) fn item_id(&self, name: &str) -> Option<i32> {
+        self.item_ids.get(name).copied()
+    }
+
+    pub(crate) fn item_name(&self, item_id: i32) -> Option<&str> {
+        self.item_names.get(&item_id).map(String::as_str)
+    }
+}
+
+pub(crate) async fn load_items() -> Result<ItemRegistry, String> {
+    let items = fs::read_to_string("assets/items.json")
+        .await
+        .or_else(|err| Err(format!("{err}")))?;
+    let item_ids: HashMap<String, i32> = serde_json::from_str(&items).or_else(|err| Err(format!("{err}")))?;
+    Ok(ItemRegistry {
+        item_names: item_ids.iter().map(|(k, v)| (*v, k.clone())).collect(),
+        item_ids,
+    })
+}
@@ -1 +1,4 @@
+pub(crate) mod global_palette;
+pub(crate) mod items;
+pub(crate) mod ops;
 pub(crate) mod registry;
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+use serde::Deserialize;
+use tokio::fs;
+use uuid::Uuid;
+
+const OPS_PATH: &str = "ops.json";
+// op level vanilla requires for cheat commands like /gamemode and for editing command blocks
+pub(crate) const GAMEMASTER_LEVEL: u8 = 2;
+
+#[derive(Deserialize)]
+struct OpEntry {
+    uuid: String,
+    level: u8,
+}
+
+/**
+ * Players with elevated permissions and their op level from 1 to 4, as listed in `ops.json` in the same
+ * format as vanilla's. Everyone else has level 0.
+ */
+pub(crate) struct Ops {
+    levels: HashMap<Uuid, u8>,
+}
+
+impl Ops {
+    pub(crate) fn empty() -> Self {
+        Self { levels: HashMap::new() }
+    }
+
+    fn from_json(json: &str) -> Result<Self, String> {
+        let entries: Vec<OpEntry> = serde_json::from_str(json).or_else(|err| Err(format!("{err}")))?;
+        let mut levels = HashMap::new();
+        for entry in entries {
+            let uuid = Uuid::parse_str(&entry.uuid).or_else(|err| Err(format!("Invalid uuid {}: {err}", entry.uuid)))?;
+            levels.insert(uuid, entry.level);
+        }
+        Ok(Self { levels })
+    }
+
+    pub(crate) fn level(&self, uuid: Uuid) -> u8 {
+        self.levels.get(&uuid).copied().unwrap_or(0)
+    }
+}
+
+/**
+ * Loads the ops from `ops.json`, without any ops if the file doesn't exist.
+ */
+pub(crate) async fn load_ops() -> Result<Ops, String> {
+    match fs::read_to_string(OPS_PATH).await {
+        Ok(ops) => Ops::from_json(&ops),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Ops::empty()),
+        Err(err) => Err(format!("{err}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn op_level_checked() {
+        let op = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let ops = Ops::from_json(r#"[{"uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5", "name": "Notch", "level": 2, "bypassesPlayerLimit": false}]"#).unwrap();
+        assert_eq!(ops.level(op), 2);
+        assert_eq!(ops.level(Uuid::nil()), 0);
+        assert!(Ops::from_json(r#"[{"uuid": "notch", "level": 4}]"#).is_err());
+    }
+}
@@ -1,7 +1,149 @@
 use async_nbt::{NbtCompound, NbtList, NbtTag};
-use serde_json::Value;
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use tokio::fs;
 
+/**
+ * The registry sent to clients during configuration, along with typed views of the entries the
+ * server itself needs. The typed views are built once when the registry is loaded.
+ */
+pub(crate) struct Registry {
+    // the whole registry as sent in the Registry Data packet
+    nbt: NbtCompound,
+    dimension_types: HashMap<String, DimensionType>,
+    // network ids of the chat types, by name
+    chat_types: HashMap<String, i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DimensionType {
+    pub(crate) min_y: i32,
+    pub(crate) height: i32,
+    pub(crate) logical_height: i32,
+    pub(crate) has_skylight: bool,
+}
+
+impl Registry {
+    pub(crate) fn from_json(root: &serde_json::Map<String, Value>) -> Result<Self, String> {
+        let mut dimension_types = HashMap::new();
+        for entry in registry_entries(root, "minecraft:dimension_type")? {
+            let (name, _, element) = parse_entry(entry)?;
+            let int = |key: &str| {
+                element[key]
+                    .as_i64()
+                    .map(|v| v as i32)
+                    .ok_or(format!("Dimension type {name} is missing {key}"))
+            };
+            let dimension_type = DimensionType {
+                min_y: int("min_y")?,
+                height: int("height")?,
+                logical_height: int("logical_height")?,
+                has_skylight: int("has_skylight")? != 0,
+            };
+            dimension_types.insert(name, dimension_type);
+        }
+        let mut chat_types = HashMap::new();
+        for entry in registry_entries(root, "minecraft:chat_type")? {
+            let (name, id, _) = parse_entry(entry)?;
+            chat_types.insert(name, id);
+        }
+        Ok(Self {
+            nbt: parse_registry_object(root)?,
+            dimension_types,
+            chat_types,
+        })
+    }
+
+    pub(crate) fn nbt(&self) -> &NbtCompound {
+        &self.nbt
+    }
+
+    pub(crate) fn dimension_type(&self, name: &str) -> Option<&DimensionType> {
+        self.dimension_types.get(name)
+    }
+
+    pub(crate) fn chat_type_id(&self, name: &str) -> Option<i32> {
+        self.chat_types.get(name).copied()
+    }
+}
+
+// the entries of the registry with the given key, a missing registry counts as empty
+fn registry_entries<'a>(root: &'a serde_json::Map<String, Value>, key: &str) -> Result<&'a Vec<Value>, String> {
+    static EMPTY: Vec<Value> = Vec::new();
+    match root.get(key) {
+        None => Ok(&EMPTY),
+        Some(registry) => registry["value"].as_array().ok_or(format!("Registry {key} has no values")),
+    }
+}
+
+fn parse_entry(entry: &Value) -> Result<(String, i32, &Value), String> {
+    let name = entry["name"].as_str().ok_or("Registry entry without a name")?;
+    let id = entry["id"].as_i64().ok_or(format!("Registry entry {name} without an id"))?;
+    Ok((name.to_string(), id as i32, &entry["element"]))
+}
+
+// registries the client needs during configuration and whether the server can fill in a default
+const REQUIRED_REGISTRIES: [(&str, bool); 6] = [
+    ("minecraft:dimension_type", true),
+    ("minecraft:chat_type", true),
+    ("minecraft:trim_pattern", true),
+    ("minecraft:trim_material", true),
+    ("minecraft:worldgen/biome", false),
+    ("minecraft:damage_type", false),
+];
+
+fn default_registry(key: &str) -> Value {
+    let entries = match key {
+        "minecraft:dimension_type" => json!([{
+            "name": "minecraft:overworld",
+            "id": 0,
+            "element": {
+                "piglin_safe": 0, "natural": 1, "ambient_light": 0.0, "monster_spawn_block_light_limit": 0,
+                "infiniburn": "#minecraft:infiniburn_overworld", "respawn_anchor_works": 0, "has_skylight": 1,
+                "bed_works": 1, "effects": "minecraft:overworld", "has_raids": 1, "logical_height": 384,
+                "coordinate_scale": 1.0, "min_y": -64, "ultrawarm": 0, "has_ceiling": 0, "height": 384,
+                "monster_spawn_light_level": {
+                    "type": "minecraft:uniform",
+                    "value": {"min_inclusive": 0, "max_inclusive": 7}
+                }
+            }
+        }]),
+        "minecraft:chat_type" => json!([{
+            "name": "minecraft:chat",
+            "id": 0,
+            "element": {
+                "chat": {"translation_key": "chat.type.text", "parameters": ["sender", "content"]},
+                "narration": {"translation_key": "chat.type.text.narrate", "parameters": ["sender", "content"]}
+            }
+        }]),
+        _ => json!([]),
+    };
+    json!({"type": key, "value": entries})
+}
+
+/**
+ * Checks that all registries the client requires are present. Missing registries are replaced by a
+ * minimal default where possible, otherwise a warning is logged as the client will reject the
+ * configuration. Returns the keys of the registries that were filled in.
+ */
+pub(crate) fn inject_defaults(root: &mut serde_json::Map<String, Value>) -> Vec<&'static str> {
+    let mut injected = vec![];
+    for (key, has_default) in REQUIRED_REGISTRIES {
+        if root.contains_key(key) {
+            continue;
+        }
+        if has_default {
+            warn!("Registry is missing {key}, using a default");
+            root.insert(key.to_string(), default_registry(key));
+            injected.push(key);
+        } else {
+            warn!("Registry is missing {key}, clients will fail to join");
+        }
+    }
+    injected
+}
+
 fn parse_registry_value(val: &Value) -> Result<NbtTag, String> {
     Ok(match val {
         Value::Null => return Err("Null value in registry".into()),
@@ -44,12 +186,60 @@ fn parse_registry_object(object: &serde_json::Map<String, Value>) -> Result<NbtC
     Ok(compound)
 }
 
-pub(crate) async fn load_registry() -> Result<NbtCompound, String> {
+pub(crate) async fn load_registry() -> Result<Registry, String> {
     let registry_json = fs::read_to_string("assets/registry.json").await.unwrap();
-    let registry_json = serde_json::from_str::<Value>(&registry_json).unwrap();
-    let root = registry_json.as_object().unwrap();
+    let mut registry_json = serde_json::from_str::<Value>(&registry_json).unwrap();
+    let root = registry_json.as_object_mut().unwrap();
+    inject_defaults(root);
 
     // for key in registry_json object, insert a new NbtCompound with the key as name
     // and the value as value
-    parse_registry_object(root)
+    Registry::from_json(root)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dimension_type_height() {
+        let sample = serde_json::json!({
+            "minecraft:dimension_type": {
+                "type": "minecraft:dimension_type",
+                "value": [{
+                    "name": "minecraft:overworld",
+                    "id": 0,
+                    "element": {"min_y": -64, "height": 384, "logical_height": 384, "has_skylight": 1}
+                }]
+            },
+            "minecraft:chat_type": {
+                "type": "minecraft:chat_type",
+                "value": [{"name": "minecraft:emote_command", "id": 1, "element": {}}]
+            }
+        });
+        let registry = Registry::from_json(sample.as_object().unwrap()).unwrap();
+        let overworld = registry.dimension_type("minecraft:overworld").unwrap();
+        assert_eq!(overworld.height, 384);
+        assert_eq!(overworld.min_y, -64);
+        assert!(overworld.has_skylight);
+        assert_eq!(registry.dimension_type("minecraft:the_nether"), None);
+        assert_eq!(registry.chat_type_id("minecraft:emote_command"), Some(1));
+        assert!(registry.nbt().inner().get("minecraft:dimension_type").is_some());
+    }
+
+    #[test]
+    fn missing_chat_type_gets_default() {
+        let mut sample = serde_json::json!({
+            "minecraft:dimension_type": default_registry("minecraft:dimension_type"),
+            "minecraft:trim_pattern": {"type": "minecraft:trim_pattern", "value": []},
+            "minecraft:trim_material": {"type": "minecraft:trim_material", "value": []},
+            "minecraft:worldgen/biome": {"type": "minecraft:worldgen/biome", "value": []},
+            "minecraft:damage_type": {"type": "minecraft:damage_type", "value": []},
+        });
+        let root = sample.as_object_mut().unwrap();
+        assert_eq!(inject_defaults(root), vec!["minecraft:chat_type"]);
+        let registry = Registry::from_json(root).unwrap();
+        assert_eq!(registry.chat_type_id("minecraft:chat"), Some(0));
+        assert_eq!(registry.dimension_type("minecraft:overworld").unwrap().height, 384);
+    }
 }
@@ -1,7 +1,10 @@
-use crate::protocol_types::compound::Position;
+use crate::connection::ConnectionState;
+use crate::protocol_types::compound::{Chat, Position};
 use crate::protocol_types::primitives::VarInt;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::string::FromUtf8Error;
 
 #[derive(Debug)]
 pub(crate) enum ProtError {
@@ -10,16 +13,48 @@ pub(crate) enum ProtError {
     KeepAliveIdMismatch(i64, i64),
     TeleportIdMismatch(VarInt, VarInt),
     PositionOutOfBounds(Position),
+    InvalidHeldSlot(i16),
+    // the client doesn't read its packets fast enough and the send queue stayed full
+    SlowClient,
+    FlyingNotAllowed,
+    // a handshake that didn't come through the proxy while proxy forwarding is enabled
+    NotForwarded,
+    // id of a packet that isn't allowed in the connection state it was sent in
+    PacketNotAllowed(i32, ConnectionState),
     Any(String),
 }
 
 impl ProtError {
+    /**
+     * The reason shown to the player when they are disconnected because of this error.
+     */
+    pub(crate) fn disconnect_reason(&self) -> Chat {
+        let reason = match self {
+            ProtError::InvalidNextState(_) => "Invalid handshake",
+            ProtError::KeepAliveIdMismatch(_, _) => "Invalid keep alive response",
+            ProtError::TeleportIdMismatch(_, _) => "Invalid teleport confirmation",
+            ProtError::PositionOutOfBounds(_) => "Position out of bounds",
+            ProtError::InvalidHeldSlot(_) => "Invalid hotbar slot",
+            ProtError::SlowClient => "Too slow",
+            ProtError::FlyingNotAllowed | ProtError::NotForwarded => return Chat::new_text(self.to_string()),
+            ProtError::PacketNotAllowed(_, _) => "Unexpected packet",
+            ProtError::ChannelClosed => "Connection closed",
+            ProtError::Any(_) => "Internal server error",
+        };
+        Chat::new_text(format!("{reason}: {self}"))
+    }
+
     pub(crate) fn is_fatal(&self) -> bool {
         match self {
             ProtError::InvalidNextState(_) => true,
             ProtError::KeepAliveIdMismatch(_, _) => true,
             ProtError::TeleportIdMismatch(_, _) => true,
             ProtError::PositionOutOfBounds(_) => true,
+            ProtError::InvalidHeldSlot(_) => true,
+            ProtError::SlowClient => true,
+            ProtError::FlyingNotAllowed => true,
+            ProtError::NotForwarded => true,
+            ProtError::PacketNotAllowed(_, _) => true,
             ProtError::ChannelClosed => true,
             ProtError::Any(_) => false,
         }
@@ -37,6 +72,13 @@ impl Display for ProtError {
                 write!(f, "Teleport id mismatch: {} != {}", v1, v2)
             }
             ProtError::PositionOutOfBounds(v) => write!(f, "Position out of bounds: {:?}", v),
+            ProtError::InvalidHeldSlot(v) => write!(f, "Invalid held slot: {}", v),
+            ProtError::SlowClient => write!(f, "Client can't keep up with the packets sent to it"),
+            ProtError::FlyingNotAllowed => write!(f, "Flying is not enabled on this server"),
+            ProtError::NotForwarded => write!(f, "This server only accepts connections through its proxy"),
+            ProtError::PacketNotAllowed(id, state) => {
+                write!(f, "Packet 0x{:02x} is not allowed in state {:?}", id, state)
+            }
             ProtError::ChannelClosed => write!(f, "Channel closed"),
             ProtError::Any(v) => write!(f, "{}", v),
         }
@@ -50,3 +92,85 @@ impl From<String> for ProtError {
         ProtError::Any(s)
     }
 }
+
+/**
+ * Errors that occur while reading or writing protocol types.
+ */
+#[derive(Debug)]
+pub(crate) enum ProtoError {
+    Io(io::Error),
+    Utf8(FromUtf8Error),
+    VarIntTooLong,
+    VarLongTooLong,
+    // length of the string in bytes
+    StringTooLong(usize),
+    // length of the string in characters and the most that are allowed
+    TooManyChars(usize, usize),
+    // name of the enum and the unknown tag
+    InvalidEnumTag(&'static str, String),
+    InvalidIdentifier(String),
+    // what the length is of and the length that was sent
+    InvalidLength(&'static str, i32),
+    Nbt(String),
+    // size of a packet frame as declared by its length prefix and the bytes actually serialized
+    FrameSizeMismatch(usize, usize),
+    Json(serde_json::Error),
+    Crypto(openssl::error::ErrorStack),
+    // coordinates that don't fit into the bits a position is packed into
+    PositionOutOfRange(Position),
+}
+
+impl Display for ProtoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtoError::Io(e) => write!(f, "IO error: {}", e),
+            ProtoError::Utf8(e) => write!(f, "UTF-8 error: {}", e),
+            ProtoError::VarIntTooLong => write!(f, "VarInt is too long (max length: 5 B)"),
+            ProtoError::VarLongTooLong => write!(f, "VarLong is too long (max length: 10 B)"),
+            ProtoError::StringTooLong(len) => write!(f, "String too long: {} B", len),
+            ProtoError::TooManyChars(len, max) => write!(f, "String too long: {} characters, at most {} allowed", len, max),
+            ProtoError::InvalidEnumTag(name, tag) => write!(f, "Invalid {}: {}", name, tag),
+            ProtoError::InvalidIdentifier(id) => write!(f, "Invalid identifier: {}", id),
+            ProtoError::InvalidLength(name, len) => write!(f, "Invalid {} length: {}", name, len),
+            ProtoError::Nbt(e) => write!(f, "NBT error: {}", e),
+            ProtoError::FrameSizeMismatch(declared, written) => {
+                write!(f, "Packet frame declared {} B but {} B were written", declared, written)
+            }
+            ProtoError::Json(e) => write!(f, "JSON error: {}", e),
+            ProtoError::Crypto(e) => write!(f, "Crypter error: {}", e),
+            ProtoError::PositionOutOfRange(v) => write!(f, "Position can't be encoded: {:?}", v),
+        }
+    }
+}
+
+impl Error for ProtoError {}
+
+impl From<io::Error> for ProtoError {
+    fn from(e: io::Error) -> Self {
+        ProtoError::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for ProtoError {
+    fn from(e: FromUtf8Error) -> Self {
+        ProtoError::Utf8(e)
+    }
+}
+
+impl From<serde_json::Error> for ProtoError {
+    fn from(e: serde_json::Error) -> Self {
+        ProtoError::Json(e)
+    }
+}
+
+impl From<openssl::error::ErrorStack> for ProtoError {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        ProtoError::Crypto(e)
+    }
+}
+
+impl From<ProtoError> for ProtError {
+    fn from(e: ProtoError) -> Self {
+        ProtError::Any(e.to_string())
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use async_nbt::NbtCompound;
+
+use crate::packets::client;
+use crate::packets::client::ClientPackets;
+use crate::protocol_types::compound::{Hand, Slot};
+
+/**
+ * A stack of items along with its NBT data, like display names, lore and enchantments.
+ * Converts to and from the `Slot` sent over the network, an empty slot being `None`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ItemStack {
+    pub(crate) item_id: i32,
+    pub(crate) count: u8,
+    nbt: NbtCompound,
+}
+
+impl ItemStack {
+    pub(crate) fn new(item_id: i32, count: u8) -> Self {
+        Self { item_id, count, nbt: NbtCompound::new() }
+    }
+}
+
+impl From<ItemStack> for Slot {
+    fn from(stack: ItemStack) -> Self {
+        Slot {
+            present: true,
+            item_id: Some(stack.item_id.into()),
+            item_count: Some(stack.count),
+            nbt: Some(stack.nbt),
+        }
+    }
+}
+
+impl From<Option<ItemStack>> for Slot {
+    fn from(stack: Option<ItemStack>) -> Self {
+        stack.map(Slot::from).unwrap_or(Slot::empty())
+    }
+}
+
+impl From<Slot> for Option<ItemStack> {
+    fn from(slot: Slot) -> Self {
+        if !slot.present {
+            return None;
+        }
+        Some(ItemStack {
+            item_id: slot.item_id?.value,
+            count: slot.item_count?,
+            nbt: slot.nbt.unwrap_or_else(NbtCompound::new),
+        })
+    }
+}
+
+// crafting output and grid, armor, main inventory, hotbar and offhand
+pub(crate) const INVENTORY_SIZE: usize = 46;
+// window slot of the first hotbar slot
+const HOTBAR_START: usize = 36;
+const OFFHAND_SLOT: usize = 45;
+// number of slots of the hotbar and main inventory
+const INVENTORY_SLOTS: i32 = 36;
+// window id of the player's inventory, which is always open
+const PLAYER_WINDOW: u8 = 0;
+
+/**
+ * The player's own inventory, indexed by window slot. The state id is bumped on every change and sent
+ * along with slot updates, so the server can tell which state the client's inventory clicks refer to.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    pub(crate) state_id: i32,
+}
+
+impl Inventory {
+    pub(crate) fn new() -> Self {
+        Self { slots: vec![None; INVENTORY_SIZE], state_id: 0 }
+    }
+
+    // window slot of the given hotbar slot (0 to 8)
+    pub(crate) fn hotbar_slot(held_slot: u8) -> usize {
+        HOTBAR_START + held_slot as usize
+    }
+
+    // window slot of an index into the player's inventory, which starts with the hotbar and has no armor or crafting slots
+    pub(crate) fn inventory_slot(index: i32) -> Option<usize> {
+        match index {
+            0..=8 => Some(HOTBAR_START + index as usize),
+            9..INVENTORY_SLOTS => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    // window slot of the item held in the given hand
+    pub(crate) fn hand_slot(hand: Hand, held_slot: u8) -> usize {
+        match hand {
+            Hand::MainHand => Self::hotbar_slot(held_slot),
+            Hand::OffHand => OFFHAND_SLOT,
+        }
+    }
+
+    pub(crate) fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub(crate) fn set(&mut self, slot: usize, stack: Option<ItemStack>) {
+        self.slots[slot] = stack;
+        self.state_id = self.state_id.wrapping_add(1);
+    }
+
+    /**
+     * Puts the stack into the first empty slot of the hotbar or the rest of the player's inventory.
+     * Returns the slot it was put into, or None if the inventory is full.
+     */
+    pub(crate) fn add(&mut self, stack: ItemStack) -> Option<usize> {
+        let slot = (0..INVENTORY_SLOTS).filter_map(Self::inventory_slot).find(|slot| self.slots[*slot].is_none())?;
+        self.set(slot, Some(stack));
+        Some(slot)
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        self.slots.swap(a, b);
+        self.state_id = self.state_id.wrapping_add(1);
+    }
+
+    /**
+     * The packet updating the client's view of a single slot.
+     */
+    pub(crate) fn slot_update(&self, slot: usize) -> ClientPackets {
+        let stack = self.slots[slot].clone();
+        ClientPackets::SetContainerSlot(client::SetContainerSlot::new(PLAYER_WINDOW as i8, self.state_id.into(), slot as i16, stack.into()))
+    }
+
+    /**
+     * The packet replacing the client's view of the whole inventory with this one, with nothing on the cursor.
+     */
+    pub(crate) fn content(&self) -> ClientPackets {
+        let slots: Vec<Slot> = self.slots.iter().cloned().map(Slot::from).collect();
+        ClientPackets::SetContainerContent(client::SetContainerContent::new(
+            PLAYER_WINDOW,
+            self.state_id.into(),
+            slots.into(),
+            Slot::empty(),
+        ))
+    }
+}
+
+// properties of furnace windows, all in ticks
+pub(crate) const FURNACE_FUEL_LEFT: i16 = 0;
+pub(crate) const FURNACE_MAX_FUEL: i16 = 1;
+pub(crate) const FURNACE_COOK_PROGRESS: i16 = 2;
+pub(crate) const FURNACE_MAX_COOK_PROGRESS: i16 = 3;
+
+/**
+ * The properties of an open container window that aren't slots, like the progress bars of furnaces and
+ * brewing stands. Unset properties are 0.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct ContainerProperties {
+    window_id: u8,
+    values: HashMap<i16, i16>,
+}
+
+impl ContainerProperties {
+    pub(crate) fn new(window_id: u8) -> Self {
+        Self { window_id, values: HashMap::new() }
+    }
+
+    pub(crate) fn get(&self, property: i16) -> i16 {
+        self.values.get(&property).copied().unwrap_or(0)
+    }
+
+    /**
+     * Sets a property and returns the packet updating it for the player that has the window open.
+     */
+    pub(crate) fn set(&mut self, property: i16, value: i16) -> ClientPackets {
+        self.values.insert(property, value);
+        ClientPackets::SetContainerProperty(client::SetContainerProperty::new(self.window_id, property, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::connection::ConnectionInfo;
+    use crate::protocol_types::primitives::VarInt;
+    use crate::protocol_types::traits::{ReadProt, WriteProt, WriteProtPacket};
+
+    #[tokio::test]
+    async fn stack_round_trips_through_slot() {
+        let mut display = NbtCompound::new();
+        display.insert("Name", r#"{"text":"Excalibur"}"#);
+        let mut nbt = NbtCompound::new();
+        nbt.insert("display", display);
+        let sword = ItemStack { item_id: 842, count: 1, nbt };
+
+        let mut buf = vec![];
+        Slot::from(sword.clone()).write(&mut buf).await.unwrap();
+        let slot = Slot::read(&mut buf.as_slice()).await.unwrap();
+        let read: Option<ItemStack> = slot.into();
+        assert_eq!(read, Some(sword));
+    }
+
+    #[test]
+    fn add_fills_hotbar_first() {
+        let mut inventory = Inventory::new();
+        let stone = ItemStack::new(1, 64);
+        assert_eq!(inventory.add(stone.clone()), Some(Inventory::hotbar_slot(0)));
+        for _ in 1..9 {
+            inventory.add(stone.clone());
+        }
+        assert_eq!(inventory.add(stone.clone()), Some(9));
+        for _ in 10..INVENTORY_SLOTS {
+            inventory.add(stone.clone());
+        }
+        assert_eq!(inventory.add(stone), None);
+        assert_eq!(inventory.get(OFFHAND_SLOT), None);
+    }
+
+    #[tokio::test]
+    async fn content_with_one_stack() {
+        let mut inventory = Inventory::new();
+        let stone = ItemStack::new(1, 64);
+        inventory.set(Inventory::hotbar_slot(0), Some(stone.clone()));
+
+        let mut buf = vec![];
+        inventory.content().write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        let mut stack = vec![];
+        Slot::from(stone).write(&mut stack).await.unwrap();
+        let mut expected = vec![0x13, 0, 1, INVENTORY_SIZE as u8];
+        expected.extend(vec![0; HOTBAR_START]); // empty slots are just the present flag
+        expected.extend(stack);
+        expected.extend(vec![0; INVENTORY_SIZE - HOTBAR_START - 1]);
+        expected.push(0); // nothing carried
+        let mut prefixed = vec![];
+        VarInt::from(expected.len()).write(&mut prefixed).await.unwrap();
+        prefixed.extend(expected);
+        assert_eq!(buf, prefixed);
+    }
+
+    #[tokio::test]
+    async fn furnace_cook_progress() {
+        let mut furnace = ContainerProperties::new(3);
+        let packet = furnace.set(FURNACE_COOK_PROGRESS, 150);
+        assert_eq!(furnace.get(FURNACE_COOK_PROGRESS), 150);
+        assert_eq!(furnace.get(FURNACE_MAX_COOK_PROGRESS), 0);
+
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        assert_eq!(buf, vec![6, 0x14, 3, 0, 2, 0, 150]);
+    }
+
+    #[test]
+    fn empty_slot_has_no_stack() {
+        let stack: Option<ItemStack> = Slot::empty().into();
+        assert_eq!(stack, None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use crate::err::ProtError;
+
+// time a client has to answer a keep alive before it is disconnected
+pub(crate) const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/**
+ * Tracks the keep alives sent to a client and its responses. Only the newest keep alive may be answered,
+ * responses carrying the id of an earlier one are rejected.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct KeepAlive {
+    // ids sent since the last valid response, the last one being the most recent
+    outstanding: Vec<i64>,
+    sent_at: Option<Instant>,
+    answered_at: Option<Instant>,
+}
+
+impl KeepAlive {
+    pub(crate) fn new() -> Self {
+        Self { outstanding: vec![], sent_at: None, answered_at: None }
+    }
+
+    /**
+     * Generates the id of a keep alive sent at `now`, which doesn't collide with any keep alive that is still in
+     * flight. Only this id is accepted as a valid response from now on.
+     */
+    pub(crate) fn send(&mut self, now: Instant) -> i64 {
+        let id = loop {
+            let id = rand::random::<i64>();
+            if !self.outstanding.contains(&id) {
+                break id;
+            }
+        };
+        self.outstanding.push(id);
+        self.sent_at = Some(now);
+        id
+    }
+
+    /**
+     * Checks a response from the client received at `now`, returning the round trip time of the keep alive.
+     */
+    pub(crate) fn ack(&mut self, id: i64, now: Instant) -> Result<Duration, ProtError> {
+        let expected = self.outstanding.last().copied();
+        let (Some(expected), Some(sent)) = (expected, self.sent_at) else {
+            return Err(ProtError::KeepAliveIdMismatch(0, id));
+        };
+        if expected != id {
+            return Err(ProtError::KeepAliveIdMismatch(expected, id));
+        }
+        self.outstanding.clear();
+        self.answered_at = Some(now);
+        Ok(now.saturating_duration_since(sent))
+    }
+
+    /**
+     * Returns whether the last keep alive sent to the client hasn't been answered yet.
+     */
+    pub(crate) fn pending(&self) -> bool {
+        match (self.sent_at, self.answered_at) {
+            (Some(sent), Some(answered)) => answered < sent,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /**
+     * Returns whether the client failed to answer the last keep alive within KEEP_ALIVE_TIMEOUT.
+     */
+    pub(crate) fn timed_out(&self, now: Instant) -> bool {
+        match self.sent_at {
+            Some(sent) => self.pending() && now.saturating_duration_since(sent) > KEEP_ALIVE_TIMEOUT,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latest_id_accepted_with_round_trip_time() {
+        let mut keep_alive = KeepAlive::new();
+        let now = Instant::now();
+        let id = keep_alive.send(now);
+        assert!(keep_alive.pending());
+        assert_eq!(keep_alive.ack(id, now + Duration::from_millis(42)).unwrap(), Duration::from_millis(42));
+        assert!(!keep_alive.pending());
+    }
+
+    #[test]
+    fn stale_id_rejected() {
+        let mut keep_alive = KeepAlive::new();
+        let now = Instant::now();
+        let stale = keep_alive.send(now);
+        let latest = keep_alive.send(now);
+        assert_ne!(stale, latest);
+        assert!(matches!(keep_alive.ack(stale, now), Err(ProtError::KeepAliveIdMismatch(expected, _)) if expected == latest));
+        assert!(keep_alive.ack(latest, now).is_ok());
+        // answering twice is just as invalid
+        assert!(keep_alive.ack(latest, now).is_err());
+    }
+
+    #[test]
+    fn unsent_id_rejected() {
+        let mut keep_alive = KeepAlive::new();
+        assert!(keep_alive.ack(0, Instant::now()).is_err());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut keep_alive = KeepAlive::new();
+        let now = Instant::now();
+        assert!(!keep_alive.timed_out(now));
+
+        let id = keep_alive.send(now);
+        assert!(!keep_alive.timed_out(now + Duration::from_secs(10)));
+        assert!(keep_alive.timed_out(now + Duration::from_secs(31)));
+
+        keep_alive.ack(id, now + Duration::from_secs(1)).unwrap();
+        assert!(!keep_alive.timed_out(now + Duration::from_secs(31)));
+    }
+}
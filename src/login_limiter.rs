@@ -0,0 +1,97 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// login attempts allowed per address and window if LOGIN_ATTEMPTS_PER_MINUTE isn't set
+const DEFAULT_MAX_ATTEMPTS: usize = 10;
+const WINDOW: Duration = Duration::from_secs(60);
+
+/**
+ * Limits how often a single address may attempt to log in within a sliding time window,
+ * so a flood of logins from one host can't tie up the server.
+ */
+pub(crate) struct LoginLimiter {
+    max_attempts: usize,
+    window: Duration,
+    // times of the attempts within the window, oldest first
+    attempts: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl LoginLimiter {
+    pub(crate) fn new(max_attempts: usize, window: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /**
+     * Creates a limiter allowing the number of attempts per minute read from the LOGIN_ATTEMPTS_PER_MINUTE environment variable.
+     */
+    pub(crate) fn from_env() -> Self {
+        let max_attempts = env::var("LOGIN_ATTEMPTS_PER_MINUTE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        Self::new(max_attempts, WINDOW)
+    }
+
+    /**
+     * Records a login attempt from the address and returns whether it is within the limit.
+     * Rejected attempts count as well, so an address has to back off for a whole window.
+     * Only the newest attempts are kept, as many as are allowed, which is all the limit depends on.
+     */
+    pub(crate) fn attempt(&self, addr: IpAddr, now: Instant) -> bool {
+        let mut attempts = self.attempts.lock().unwrap();
+        // forget addresses whose attempts have all expired so the map doesn't grow forever
+        attempts.retain(|_, times| {
+            while times.front().is_some_and(|time| now.duration_since(*time) >= self.window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = attempts.entry(addr).or_default();
+        let allowed = times.len() < self.max_attempts;
+        times.push_back(now);
+        if times.len() > self.max_attempts {
+            times.pop_front();
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attempts_expire_after_window() {
+        let limiter = LoginLimiter::new(2, WINDOW);
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+        assert!(limiter.attempt(addr, start));
+        assert!(limiter.attempt(addr, start + Duration::from_secs(1)));
+        assert!(!limiter.attempt(addr, start + Duration::from_secs(2)));
+        assert!(limiter.attempt("10.0.0.2".parse().unwrap(), start + Duration::from_secs(2)));
+        // the first two attempts have expired, the rejected one still counts
+        assert!(limiter.attempt(addr, start + Duration::from_secs(61)));
+        assert!(!limiter.attempt(addr, start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn flood_keeps_attempts_bounded() {
+        let limiter = LoginLimiter::new(3, WINDOW);
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+        for i in 0..1000 {
+            assert_eq!(limiter.attempt(addr, start + Duration::from_millis(i)), i < 3);
+        }
+        assert_eq!(limiter.attempts.lock().unwrap()[&addr].len(), 3);
+        // the flood has to stop for a whole window before logins are allowed again
+        assert!(!limiter.attempt(addr, start + Duration::from_secs(60)));
+        assert!(limiter.attempt(addr, start + Duration::from_secs(61)));
+    }
+}
@@ -1,21 +1,35 @@
-use std::sync::RwLock;
-use crate::serve::start_server;
-use async_nbt::NbtCompound;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use crate::serve::{start_server, ConnectionActorHandle};
 use dotenv::dotenv;
-use log::info;
 use openssl::pkey::Private;
 use openssl::rsa::Rsa;
 use crate::chunk::world::World;
+use crate::connection::EntityIds;
+use crate::data::global_palette::GlobalPalette;
+use crate::data::items::ItemRegistry;
+use crate::data::ops::Ops;
+use crate::data::registry::Registry;
+use crate::login_limiter::LoginLimiter;
 
+mod buffer_pool;
 mod chunk;
+mod commands;
 mod connection;
 mod data;
 mod encryption;
 mod err;
+mod inventory;
+mod keep_alive;
+mod login_limiter;
 mod packets;
 mod protocol_types;
 mod protocol_util;
+mod proxy;
+mod query;
+mod rcon;
 mod serve;
+mod status;
 pub mod web;
 
 const MSG: &str = r#"
@@ -26,13 +40,8 @@ const MSG: &str = r#"
     },
     "players": {
         "max": 100,
-        "online": 5,
-        "sample": [
-            {
-                "name": "thinkofdeath",
-                "id": "4566e69f-c907-48ee-8d71-d7ba5aa00d20"
-            }
-        ]
+        "online": 0,
+        "sample": []
     },
     "description": {
         "text": "made in §mogaml§rRUST §c§l(/) (°,,,,°) (/)"
@@ -42,14 +51,31 @@ const MSG: &str = r#"
     "previewsChat": true
 }
 "#;
+// status response while the server hasn't finished loading its assets yet
+const STARTING_MSG: &str = r#"
+{
+    "version": {
+        "name": "1.20.2",
+        "protocol": 764
+    },
+    "players": {
+        "max": 100,
+        "online": 0
+    },
+    "description": {
+        "text": "Server is starting..."
+    }
+}
+"#;
+// defaults for RUSTCRAFT_PORT and RUSTCRAFT_ONLINE
 const PORT: u16 = 25565;
 const ONLINE: bool = true;
+const REGION_DIR: &str = "world/region";
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     env_logger::init();
-    info!("Starting up server on port {PORT}...");
     start_server().await;
 }
 
@@ -57,7 +83,72 @@ struct Assets {
     key: Rsa<Private>,
     pub_key: Vec<u8>,
     online: bool,
+    // trust player data forwarded by a BungeeCord proxy in the handshake instead of authenticating players
+    bungee_forwarding: bool,
     motd: String,
-    registry: NbtCompound,
-    world: RwLock<World>,
+    // sent to clients on the minecraft:brand channel, shown in the debug screen
+    brand: String,
+    // whether players outside of creative mode may fly, otherwise they are kicked
+    allow_flight: bool,
+    // names always listed in the player sample of the server list
+    status_sample: Vec<String>,
+    // only report the player count in the server list, without any names
+    hide_player_sample: bool,
+    // ping the client at the end of configuration and only finish it once the pong arrives
+    configuration_ping: bool,
+    registry: Registry,
+    global_palette: GlobalPalette,
+    items: ItemRegistry,
+    ops: Ops,
+    // longest chat message in characters relayed to other players, at most the 256 the protocol allows
+    max_chat_length: usize,
+    // an async lock so waiting for it doesn't block the runtime. Guards are only held to copy data out of
+    // or apply changes to the world, never while packets are serialized or written
+    world: tokio::sync::RwLock<World>,
+    // chunk columns kept in memory before those far from all players are unloaded, unlimited if not set
+    max_loaded_columns: Option<usize>,
+    // players in the Play state
+    online_players: AtomicUsize,
+    // stop advancing the world while no players are online
+    pause_when_empty: bool,
+    // set once the server has finished starting up and accepts logins
+    ready: AtomicBool,
+    // handles of all connection actors, used to broadcast packets to other players
+    connections: Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>,
+    entity_ids: EntityIds,
+    // login attempts per address, to turn away login floods
+    login_limiter: LoginLimiter,
+}
+
+#[cfg(test)]
+impl Assets {
+    // offline mode assets with a small flat world for handler tests
+    async fn for_test() -> Self {
+        let rsa = Rsa::generate(1024).unwrap();
+        Self {
+            pub_key: rsa.public_key_to_der().unwrap(),
+            key: rsa,
+            online: false,
+            bungee_forwarding: false,
+            motd: String::new(),
+            brand: "rustcraft".into(),
+            allow_flight: false,
+            status_sample: vec![],
+            hide_player_sample: false,
+            configuration_ping: false,
+            registry: data::registry::load_registry().await.unwrap(),
+            global_palette: data::global_palette::load_global_palette().await.unwrap(),
+            items: data::items::load_items().await.unwrap(),
+            ops: Ops::empty(),
+            max_chat_length: protocol_types::primitives::MAX_CHAT_CHARS,
+            world: tokio::sync::RwLock::new(World::new_grass()),
+            max_loaded_columns: None,
+            online_players: AtomicUsize::new(0),
+            pause_when_empty: false,
+            ready: AtomicBool::new(true),
+            connections: Arc::new(tokio::sync::RwLock::new(vec![])),
+            entity_ids: EntityIds::new(),
+            login_limiter: LoginLimiter::new(3, std::time::Duration::from_secs(60)),
+        }
+    }
 }
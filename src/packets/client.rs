@@ -1,7 +1,10 @@
+use crate::chunk::heightmaps;
+use crate::chunk::section::ChunkSection;
 use crate::connection::ConnectionInfo;
-use crate::protocol_types::compound::{BitSet, BlockEntity, Chat, Position, Recipe, TagGroup, GameEvent};
-use crate::protocol_types::primitives::SizedVec;
-use crate::protocol_types::primitives::VarInt;
+use crate::err::ProtoError;
+use crate::protocol_types::compound::{Angle, BitSet, BlockEntity, BlockOffset, BossBarAction, Chat, CommandNode, GameMode, Hand, Identifier, PlayerInfoEntry, Position, Recipe, Slot, SoundCategory, TagGroup, GameEvent};
+use crate::protocol_types::primitives::{RawBytes, SizedVec};
+use crate::protocol_types::primitives::{OptVarInt, VarInt, VarLong};
 use crate::protocol_types::traits::{ClientPacket, SizedProt, WriteProt, WriteProtPacket};
 use crate::{packet, packet_base};
 use async_nbt::NbtCompound;
@@ -13,9 +16,7 @@ use rustcraft_derive::WriteProtPacket;
 use std::sync::Arc;
 use std::sync::RwLock;
 use tokio::io::AsyncWrite;
-use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
-use std::env;
 
 packet!(
     StatusRes 0x00 {
@@ -37,6 +38,12 @@ packet!(
     }
 );
 
+packet!(
+    LoginDisconnect 0x00 {
+        reason: Chat,
+    }
+);
+
 packet!(
     LoginSuccess 0x02 {
         uuid: Uuid,
@@ -45,6 +52,13 @@ packet!(
     }
 );
 
+packet!(
+    ConfigurationPluginMessage 0x00 {
+        channel: Identifier,
+        data: RawBytes,
+    }
+);
+
 packet!(
     ConfigurationKeepAlive 0x03 {
         id: i64,
@@ -55,6 +69,18 @@ packet!(
     ConfigurationFinish 0x02 {}
 );
 
+packet!(
+    ConfigurationPing 0x04 {
+        id: i32,
+    }
+);
+
+packet!(
+    ConfigurationDisconnect 0x01 {
+        reason: Chat,
+    }
+);
+
 packet!(
     RegistryData 0x05 {
         data: NbtCompound
@@ -65,33 +91,252 @@ packet!(
     PlayLogin 0x29 {
         entity_id: i32,
         is_hardcore: bool,
-        dimension_names: SizedVec<String>,
+        dimension_names: SizedVec<Identifier>,
         max_players: VarInt,
         view_distance: VarInt,
         simulation_distance: VarInt,
         reduced_debug_info: bool,
         enable_respawn_screen: bool,
         do_limited_crafting: bool,
-        dimension_type: String,
-        dimension_name: String,
+        dimension_type: Identifier,
+        dimension_name: Identifier,
         hashed_seed: i64,
         game_mode: u8,
         previous_game_mode: u8,
         is_debug: bool,
         is_flat: bool,
         has_death_location: bool,
-        death_dimension_name: {has_death_location == true} && String,
+        death_dimension_name: {has_death_location == true} && Identifier,
         death_location: {has_death_location == true} && Position,
         portal_cooldown: VarInt,
     }
 );
 
+/**
+ * Named parameters of the PlayLogin packet, the defaults describe a survival overworld without a death location.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct PlayLoginParams {
+    pub(crate) entity_id: i32,
+    pub(crate) is_hardcore: bool,
+    pub(crate) dimension_names: Vec<Identifier>,
+    pub(crate) max_players: i32,
+    pub(crate) view_distance: i32,
+    pub(crate) simulation_distance: i32,
+    pub(crate) reduced_debug_info: bool,
+    pub(crate) enable_respawn_screen: bool,
+    pub(crate) do_limited_crafting: bool,
+    pub(crate) dimension_type: Identifier,
+    pub(crate) dimension_name: Identifier,
+    pub(crate) hashed_seed: i64,
+    pub(crate) game_mode: u8,
+    pub(crate) previous_game_mode: u8,
+    pub(crate) is_debug: bool,
+    pub(crate) is_flat: bool,
+    pub(crate) death_location: Option<(Identifier, Position)>,
+    pub(crate) portal_cooldown: i32,
+}
+
+impl Default for PlayLoginParams {
+    fn default() -> Self {
+        let overworld: Identifier = "minecraft:overworld".parse().unwrap();
+        Self {
+            entity_id: 0,
+            is_hardcore: false,
+            dimension_names: vec![overworld.clone()],
+            max_players: 20,
+            view_distance: 10,
+            simulation_distance: 10,
+            reduced_debug_info: false,
+            enable_respawn_screen: true,
+            do_limited_crafting: false,
+            dimension_type: overworld.clone(),
+            dimension_name: overworld,
+            hashed_seed: 0,
+            game_mode: 0,
+            previous_game_mode: 0,
+            is_debug: false,
+            is_flat: false,
+            death_location: None,
+            portal_cooldown: 0,
+        }
+    }
+}
+
+impl From<PlayLoginParams> for PlayLogin {
+    fn from(params: PlayLoginParams) -> Self {
+        let (death_dimension_name, death_location) = match params.death_location {
+            Some((dimension, location)) => (Some(dimension), Some(location)),
+            None => (None, None),
+        };
+        Self::new(
+            params.entity_id,
+            params.is_hardcore,
+            params.dimension_names.into(),
+            params.max_players.into(),
+            params.view_distance.into(),
+            params.simulation_distance.into(),
+            params.reduced_debug_info,
+            params.enable_respawn_screen,
+            params.do_limited_crafting,
+            params.dimension_type,
+            params.dimension_name,
+            params.hashed_seed,
+            params.game_mode,
+            params.previous_game_mode,
+            params.is_debug,
+            params.is_flat,
+            death_location.is_some(),
+            death_dimension_name,
+            death_location,
+            params.portal_cooldown.into(),
+        )
+    }
+}
+
+packet!(
+    Respawn 0x43 {
+        dimension_type: Identifier,
+        dimension_name: Identifier,
+        hashed_seed: i64,
+        game_mode: u8,
+        // -1 if there is none
+        previous_game_mode: i8,
+        is_debug: bool,
+        is_flat: bool,
+        has_death_location: bool,
+        death_dimension_name: {has_death_location == true} && Identifier,
+        death_location: {has_death_location == true} && Position,
+        portal_cooldown: VarInt,
+        // bit mask of the attributes and entity metadata the player keeps, nothing is kept after dying
+        data_kept: u8,
+    }
+);
+
+impl Respawn {
+    // respawns the player in the overworld after they died, in the given game mode
+    pub(crate) fn after_death(game_mode: GameMode) -> Self {
+        let overworld: Identifier = "minecraft:overworld".parse().unwrap();
+        Self::new(overworld.clone(), overworld, 0, game_mode.id(), -1, false, false, false, None, None, 0.into(), 0)
+    }
+}
+
+packet!(
+    SetHealth 0x59 {
+        // 0 or less means the player is dead
+        health: f32,
+        food: VarInt,
+        saturation: f32,
+    }
+);
+
 packet!(
     PlayKeepAlive 0x24 {
         id: i64,
     }
 );
 
+// entity type id of players in the entity type registry
+pub(crate) const PLAYER_ENTITY_TYPE: i32 = 122;
+
+packet!(
+    Commands 0x11 {
+        nodes: SizedVec<CommandNode>,
+        root_index: VarInt,
+    }
+);
+
+packet!(
+    SpawnPlayer 0x01 {
+        entity_id: VarInt,
+        uuid: Uuid,
+        typ: VarInt,
+        x: f64,
+        y: f64,
+        z: f64,
+        pitch: Angle,
+        yaw: Angle,
+        head_yaw: Angle,
+        data: VarInt,
+        velocity_x: i16,
+        velocity_y: i16,
+        velocity_z: i16,
+    }
+);
+
+// animations of EntityAnimation
+pub(crate) const ANIMATION_SWING_MAIN_ARM: u8 = 0;
+pub(crate) const ANIMATION_SWING_OFFHAND: u8 = 3;
+
+packet!(
+    EntityAnimation 0x03 {
+        entity_id: VarInt,
+        animation: u8,
+    }
+);
+
+// actions of the player info entries sent in PlayerInfoUpdate
+pub(crate) const PLAYER_INFO_ADD_AND_LIST: u8 = 0x01 | 0x08;
+pub(crate) const PLAYER_INFO_DISPLAY_NAME: u8 = 0x20;
+
+packet!(
+    PlayerInfoUpdate 0x3c {
+        actions: u8,
+        players: SizedVec<PlayerInfoEntry>,
+    }
+);
+
+packet!(
+    PlayerInfoRemove 0x3b {
+        uuids: SizedVec<Uuid>,
+    }
+);
+
+packet!(
+    RemoveEntities 0x40 {
+        entity_ids: SizedVec<VarInt>,
+    }
+);
+
+packet!(
+    UpdateEntityPositionAndRotation 0x2d {
+        entity_id: VarInt,
+        delta_x: i16,
+        delta_y: i16,
+        delta_z: i16,
+        yaw: Angle,
+        pitch: Angle,
+        on_ground: bool,
+    }
+);
+
+packet!(
+    HeadRotation 0x44 {
+        entity_id: VarInt,
+        head_yaw: Angle,
+    }
+);
+
+packet!(
+    PlayPluginMessage 0x18 {
+        channel: Identifier,
+        data: RawBytes,
+    }
+);
+
+packet!(
+    UpdateTime 0x60 {
+        world_age: i64,
+        time_of_day: i64,
+    }
+);
+
+packet!(
+    PlayDisconnect 0x1b {
+        reason: Chat,
+    }
+);
+
 packet!(
     SetHeldItem 0x4f {
         slot: u8,
@@ -138,6 +383,36 @@ packet!(
     }
 );
 
+impl ChunkDataAndUpdateLight {
+    /**
+     * Builds the packet for the column at the given chunk coordinates.
+     * Lighting isn't simulated yet, so every section is sent with full sky light and no block light.
+     */
+    pub(crate) async fn from_column(x: i32, z: i32, column: &[ChunkSection]) -> Result<Self, ProtoError> {
+        let mut data = vec![];
+        for section in column {
+            section.write(&mut data).await?;
+        }
+        // light sections include one below and one above the column
+        let light_sections = column.len() + 2;
+        let all_sections = (1i64 << light_sections) - 1;
+        let sky_lights: Vec<SizedVec<u8>> = (0..light_sections).map(|_| vec![0xff; 2048].into()).collect();
+        Ok(Self::new(
+            x,
+            z,
+            heightmaps(column),
+            data.into(),
+            vec![].into(),
+            BitSet(vec![all_sections].into()),
+            BitSet(vec![].into()),
+            BitSet(vec![].into()),
+            BitSet(vec![all_sections].into()),
+            sky_lights.into(),
+            vec![].into(),
+        ))
+    }
+}
+
 packet!(
     SetCenterChunk 0x50 {
         x: VarInt,
@@ -145,6 +420,49 @@ packet!(
     }
 );
 
+packet!(
+    UnloadChunk 0x1f {
+        z: i32,
+        x: i32,
+    }
+);
+
+packet!(
+    SetBorderCenter 0x49 {
+        x: f64,
+        z: f64,
+    }
+);
+
+packet!(
+    SetBorderLerpSize 0x4a {
+        old_diameter: f64,
+        new_diameter: f64,
+        // real time in milliseconds
+        speed: VarLong,
+    }
+);
+
+packet!(
+    SetBorderSize 0x4b {
+        diameter: f64,
+    }
+);
+
+packet!(
+    SetBorderWarningDelay 0x4c {
+        // seconds
+        warning_time: VarInt,
+    }
+);
+
+packet!(
+    SetBorderWarningDistance 0x4d {
+        // blocks
+        warning_blocks: VarInt,
+    }
+);
+
 packet!(
     SetDefaultSpawnPosition 0x52 {
         location: Position,
@@ -162,6 +480,47 @@ packet!(
     }
 );
 
+impl DisguisedChatMessage {
+    // Chat message sent by the given player, using their display name as the sender
+    pub(crate) fn from_player(connection: &ConnectionInfo, message: Chat, chat_type: i32) -> Self {
+        Self::new(message, chat_type.into(), connection.display_name(), false, None)
+    }
+}
+
+packet!(
+    SystemChatMessage 0x67 {
+        content: Chat,
+        // shown above the hotbar instead of in the chat
+        overlay: bool,
+    }
+);
+
+packet!(
+    SetTitleText 0x61 {
+        text: Chat,
+    }
+);
+
+packet!(
+    SetSubtitleText 0x5f {
+        text: Chat,
+    }
+);
+
+packet!(
+    SetActionBarText 0x48 {
+        text: Chat,
+    }
+);
+
+packet!(
+    SetTitleAnimationTimes 0x62 {
+        // all in ticks
+        fade_in: i32,
+        stay: i32,
+        fade_out: i32,
+    }
+);
 
 packet!(
     SendGameEvent 0x20 {
@@ -169,6 +528,47 @@ packet!(
     }
 );
 
+packet!(
+    SoundEffect 0x64 {
+        // id in the sound event registry, None for sounds given by name
+        sound_id: OptVarInt,
+        sound_name: {sound_id.0.is_none()} && Identifier,
+        // named sounds can be heard within a fixed range instead of one depending on their volume
+        has_fixed_range: {sound_id.0.is_none()} && bool,
+        fixed_range: {has_fixed_range == Some(true)} && f32,
+        category: SoundCategory,
+        // fixed-point coordinates with 3 fraction bits
+        x: i32,
+        y: i32,
+        z: i32,
+        volume: f32,
+        pitch: f32,
+        // seed of the random variation of the sound
+        seed: i64,
+    }
+);
+
+packet!(
+    Explosion 0x1e {
+        x: f64,
+        y: f64,
+        z: f64,
+        strength: f32,
+        // blocks destroyed by the explosion, relative to its center
+        records: SizedVec<BlockOffset>,
+        // added to the velocity of the receiving player
+        motion_x: f32,
+        motion_y: f32,
+        motion_z: f32,
+    }
+);
+
+packet!(
+    AcknowledgeBlockChange 0x05 {
+        sequence: VarInt,
+    }
+);
+
 packet!(
     BlockUpdate 0x09 {
         position: Position,
@@ -176,17 +576,134 @@ packet!(
     }
 );
 
+packet!(
+    BossBar 0x0a {
+        // identifies the boss bar in later updates
+        id: Uuid,
+        action: BossBarAction,
+    }
+);
+
+// block entity type of command blocks in the block entity type registry
+pub(crate) const BLOCK_ENTITY_COMMAND_BLOCK: i32 = 22;
+
+packet!(
+    BlockEntityData 0x07 {
+        location: Position,
+        typ: VarInt,
+        nbt: NbtCompound,
+    }
+);
+
+packet!(
+    SetContainerContent 0x13 {
+        window_id: u8,
+        state_id: VarInt,
+        slots: SizedVec<Slot>,
+        // item held by the cursor
+        carried: Slot,
+    }
+);
+
+packet!(
+    SetContainerProperty 0x14 {
+        window_id: u8,
+        // meaning depends on the window type, e.g. the cook progress of a furnace
+        property: i16,
+        value: i16,
+    }
+);
+
+packet!(
+    SetContainerSlot 0x15 {
+        window_id: i8,
+        state_id: VarInt,
+        slot: i16,
+        slot_data: Slot,
+    }
+);
+
+// bits of the player abilities flags
+pub(crate) const ABILITY_INVULNERABLE: u8 = 0x01;
+pub(crate) const ABILITY_FLYING: u8 = 0x02;
+pub(crate) const ABILITY_ALLOW_FLYING: u8 = 0x04;
+// blocks break instantly
+pub(crate) const ABILITY_CREATIVE: u8 = 0x08;
+
+packet!(
+    PlayerAbilities 0x36 {
+        flags: u8,
+        fly_speed: f32,
+        // field of view modifier, changes with the walking speed
+        fov_modifier: f32,
+    }
+);
+
+impl PlayerAbilities {
+    /**
+     * The abilities of a player in the given game mode with the vanilla default speeds.
+     */
+    pub(crate) fn for_game_mode(game_mode: GameMode, flying: bool) -> Self {
+        let flags = match game_mode {
+            GameMode::Survival | GameMode::Adventure => 0,
+            GameMode::Creative => ABILITY_INVULNERABLE | ABILITY_ALLOW_FLYING | ABILITY_CREATIVE,
+            // spectators are always flying
+            GameMode::Spectator => ABILITY_INVULNERABLE | ABILITY_ALLOW_FLYING | ABILITY_FLYING,
+        };
+        let flying = if flying && flags & ABILITY_ALLOW_FLYING != 0 { ABILITY_FLYING } else { 0 };
+        Self::new(flags | flying, 0.05, 0.1)
+    }
+}
+
+packet!(
+    OpenBook 0x30 {
+        hand: Hand,
+    }
+);
+
+// the transfer packets only exist from 1.20.5 on, so their ids are those of the newer protocols
+packet!(
+    ConfigurationTransfer 0x0b {
+        host: String,
+        port: VarInt,
+    }
+);
+
+packet!(
+    PlayTransfer 0x73 {
+        host: String,
+        port: VarInt,
+    }
+);
+
 #[derive(WriteProtPacket, Clone)]
 pub(crate) enum ClientPackets {
     StatusRes(StatusRes),
     PingRes(PingRes),
     EncryptionReq(EncryptionReq),
+    LoginDisconnect(LoginDisconnect),
     LoginSuccess(LoginSuccess),
+    ConfigurationPluginMessage(ConfigurationPluginMessage),
     ConfigurationKeepAlive(ConfigurationKeepAlive),
     ConfigurationFinish(ConfigurationFinish),
+    ConfigurationPing(ConfigurationPing),
+    ConfigurationDisconnect(ConfigurationDisconnect),
     RegistryData(RegistryData),
     PlayLogin(PlayLogin),
     PlayKeepAlive(PlayKeepAlive),
+    Respawn(Respawn),
+    SetHealth(SetHealth),
+    PlayPluginMessage(PlayPluginMessage),
+    SpawnPlayer(SpawnPlayer),
+    EntityAnimation(EntityAnimation),
+    PlayerInfoUpdate(PlayerInfoUpdate),
+    PlayerInfoRemove(PlayerInfoRemove),
+    RemoveEntities(RemoveEntities),
+    UpdateTime(UpdateTime),
+    Commands(Commands),
+    UpdateEntityPositionAndRotation(UpdateEntityPositionAndRotation),
+    HeadRotation(HeadRotation),
+    PlayDisconnect(PlayDisconnect),
     SetHeldItem(SetHeldItem),
     UpdateRecipes(UpdateRecipes),
     UpdateTags(UpdateTags),
@@ -194,6 +711,226 @@ pub(crate) enum ClientPackets {
     ChunkDataAndUpdateLight(ChunkDataAndUpdateLight),
     SetDefaultSpawnPosition(SetDefaultSpawnPosition),
     SetCenterChunk(SetCenterChunk),
+    SetBorderCenter(SetBorderCenter),
+    SetBorderLerpSize(SetBorderLerpSize),
+    SetBorderSize(SetBorderSize),
+    SetBorderWarningDelay(SetBorderWarningDelay),
+    SetBorderWarningDistance(SetBorderWarningDistance),
+    SendGameEvent(SendGameEvent),
+    SoundEffect(SoundEffect),
+    UnloadChunk(UnloadChunk),
     DisguisedChatMessage(DisguisedChatMessage),
+    SystemChatMessage(SystemChatMessage),
+    SetTitleText(SetTitleText),
+    SetSubtitleText(SetSubtitleText),
+    SetActionBarText(SetActionBarText),
+    SetTitleAnimationTimes(SetTitleAnimationTimes),
     BlockUpdate(BlockUpdate),
+    Explosion(Explosion),
+    BlockEntityData(BlockEntityData),
+    BossBar(BossBar),
+    AcknowledgeBlockChange(AcknowledgeBlockChange),
+    SetContainerContent(SetContainerContent),
+    SetContainerProperty(SetContainerProperty),
+    SetContainerSlot(SetContainerSlot),
+    PlayerAbilities(PlayerAbilities),
+    OpenBook(OpenBook),
+    ConfigurationTransfer(ConfigurationTransfer),
+    PlayTransfer(PlayTransfer),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_nbt::{NbtList, NbtTag};
+    use crate::connection::ConnectionState;
+    use rustcraft_derive::Packet;
+
+    // SetCenterChunk declared through the derive instead of the packet! macro
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x50, state = Play)]
+    struct DerivedSetCenterChunk {
+        x: VarInt,
+        y: VarInt,
+    }
+
+    #[tokio::test]
+    async fn derived_packet_matches_macro() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let derived = DerivedSetCenterChunk::new(VarInt::from(-3), VarInt::from(300));
+        let declared = SetCenterChunk::new(VarInt::from(-3), VarInt::from(300));
+        assert_eq!(DerivedSetCenterChunk::id(), SetCenterChunk::id());
+        assert!(matches!(DerivedSetCenterChunk::STATE, ConnectionState::Play));
+        assert_eq!(derived.prot_size(), declared.prot_size());
+        assert_eq!(derived.to_string(), "DerivedSetCenterChunk");
+
+        let mut derived_bytes = vec![];
+        derived.write(&mut derived_bytes, connection.clone()).await.unwrap();
+        let mut declared_bytes = vec![];
+        declared.write(&mut declared_bytes, connection).await.unwrap();
+        assert_eq!(derived_bytes, declared_bytes);
+    }
+
+    #[tokio::test]
+    async fn creative_abilities_allow_flying() {
+        let creative = PlayerAbilities::for_game_mode(GameMode::Creative, false);
+        assert_eq!(creative.flags, ABILITY_INVULNERABLE | ABILITY_ALLOW_FLYING | ABILITY_CREATIVE);
+        assert_eq!(PlayerAbilities::for_game_mode(GameMode::Creative, true).flags & ABILITY_FLYING, ABILITY_FLYING);
+        assert_eq!(PlayerAbilities::for_game_mode(GameMode::Survival, true).flags, 0);
+        assert_eq!(PlayerAbilities::for_game_mode(GameMode::Spectator, false).flags & ABILITY_FLYING, ABILITY_FLYING);
+
+        let mut buf = vec![];
+        ClientPackets::PlayerAbilities(creative).write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        let mut expected = vec![10, 0x36, 0x0d];
+        expected.extend(0.05f32.to_be_bytes());
+        expected.extend(0.1f32.to_be_bytes());
+        assert_eq!(buf, expected);
+    }
+
+    #[tokio::test]
+    async fn title_packets() {
+        let text = Chat::new_text("Welcome".into());
+        let mut text_bytes = vec![];
+        text.write(&mut text_bytes).await.unwrap();
+        let packets = [
+            (ClientPackets::SetTitleText(SetTitleText::new(text.clone())), 0x61),
+            (ClientPackets::SetSubtitleText(SetSubtitleText::new(text.clone())), 0x5f),
+            (ClientPackets::SetActionBarText(SetActionBarText::new(text)), 0x48),
+        ];
+        for (packet, id) in packets {
+            let mut buf = vec![];
+            packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+            let mut expected = vec![text_bytes.len() as u8 + 1, id];
+            expected.extend(&text_bytes);
+            assert_eq!(buf, expected);
+        }
+
+        let times = ClientPackets::SetTitleAnimationTimes(SetTitleAnimationTimes::new(10, 70, 20));
+        let mut buf = vec![];
+        times.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        assert_eq!(buf, vec![13, 0x62, 0, 0, 0, 10, 0, 0, 0, 70, 0, 0, 0, 20]);
+    }
+
+    #[tokio::test]
+    async fn sign_block_entity_data() {
+        let mut messages = NbtList::new();
+        for line in ["Hello", "", "", ""] {
+            messages.push(Chat::new_text(line.into()).json().clone());
+        }
+        let mut front_text = NbtCompound::new();
+        front_text.insert("messages", messages);
+        front_text.insert("color", "black");
+        front_text.insert("has_glowing_text", 0i8);
+        let mut sign = NbtCompound::new();
+        sign.insert("front_text", front_text);
+        sign.insert("is_waxed", 0i8);
+
+        let location = Position::new(3, 70, -9);
+        // signs are type 7 in the block entity type registry
+        let packet = ClientPackets::BlockEntityData(BlockEntityData::new(location, 7.into(), sign.clone()));
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+
+        let mut expected = vec![0x07];
+        location.write(&mut expected).await.unwrap();
+        expected.push(7);
+        sign.write(&mut expected).await.unwrap();
+        let mut prefixed = vec![];
+        VarInt::from(expected.len()).write(&mut prefixed).await.unwrap();
+        prefixed.extend(expected);
+        assert_eq!(buf, prefixed);
+        assert!(String::from_utf8_lossy(&buf).contains(r#"{"text":"Hello"}"#));
+    }
+
+    #[test]
+    fn chat_sender_uses_display_name() {
+        let mut connection = ConnectionInfo::new();
+        connection.username = "steve".into();
+        let message = DisguisedChatMessage::from_player(&connection, Chat::new_text("hi".into()), 0);
+        assert_eq!(message.sender_name, Chat::new_text("steve".into()));
+
+        connection.display_name = Some(Chat::new_text("Steve the Great".into()));
+        let message = DisguisedChatMessage::from_player(&connection, Chat::new_text("hi".into()), 0);
+        assert_eq!(message.sender_name, Chat::new_text("Steve the Great".into()));
+        assert_eq!(connection.username, "steve");
+    }
+
+    #[tokio::test]
+    async fn play_login_params_match_positional() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let positional = ClientPackets::PlayLogin(PlayLogin::new(
+            7,
+            false,
+            vec!["world".parse().unwrap()].into(),
+            VarInt::from(2),
+            VarInt::from(5),
+            VarInt::from(3),
+            false,
+            false,
+            false,
+            "minecraft:overworld".parse().unwrap(),
+            "minecraft:overworld".parse().unwrap(),
+            0, 0, 0, false, false, true,
+            Some("minecraft:overworld".parse().unwrap()),
+            Some(Position::new(1, 64, -1)),
+            0.into(),
+        ));
+        let named = ClientPackets::PlayLogin(PlayLogin::from(PlayLoginParams {
+            entity_id: 7,
+            dimension_names: vec!["world".parse().unwrap()],
+            max_players: 2,
+            view_distance: 5,
+            simulation_distance: 3,
+            enable_respawn_screen: false,
+            death_location: Some(("minecraft:overworld".parse().unwrap(), Position::new(1, 64, -1))),
+            ..Default::default()
+        }));
+        let mut positional_bytes = vec![];
+        positional.write(&mut positional_bytes, connection.clone()).await.unwrap();
+        let mut named_bytes = vec![];
+        named.write(&mut named_bytes, connection).await.unwrap();
+        assert_eq!(positional_bytes, named_bytes);
+    }
+
+    #[tokio::test]
+    async fn chunk_packet_from_column() {
+        let mut column: Vec<ChunkSection> = (0..4).map(|_| ChunkSection::new()).collect();
+        column[0].fill(1);
+        column[1].set_block(Position::new(2, 5, 3), 1).unwrap();
+
+        let packet = ChunkDataAndUpdateLight::from_column(1, -2, &column).await.unwrap();
+        let mut expected = vec![];
+        for section in &column {
+            section.write(&mut expected).await.unwrap();
+        }
+        assert_eq!(packet.data.vec, expected);
+        assert_eq!((packet.chunk_x, packet.chunk_z), (1, -2));
+        assert_eq!(packet.sky_lights.vec.len(), column.len() + 2);
+        assert!(packet.sky_light_mask.bit(column.len() + 1));
+        assert!(packet.empty_block_light_mask.bit(0));
+
+        // the heightmap entry of the tallest block is at index z * 16 + x, 7 entries of 9 bits per long
+        let Some(NbtTag::LongArray(heights)) = packet.heightmaps.inner().get("MOTION_BLOCKING") else {
+            panic!("Expected a MOTION_BLOCKING heightmap");
+        };
+        let entry = |index: usize| (heights[index / 7] as u64 >> (index % 7 * 9)) & 0x1ff;
+        assert_eq!(entry(3 * 16 + 2), 16 + 5 + 1);
+        assert_eq!(entry(0), 16);
+    }
+
+    #[tokio::test]
+    async fn sound_effect_bytes() {
+        let packet = ClientPackets::SoundEffect(SoundEffect::new(OptVarInt(Some(5)), None, None, None, SoundCategory::Block, 4, 64 * 8 + 4, -4, 1.0, 0.5, 7));
+        let mut buf = vec![];
+        packet.write(&mut buf, Arc::new(RwLock::new(ConnectionInfo::new()))).await.unwrap();
+        // the registry id is sent plus one, 0 would mean a sound given by name
+        let mut expected = vec![31, 0x64, 6, 4];
+        for coordinate in [4i32, 64 * 8 + 4, -4] {
+            expected.extend(coordinate.to_be_bytes());
+        }
+        expected.extend(1f32.to_be_bytes());
+        expected.extend(0.5f32.to_be_bytes());
+        expected.extend(7i64.to_be_bytes());
+        assert_eq!(buf, expected);
+    }
 }
@@ -1,115 +1,285 @@
-use std::io::Cursor;
 use std::sync::{Arc, RwLock};
 
 use tokio::io::{AsyncRead, AsyncReadExt};
-use tokio::net::tcp::OwnedReadHalf;
+use log::debug;
 
 use crate::connection::{ConnectionInfo, ConnectionState};
+use crate::err::ProtError;
 use crate::packets::server;
 use crate::protocol_types::primitives::VarInt;
-use crate::protocol_types::traits::{ReadProt, ReadProtPacket, ServerPacket, SizedProt};
-use crate::protocol_util::skip;
+use crate::protocol_types::traits::{ReadProt, ReadProtPacket, ServerPacket};
+use crate::protocol_util::{show, skip, PacketLogFormat};
+
+// serverbound packet ids accepted in each connection state, anything else disconnects the client
+const HANDSHAKE_PACKETS: &[i32] = &[0x00];
+const STATUS_PACKETS: &[i32] = &[0x00, 0x01];
+const LOGIN_PACKETS: &[i32] = &[0x00, 0x01, 0x03];
+const CONFIGURATION_PACKETS: &[i32] = &[0x00, 0x01, 0x02, 0x03, 0x04];
+const PLAY_PACKETS: &[i32] = &[
+    0x00, 0x04, 0x05, 0x06, 0x08, 0x0f, 0x14, 0x16, 0x17, 0x18, 0x1c, 0x1f, 0x20, 0x21, 0x2b, 0x2c, 0x2d, 0x2e, 0x32, 0x34, 0x35,
+];
+
+/**
+ * The ids of the packets a client may send in the given state. Packets outside of this list are rejected
+ * as potential exploits rather than skipped, so malformed or out of order packets can't reach the handlers.
+ */
+pub(crate) fn allowed_packets(state: ConnectionState) -> &'static [i32] {
+    match state {
+        ConnectionState::Handshake => HANDSHAKE_PACKETS,
+        ConnectionState::Status => STATUS_PACKETS,
+        ConnectionState::Login => LOGIN_PACKETS,
+        ConnectionState::Configuration => CONFIGURATION_PACKETS,
+        ConnectionState::Play => PLAY_PACKETS,
+    }
+}
 
 pub(crate) async fn parse_packet(
-    stream: &mut OwnedReadHalf,
+    stream: &mut (impl AsyncRead + Unpin + Send),
     connection: Arc<RwLock<ConnectionInfo>>,
-) -> Result<Box<dyn ServerPacket>, String> {
-    let (mut read_from, length): (Box<(dyn AsyncRead + Unpin + Send)>, Option<VarInt>) =
-        if connection.read().unwrap().decrypter.is_some() {
-            let length = VarInt::read_decrypt(stream, connection.clone()).await?;
-            let mut packet = vec![0u8; length.value as usize];
-            stream.read_exact(&mut packet).await.or_else(|err| {
-                Err(format!(
-                    "Trying to read encrypted packet with size {length}: {err}"
-                ))
-            })?;
-            let mut decrypted = vec![0u8; length.value as usize];
-            connection
-                .write()
-                .unwrap()
-                .decrypter
-                .as_mut()
-                .unwrap()
-                .update(&packet, &mut decrypted)
-                .unwrap();
-            (Box::new(Cursor::new(decrypted)), Some(length))
-        } else {
-            (Box::new(stream), None)
-        };
-    let length = if let Some(length) = length {
-        length
+) -> Result<Box<dyn ServerPacket>, ProtError> {
+    // the whole frame is read up front so bytes at its end that the packet doesn't model can be skipped
+    let encrypted = connection.read().unwrap().decrypter.is_some();
+    let length = if encrypted {
+        VarInt::read_decrypt(stream, connection.clone()).await?
     } else {
-        VarInt::read(&mut read_from).await?
+        VarInt::read(stream).await?
     };
+    if length.value < 0 {
+        return Err(ProtError::Any(format!("Invalid packet length {length}")));
+    }
+    let mut frame = vec![0u8; length.value as usize];
+    stream.read_exact(&mut frame).await.or_else(|err| {
+        Err(ProtError::Any(format!(
+            "Trying to read packet with size {length}: {err}"
+        )))
+    })?;
+    if encrypted {
+        let mut decrypted = vec![0u8; frame.len()];
+        connection
+            .write()
+            .unwrap()
+            .decrypter
+            .as_mut()
+            .unwrap()
+            .update(&frame, &mut decrypted)
+            .unwrap();
+        frame = decrypted;
+    }
+    if PacketLogFormat::from_env() == Some(PacketLogFormat::Hex) {
+        debug!("Inbound frame: {}", show(&frame));
+    }
+    read_packet(&mut frame.as_slice(), length, connection).await
+}
 
-    let id = VarInt::read(&mut read_from).await?;
+/**
+ * Reads the id and body of a packet frame of the given length. Bytes at the end of the body that the
+ * packet doesn't model, e.g. fields added by newer clients, are skipped so the stream stays aligned.
+ */
+async fn read_packet(
+    read_from: &mut (impl AsyncRead + Unpin + Send),
+    length: VarInt,
+    connection: Arc<RwLock<ConnectionInfo>>,
+) -> Result<Box<dyn ServerPacket>, ProtError> {
+    let (id, id_len) = VarInt::read_with_len(read_from).await?;
+    if (length.value as usize) < id_len {
+        return Err(ProtError::Any(format!("Packet length {length} is shorter than its id")));
+    }
+    let mut body = read_from.take((length.value as usize - id_len) as u64);
     let state = connection.read().unwrap().state().clone();
+    if !allowed_packets(state).contains(&id.value) {
+        return Err(ProtError::PacketNotAllowed(id.value, state));
+    }
     let packet: Box<dyn ServerPacket> = match (id.value, state) {
         (0x00, ConnectionState::Handshake) => {
-            Box::new(server::Handshake::read(&mut read_from).await?)
+            Box::new(server::Handshake::read(&mut body).await?)
         }
 
-        (0x00, ConnectionState::Status) => Box::new(server::StatusReq::read(&mut read_from).await?),
-        (0x01, ConnectionState::Status) => Box::new(server::PingReq::read(&mut read_from).await?),
+        (0x00, ConnectionState::Status) => Box::new(server::StatusReq::read(&mut body).await?),
+        (0x01, ConnectionState::Status) => Box::new(server::PingReq::read(&mut body).await?),
 
         (0x00, ConnectionState::Login) => {
-            let p = Box::new(server::LoginStart::read(&mut read_from).await?);
-            skip(&mut read_from, 16).await?;
+            let p = Box::new(server::LoginStart::read(&mut body).await?);
+            skip(&mut body, 16).await?;
             p
         }
         (0x01, ConnectionState::Login) => {
-            Box::new(server::EncryptionResponse::read(&mut read_from).await?)
+            Box::new(server::EncryptionResponse::read(&mut body).await?)
         }
-        (0x03, ConnectionState::Login) => Box::new(server::LoginAck::read(&mut read_from).await?),
+        (0x03, ConnectionState::Login) => Box::new(server::LoginAck::read(&mut body).await?),
 
         (0x00, ConnectionState::Configuration) => {
-            Box::new(server::ClientInfo::read(&mut read_from).await?)
+            Box::new(server::ClientInfo::read(&mut body).await?)
+        }
+        (0x01, ConnectionState::Configuration) | (0x0f, ConnectionState::Play) => {
+            // the payload isn't length-prefixed and takes up the rest of the packet
+            Box::new(server::ServerboundPluginMessage::read(&mut body).await?)
         }
         (0x02, ConnectionState::Configuration) => {
-            Box::new(server::ConfigurationFinish::read(&mut read_from).await?)
+            Box::new(server::ConfigurationFinish::read(&mut body).await?)
         }
         (0x03, ConnectionState::Configuration) => {
-            Box::new(server::ConfigurationKeepAlive::read(&mut read_from).await?)
+            Box::new(server::ConfigurationKeepAlive::read(&mut body).await?)
+        }
+        (0x04, ConnectionState::Configuration) => {
+            Box::new(server::ConfigurationPong::read(&mut body).await?)
         }
 
         (0x14, ConnectionState::Play) => {
-            Box::new(server::PlayKeepAlive::read(&mut read_from).await?)
+            Box::new(server::PlayKeepAlive::read(&mut body).await?)
+        }
+        (0x04, ConnectionState::Play) => {
+            Box::new(server::ChatCommand::read(&mut body).await?)
+        }
+        (0x05, ConnectionState::Play) => {
+            Box::new(server::ChatMessage::read(&mut body).await?)
         }
         (0x06, ConnectionState::Play) => {
-            Box::new(server::PlayerSession::read(&mut read_from).await?)
+            Box::new(server::PlayerSession::read(&mut body).await?)
+        }
+        (0x08, ConnectionState::Play) => {
+            Box::new(server::ClientStatus::read(&mut body).await?)
         }
         (0x16, ConnectionState::Play) => {
-            Box::new(server::SetPlayerPosition::read(&mut read_from).await?)
+            Box::new(server::SetPlayerPosition::read(&mut body).await?)
         }
         (0x17, ConnectionState::Play) => {
-            Box::new(server::SetPlayerPositionAndRotation::read(&mut read_from).await?)
+            Box::new(server::SetPlayerPositionAndRotation::read(&mut body).await?)
         }
         (0x18, ConnectionState::Play) => {
-            Box::new(server::SetPlayerRotation::read(&mut read_from).await?)
+            Box::new(server::SetPlayerRotation::read(&mut body).await?)
+        }
+        (0x1c, ConnectionState::Play) => {
+            Box::new(server::PickItem::read(&mut body).await?)
+        }
+        (0x2c, ConnectionState::Play) => {
+            Box::new(server::ProgramCommandBlock::read(&mut body).await?)
+        }
+        (0x2d, ConnectionState::Play) => {
+            Box::new(server::ProgramCommandBlockMinecart::read(&mut body).await?)
+        }
+        (0x2e, ConnectionState::Play) => {
+            Box::new(server::SetCreativeModeSlot::read(&mut body).await?)
+        }
+        (0x2b, ConnectionState::Play) => {
+            Box::new(server::SetHeldItem::read(&mut body).await?)
+        }
+        (0x1f, ConnectionState::Play) => {
+            Box::new(server::PlayerAbilities::read(&mut body).await?)
         }
         (0x20, ConnectionState::Play) => {
-            Box::new(server::PlayerAction::read(&mut read_from).await?)
+            Box::new(server::PlayerAction::read(&mut body).await?)
         }
         (0x21, ConnectionState::Play) => {
-            Box::new(server::PlayerCommand::read(&mut read_from).await?)
+            Box::new(server::PlayerCommand::read(&mut body).await?)
+        }
+        (0x32, ConnectionState::Play) => {
+            Box::new(server::SwingArm::read(&mut body).await?)
+        }
+        (0x34, ConnectionState::Play) => {
+            Box::new(server::UseItemOn::read(&mut body).await?)
+        }
+        (0x35, ConnectionState::Play) => {
+            Box::new(server::UseItem::read(&mut body).await?)
         }
         (0x00, ConnectionState::Play) => {
-            Box::new(server::ConfirmTeleportation::read(&mut read_from).await?)
+            Box::new(server::ConfirmTeleportation::read(&mut body).await?)
         }
         _ => {
-            // eat remainder of packet
-            skip(
-                &mut read_from,
-                (length.value - id.prot_size() as i32) as u64,
-            )
-            .await?;
-            return Err(format!(
+            // allowed but not parsed, eat remainder of packet
+            let remaining = body.limit();
+            skip(&mut body, remaining).await?;
+            return Err(ProtError::Any(format!(
                 "Unrecognized packet with id 0x{:x} (current connection state: {:?})",
                 id.value,
                 connection.read().unwrap().state()
-            ));
+            )));
         }
     };
 
+    let leftover = body.limit();
+    if leftover > 0 {
+        debug!("Skipping {leftover} unread bytes at the end of packet {packet}");
+        skip(&mut body, leftover).await?;
+    }
     Ok(packet)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol_types::traits::WriteProt;
+    use openssl::symm::{Cipher, Crypter, Mode};
+
+    #[tokio::test]
+    async fn trailing_bytes_skipped() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let stream = over_long_then_regular().await;
+        let mut read_from = stream.as_slice();
+        for slot in [3, 5] {
+            let length = VarInt::read(&mut read_from).await.unwrap();
+            let packet = read_packet(&mut read_from, length, connection.clone()).await.unwrap();
+            assert_eq!(format!("{packet:?}"), format!("SetHeldItem {{ slot: {slot} }}"));
+        }
+        assert!(read_from.is_empty());
+    }
+
+    // two Set Held Item packets, the first one with bytes the server doesn't know about
+    async fn over_long_then_regular() -> Vec<u8> {
+        let mut stream = vec![];
+        for (slot, extra) in [(3i16, vec![1u8, 2, 3]), (5, vec![])] {
+            let mut body = vec![];
+            VarInt::from(0x2b).write(&mut body).await.unwrap();
+            slot.write(&mut body).await.unwrap();
+            body.extend(extra);
+            VarInt::from(body.len()).write(&mut stream).await.unwrap();
+            stream.extend(body);
+        }
+        stream
+    }
+
+    #[tokio::test]
+    async fn play_packet_rejected_during_login() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Login);
+        // Set Held Item is only allowed in Play
+        let stream = over_long_then_regular().await;
+        match parse_packet(&mut stream.as_slice(), connection.clone()).await {
+            Err(err) => assert!(matches!(err, ProtError::PacketNotAllowed(0x2b, ConnectionState::Login)) && err.is_fatal()),
+            Ok(p) => panic!("Expected {p} to be rejected"),
+        }
+
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        assert!(parse_packet(&mut stream.as_slice(), connection).await.is_ok());
+    }
+
+    async fn assert_aligned(mut read_from: &[u8], connection: Arc<RwLock<ConnectionInfo>>) {
+        for slot in [3, 5] {
+            let packet = parse_packet(&mut read_from, connection.clone()).await.unwrap();
+            assert_eq!(format!("{packet:?}"), format!("SetHeldItem {{ slot: {slot} }}"));
+        }
+        assert!(read_from.is_empty());
+    }
+
+    #[tokio::test]
+    async fn plain_stream_stays_aligned() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        assert_aligned(&over_long_then_regular().await, connection).await;
+    }
+
+    #[tokio::test]
+    async fn encrypted_stream_stays_aligned() {
+        let key = [7u8; 16];
+        let cipher = Cipher::aes_128_cfb8();
+        let mut encrypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&key)).unwrap();
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        connection.write().unwrap().decrypter = Some(Crypter::new(cipher, Mode::Decrypt, &key, Some(&key)).unwrap());
+
+        let plain = over_long_then_regular().await;
+        let mut encrypted = vec![0u8; plain.len()];
+        encrypter.update(&plain, &mut encrypted).unwrap();
+        assert_aligned(&encrypted, connection).await;
+    }
+}
@@ -1,30 +1,35 @@
 use core::fmt::Debug;
 use core::fmt::Display;
 use std::sync::{Arc, RwLock};
-
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 use async_nbt::NbtCompound;
+
 use async_trait::async_trait;
-use log::{debug, info};
+use log::{debug, info, warn};
 use openssl::rsa::Padding;
 use tokio::io::AsyncRead;
 use uuid::Uuid;
 
 use crate::connection::ConnectionInfo;
-use crate::connection::ConnectionState;
+use crate::connection::{ConnectionState, NextState};
+use crate::data::ops::GAMEMASTER_LEVEL;
 use crate::encryption::encrypt;
-use crate::err::ProtError;
-use crate::err::ProtError::{KeepAliveIdMismatch, TeleportIdMismatch};
+use crate::err::{ProtError, ProtoError};
 use crate::packet;
 use crate::packet_base;
 use crate::packets::client;
 use crate::packets::client::{ChunkDataAndUpdateLight, ClientPackets};
-use crate::protocol_types::compound::{BitSet, Position, PlayerActions};
-use crate::protocol_types::primitives::SizedVec;
+use crate::protocol_types::compound::{ArgumentSignature, BlockFace, Chat, CommandBlockMode, GameEvent, GameMode, Hand, Identifier, PlayerInfoEntry, Position, PlayerActions, PosRotGround, Slot};
+use crate::protocol_types::primitives::{LimitedString, RawBytes, SizedVec, MAX_STRING_CHARS};
 use crate::protocol_types::primitives::VarInt;
 use crate::protocol_types::traits::{ReadProt, ReadProtPacket, ServerPacket, SizedProt, WriteProt};
+use crate::proxy::ForwardedHandshake;
+use crate::inventory::{Inventory, INVENTORY_SIZE};
 use crate::protocol_util::name_uuid;
-use crate::Assets;
-use crate::chunk::world::WorldPlayer;
+use crate::{commands, serve, status, Assets, STARTING_MSG};
+use crate::chunk::world::{column_of, view_changes, Sound, World, WorldPlayer, VIEW_RADIUS};
+use crate::chunk::AIR;
 
 packet!(
     Handshake 0x00 {
@@ -33,14 +38,31 @@ packet!(
         server_port: u16,
         next_state: VarInt,
     },
-    handler |this, connection, _assets| {
-        connection.write().as_mut().unwrap().set_state(if this.next_state.value == 1 {
-            ConnectionState::Status
-        } else if this.next_state.value == 2 {
-            ConnectionState::Login
-        } else {
-            return Err(ProtError::InvalidNextState(this.next_state.clone()));
-        });
+    handler |this, connection, assets| {
+        let state = match NextState::try_from(this.next_state.value)? {
+            NextState::Status => ConnectionState::Status,
+            NextState::Login => ConnectionState::Login,
+            NextState::Transfer => {
+                debug!("Client was transferred to {}:{}, continuing with login", this.server_address, this.server_port);
+                connection.write().unwrap().transfer_origin = Some((this.server_address.clone(), this.server_port));
+                ConnectionState::Login
+            }
+        };
+        connection.write().as_mut().unwrap().set_state(state);
+        if assets.bungee_forwarding && matches!(state, ConnectionState::Login) {
+            match ForwardedHandshake::parse(&this.server_address) {
+                Ok(forwarded) => {
+                    let mut connection = connection.write().unwrap();
+                    connection.address = Some(forwarded.client_ip);
+                    connection.forwarded_uuid = Some(forwarded.uuid);
+                }
+                Err(e) => {
+                    // fatal, so the client is sent the reason and the connection is closed
+                    debug!("Rejecting handshake without forwarded data: {e}");
+                    return Err(ProtError::NotForwarded);
+                }
+            }
+        }
         Ok(vec![])
     }
 );
@@ -48,7 +70,16 @@ packet!(
 packet!(
     StatusReq 0x00 {},
     handler |_this, connection, assets| {
-        let res = client::StatusRes::new(assets.motd.clone());
+        let motd = if assets.ready.load(Ordering::Acquire) {
+            let online: Vec<(String, Uuid)> = assets.world.read().await
+                .players()
+                .map(|player| (player.username.clone(), player.uuid))
+                .collect();
+            status::status_json(&assets.motd, &assets.status_sample, &online, assets.hide_player_sample).or_else(|err| Err(format!("{err}")))?
+        } else {
+            STARTING_MSG.to_string()
+        };
+        let res = client::StatusRes::new(motd);
         Ok(vec![ClientPackets::StatusRes(res)])
     }
 );
@@ -69,15 +100,33 @@ packet!(
     },
     handler |this, connection, assets| {
         info!("Player {} wants to login...", this.name);
+        if !assets.ready.load(Ordering::Acquire) {
+            let reason = Chat::new_text("The server is still starting, please try again in a moment.".into());
+            return Ok(vec![ClientPackets::LoginDisconnect(client::LoginDisconnect::new(reason))]);
+        }
+        let address = connection.read().unwrap().address;
+        if let Some(address) = address {
+            if !assets.login_limiter.attempt(address, Instant::now()) {
+                info!("Too many login attempts from {address}");
+                let reason = Chat::new_text("Too many login attempts, please wait a minute before trying again.".into());
+                return Ok(vec![ClientPackets::LoginDisconnect(client::LoginDisconnect::new(reason))]);
+            }
+        }
         let mut con = connection.write();
         let con = con.as_mut().unwrap();
         con.username = this.name.clone();
         con.verify_token = vec!(rand::random::<u8>(), rand::random::<u8>(), rand::random::<u8>(), rand::random::<u8>());
-        if assets.online {
+        if let Some(uuid) = con.forwarded_uuid {
+            // the proxy has authenticated the player already
+            con.uuid = uuid;
+            let res = client::LoginSuccess::new(uuid, this.name.clone(), VarInt::from(0));
+            Ok(vec![ClientPackets::LoginSuccess(res)])
+        } else if assets.online {
             let res = client::EncryptionReq::new("".into(), assets.pub_key.clone().into(), con.verify_token.clone().into());
             Ok(vec![ClientPackets::EncryptionReq(res)])
         } else {
-            let res = client::LoginSuccess::new(name_uuid(format!("OfflinePlayer:{}", this.name)), this.name.clone(), VarInt::from(0));
+            con.uuid = name_uuid(format!("OfflinePlayer:{}", this.name));
+            let res = client::LoginSuccess::new(con.uuid, this.name.clone(), VarInt::from(0));
             Ok(vec![ClientPackets::LoginSuccess(res)])
         }
     }
@@ -111,8 +160,8 @@ packet!(
         debug!("Encryption enabled.");
         let username = connection.read().unwrap().username.clone();
         let uuid = encrypt(shared_secret_plain, assets, username.clone()).await?;
-        let res = client::LoginSuccess::new(uuid, username, VarInt::from(0));
         connection.write().as_mut().unwrap().uuid = uuid;
+        let res = client::LoginSuccess::new(uuid, username, VarInt::from(0));
         Ok(vec![ClientPackets::LoginSuccess(res)])
     }
 );
@@ -120,9 +169,47 @@ packet!(
 packet!(
     LoginAck 0x03 {},
     handler |this, connection, assets| {
-        connection.write().as_mut().unwrap().set_state(ConnectionState::Configuration);
-        let res = client::RegistryData::new(assets.registry.clone());
-        Ok(vec![ClientPackets::RegistryData(res)])
+        {
+            let mut connection = connection.write().unwrap();
+            connection.set_state(ConnectionState::Configuration);
+            // the uuid is known by now in every login flow
+            connection.op_level = assets.ops.level(connection.uuid);
+        }
+        let brand = client::ConfigurationPluginMessage::new("minecraft:brand".parse()?, brand_payload(&assets.brand).await?);
+        let res = client::RegistryData::new(assets.registry.nbt().clone());
+        Ok(vec![ClientPackets::ConfigurationPluginMessage(brand), ClientPackets::RegistryData(res)])
+    }
+);
+
+async fn brand_payload(brand: &String) -> Result<RawBytes, ProtoError> {
+    let mut payload = vec![];
+    brand.write(&mut payload).await?;
+    Ok(RawBytes(payload))
+}
+
+packet!(
+    ServerboundPluginMessage 0x01 {
+        channel: Identifier,
+        data: RawBytes,
+    },
+    handler |this, connection, assets| {
+        if this.channel.to_string() != "minecraft:brand" {
+            debug!("Ignoring plugin message on channel {}", this.channel);
+            return Ok(vec![]);
+        }
+        let brand = String::read(&mut this.data.0.as_slice()).await?;
+        debug!("Client brand: {brand}");
+        let state = {
+            let mut connection = connection.write().unwrap();
+            connection.client_brand = Some(brand);
+            connection.state().clone()
+        };
+        let channel = "minecraft:brand".parse()?;
+        let data = brand_payload(&assets.brand).await?;
+        match state {
+            ConnectionState::Play => Ok(vec![ClientPackets::PlayPluginMessage(client::PlayPluginMessage::new(channel, data))]),
+            _ => Ok(vec![ClientPackets::ConfigurationPluginMessage(client::ConfigurationPluginMessage::new(channel, data))]),
+        }
     }
 );
 
@@ -138,43 +225,142 @@ packet!(
         allow_server_listings: bool,
     },
     handler |_this, connection, assets| {
+        if assets.configuration_ping {
+            // the client answers the ping only after it has processed all configuration data sent before it
+            let id = rand::random::<i32>();
+            connection.write().unwrap().configuration_ping_id = Some(id);
+            return Ok(vec![ClientPackets::ConfigurationPing(client::ConfigurationPing::new(id))]);
+        }
         let res = client::ConfigurationFinish::new();
         Ok(vec![ClientPackets::ConfigurationFinish(res)])
     }
 );
 
+packet!(
+    ConfigurationPong 0x04 {
+        id: i32,
+    },
+    handler |this, connection, assets| {
+        let mut connection = connection.write().unwrap();
+        if connection.configuration_ping_id != Some(this.id) {
+            debug!("Ignoring pong {} that doesn't answer the configuration ping", this.id);
+            return Ok(vec![]);
+        }
+        connection.configuration_ping_id = None;
+        Ok(vec![ClientPackets::ConfigurationFinish(client::ConfigurationFinish::new())])
+    }
+);
+
 packet!(
     ConfigurationFinish 0x02 {},
     handler |_this, connection, assets| {
-        connection.write().as_mut().unwrap().set_state(ConnectionState::Play);
-        let res = client::PlayLogin::new(
-            0,
-            false,
-            vec!["world".into()].into(),
-            VarInt::from(2),
-            VarInt::from(5),
-            VarInt::from(3),
-            false,
-            false,
-            false,
-            "minecraft:overworld".into(),
-            "minecraft:overworld".into(),
-            0, 0, 0, false, false, false, None, None, 0.into());
-        Ok(vec![ClientPackets::PlayLogin(res)])
+        let entity_id = {
+            let mut connection = connection.write().unwrap();
+            connection.set_state(ConnectionState::Play);
+            connection.entity_id = assets.entity_ids.next();
+            connection.entity_id
+        };
+        let params = client::PlayLoginParams {
+            entity_id,
+            dimension_names: vec!["world".parse()?],
+            max_players: 2,
+            view_distance: 5,
+            simulation_distance: 3,
+            enable_respawn_screen: false,
+            ..Default::default()
+        };
+        let dimension = params.dimension_type.to_string();
+        if !assets.registry.dimension_type(&dimension).is_some_and(World::fits) {
+            warn!("Dimension type {} doesn't match the height of the world, clients won't be able to read chunks", dimension);
+        }
+        let res = client::PlayLogin::from(params);
+        let (inventory, abilities) = {
+            let connection = connection.read().unwrap();
+            (connection.inventory.content(), client::PlayerAbilities::for_game_mode(connection.game_mode, connection.flying))
+        };
+        let mut to_send = vec![
+            ClientPackets::PlayLogin(res),
+            ClientPackets::PlayerAbilities(abilities),
+            ClientPackets::Commands(commands::command_graph()),
+            inventory,
+        ];
+        to_send.extend(join(connection, assets).await);
+        Ok(to_send)
     }
 );
 
+fn spawn_player(entity_id: i32, uuid: Uuid, position: &PosRotGround) -> ClientPackets {
+    ClientPackets::SpawnPlayer(client::SpawnPlayer::new(
+        entity_id.into(),
+        uuid,
+        client::PLAYER_ENTITY_TYPE.into(),
+        position.x,
+        position.y,
+        position.z,
+        position.pitch.into(),
+        position.yaw.into(),
+        position.yaw.into(),
+        0.into(),
+        0,
+        0,
+        0,
+    ))
+}
+
+
+// players are added to the tab list along with their nickname
+const PLAYER_INFO_ADD: u8 = client::PLAYER_INFO_ADD_AND_LIST | client::PLAYER_INFO_DISPLAY_NAME;
+
+/**
+ * Places a player that just entered the Play state at its last known position, adds it to the world's players
+ * until it leaves, spawns all other players for it and announces it to them. Returns the packets for the joining
+ * player.
+ */
+async fn join(connection: Arc<RwLock<ConnectionInfo>>, assets: Arc<Assets>) -> Vec<ClientPackets> {
+    assets.online_players.fetch_add(1, Ordering::AcqRel);
+    let uuid = connection.read().unwrap().uuid;
+    let last = assets.world.read().await.player(uuid).map(|p| p.position).unwrap_or(Position::new(0, 0, 0));
+    let (username, display_name, entity_id, position) = {
+        let mut connection = connection.write().unwrap();
+        connection.position.x = last.x as f64;
+        connection.position.y = last.y as f64 + 4.0;
+        connection.position.z = last.z as f64;
+        (connection.username.clone(), connection.display_name.clone(), connection.entity_id, connection.position.clone())
+    };
+    assets.world.write().await.set_player(WorldPlayer { uuid, username: username.clone(), position: position.clone().into() });
+
+    let mut infos = vec![];
+    let mut spawns = vec![];
+    for handle in assets.connections.read().await.iter() {
+        if Arc::ptr_eq(handle.connection(), &connection) {
+            continue;
+        }
+        let other = handle.connection().read().unwrap();
+        if other.closed() || !matches!(other.state(), ConnectionState::Play) {
+            continue;
+        }
+        infos.push(PlayerInfoEntry::added(other.uuid, other.username.clone(), other.display_name.clone()));
+        spawns.push(spawn_player(other.entity_id, other.uuid, &other.position));
+    }
+    let mut to_send = vec![];
+    if !infos.is_empty() {
+        to_send.push(ClientPackets::PlayerInfoUpdate(client::PlayerInfoUpdate::new(PLAYER_INFO_ADD, infos.into())));
+    }
+    to_send.extend(spawns);
+
+    let info = client::PlayerInfoUpdate::new(PLAYER_INFO_ADD, vec![PlayerInfoEntry::added(uuid, username, display_name)].into());
+    serve::broadcast(&assets, Some(uuid), ClientPackets::PlayerInfoUpdate(info)).await;
+    serve::broadcast(&assets, Some(uuid), spawn_player(entity_id, uuid, &position)).await;
+    to_send
+}
+
 packet!(
     ConfigurationKeepAlive 0x03 {
         id: i64,
     },
     handler |this, connection, assets| {
-        let keep_alive_id = connection.read().unwrap().keep_alive_id;
-        if this.id != keep_alive_id {
-            Err(KeepAliveIdMismatch(keep_alive_id, this.id))
-        } else {
-            Ok(vec![])
-        }
+        connection.write().unwrap().keep_alive.ack(this.id, Instant::now())?;
+        Ok(vec![])
     }
 );
 
@@ -183,12 +369,8 @@ packet!(
         id: i64,
     },
     handler |this, connection, assets| {
-        let keep_alive_id = connection.read().unwrap().keep_alive_id;
-        if this.id != keep_alive_id {
-            Err(KeepAliveIdMismatch(keep_alive_id, this.id))
-        } else {
-            Ok(vec![])
-        }
+        connection.write().unwrap().keep_alive.ack(this.id, Instant::now())?;
+        Ok(vec![])
     }
 );
 
@@ -204,12 +386,10 @@ packet!(
         let guard = guard.as_mut().unwrap();
         let p1 = client::SetHeldItem::new(0);
         let p2 = client::UpdateRecipes::new(vec![].into());
-        guard.teleport_id = rand::random::<usize>().into();
-        let uuid = guard.uuid;
-        let world = assets.world.read().unwrap();
-        let pos = world.player(uuid).map(|p| p.position).unwrap_or(Position {x:0, y:0, z:0});
+        guard.begin_teleport();
+        // the position was restored when the player joined
         let p3 = client::SynchronizePlayerPosition::new(
-            pos.x as f64, pos.y as f64 + 4.0, pos.z as f64, 0.0, 0.0, 0u8,
+            guard.position.x, guard.position.y, guard.position.z, 0.0, 0.0, 0u8,
             guard.teleport_id.clone(),
         );
         Ok(vec![ClientPackets::SetHeldItem(p1), ClientPackets::UpdateRecipes(p2), ClientPackets::SynchronizePlayerPosition(p3)])
@@ -224,21 +404,95 @@ packet!(
         on_ground: bool,
     },
     handler |this, connection, assets| {
-        let mut guard = assets.world.write();
-        let mut guard = guard.as_mut().unwrap();
-        guard.set_player(WorldPlayer {
-            uuid: connection.read().unwrap().uuid,
-            username: connection.read().unwrap().username.clone(),
-            position: Position {
-                x: this.x as i32,
-                y: this.y as i32,
-                z: this.z as i32,
-            },
-        });
-        Ok(vec![])
+        if connection.read().unwrap().awaiting_teleport {
+            debug!("Ignoring position update before teleport confirmation");
+            return Ok(vec![]);
+        }
+        let new = {
+            let position = &connection.read().unwrap().position;
+            PosRotGround { x: this.x, y: this.y, z: this.z, on_ground: this.on_ground, ..position.clone() }
+        };
+        broadcast_movement(connection.clone(), assets.clone(), new).await?;
+        update_view(connection, assets).await
     }
 );
 
+/**
+ * Sends the columns that came into view and unloads those that went out of view if the player
+ * crossed into another column since the chunks were last sent.
+ */
+async fn update_view(connection: Arc<RwLock<ConnectionInfo>>, assets: Arc<Assets>) -> Result<Vec<ClientPackets>, ProtError> {
+    let (old, new) = {
+        let mut connection = connection.write().unwrap();
+        let new = column_of(connection.position.x, connection.position.z);
+        match connection.center_chunk {
+            Some(old) if old != new => {
+                connection.center_chunk = Some(new);
+                (old, new)
+            }
+            _ => return Ok(vec![]),
+        }
+    };
+    let (entered, left) = view_changes(old, new, VIEW_RADIUS);
+    let mut to_send = vec![ClientPackets::SetCenterChunk(client::SetCenterChunk::new(new.0.into(), new.1.into()))];
+    for (x, z) in left {
+        to_send.push(ClientPackets::UnloadChunk(client::UnloadChunk::new(z, x)));
+    }
+    let columns = assets.world.write().await.get_columns(&entered, &assets.global_palette).await;
+    for (x, z, column) in columns {
+        to_send.push(ClientPackets::ChunkDataAndUpdateLight(ChunkDataAndUpdateLight::from_column(x, z, &column).await?));
+    }
+    Ok(to_send)
+}
+
+/**
+ * Copies the position of the player into the world, where it is kept while they are offline and used to find the
+ * players near a position.
+ */
+async fn update_world_position(connection: &RwLock<ConnectionInfo>, assets: &Assets) {
+    let (uuid, position) = {
+        let connection = connection.read().unwrap();
+        (connection.uuid, connection.position.clone().into())
+    };
+    assets.world.write().await.move_player(uuid, position);
+}
+
+/**
+ * Updates the position of the player and lets all other players know about the movement.
+ */
+async fn broadcast_movement(connection: Arc<RwLock<ConnectionInfo>>, assets: Arc<Assets>, new: PosRotGround) -> Result<(), ProtError> {
+    let (uuid, packets) = {
+        let mut connection = connection.write().unwrap();
+        if connection.is_flying(&new) && !assets.allow_flight {
+            return Err(ProtError::FlyingNotAllowed);
+        }
+        let entity_id: VarInt = connection.entity_id.into();
+        let mut packets = vec![];
+        match connection.position.delta_to(&new) {
+            Some((delta_x, delta_y, delta_z)) => {
+                packets.push(ClientPackets::UpdateEntityPositionAndRotation(client::UpdateEntityPositionAndRotation::new(
+                    entity_id,
+                    delta_x,
+                    delta_y,
+                    delta_z,
+                    new.yaw.into(),
+                    new.pitch.into(),
+                    new.on_ground,
+                )));
+                packets.push(ClientPackets::HeadRotation(client::HeadRotation::new(entity_id, new.yaw.into())));
+            }
+            None => debug!("{} moved too far to be sent as a delta", connection.username),
+        }
+        connection.position = new;
+        (connection.uuid, packets)
+    };
+    update_world_position(&connection, &assets).await;
+    for packet in packets {
+        serve::broadcast(&assets, Some(uuid), packet).await;
+    }
+    Ok(())
+}
+
 packet!(
     SetPlayerPositionAndRotation 0x17 {
         x: f64,
@@ -249,15 +503,20 @@ packet!(
         on_ground: bool,
     },
     handler |this, connection, assets| {
-        let mut guard = connection.write();
-        let guard = guard.as_mut().unwrap();
-        guard.position.x = this.x;
-        guard.position.y = this.y;
-        guard.position.z = this.z;
-        guard.position.pitch = this.pitch as f64;
-        guard.position.yaw = this.yaw as f64;
-        guard.position.on_ground = this.on_ground;
-        Ok(vec![])
+        if connection.read().unwrap().awaiting_teleport {
+            debug!("Ignoring position update before teleport confirmation");
+            return Ok(vec![]);
+        }
+        let new = PosRotGround {
+            x: this.x,
+            y: this.y,
+            z: this.z,
+            pitch: this.pitch as f64,
+            yaw: this.yaw as f64,
+            on_ground: this.on_ground,
+        };
+        broadcast_movement(connection.clone(), assets.clone(), new).await?;
+        update_view(connection, assets).await
     }
 );
 
@@ -277,6 +536,109 @@ packet!(
     }
 );
 
+packet!(
+    ClientStatus 0x08 {
+        action: VarInt,
+    },
+    handler |this, connection, assets| {
+        match this.action.value {
+            0 => {
+                // perform respawn, which the client only asks for after it died
+                let world_spawn = assets.world.read().await.spawn;
+                let packets = {
+                    let mut connection = connection.write().unwrap();
+                    if !connection.is_dead() {
+                        debug!("Ignoring respawn request of {}, who is alive", connection.username);
+                        return Ok(vec![]);
+                    }
+                    connection.respawn(world_spawn)
+                };
+                update_world_position(&connection, &assets).await;
+                Ok(packets)
+            }
+            _ => Ok(vec![]),
+        }
+    }
+);
+
+/**
+ * The message rejecting a chat message or command longer than the server allows, None if it isn't too long.
+ */
+fn too_long(text: &str, assets: &Assets, kind: &str) -> Option<ClientPackets> {
+    if text.chars().count() <= assets.max_chat_length {
+        return None;
+    }
+    let error = format!("{kind} can't be longer than {} characters", assets.max_chat_length);
+    Some(ClientPackets::SystemChatMessage(client::SystemChatMessage::new(Chat::new_text(error), false)))
+}
+
+packet!(
+    ChatCommand 0x04 {
+        // read at the protocol's limit, the server's own limit may be longer than vanilla's
+        command: LimitedString<MAX_STRING_CHARS>,
+        timestamp: i64,
+        salt: i64,
+        argument_signatures: SizedVec<ArgumentSignature>,
+        message_count: VarInt,
+        acknowledged: [u8; 3],
+    },
+    handler |this, connection, assets| {
+        if let Some(error) = too_long(&this.command.0, &assets, "Commands") {
+            return Ok(vec![error]);
+        }
+        let before = connection.read().unwrap().display_name.clone();
+        let packets = commands::execute(&connection, &assets, &this.command.0).await;
+        let renamed = {
+            let connection = connection.read().unwrap();
+            (connection.display_name != before).then(|| PlayerInfoEntry::display_name(connection.uuid, connection.display_name.clone()))
+        };
+        // nicknames are shown in the tab list of every player, including the renamed one
+        if let Some(entry) = renamed {
+            let info = client::PlayerInfoUpdate::new(client::PLAYER_INFO_DISPLAY_NAME, vec![entry].into());
+            serve::broadcast(&assets, None, ClientPackets::PlayerInfoUpdate(info)).await;
+        }
+        // commands like /tp move the player
+        update_world_position(&connection, &assets).await;
+        Ok(packets)
+    }
+);
+
+packet!(
+    ChatMessage 0x05 {
+        // read at the protocol's limit, the server's own limit may be longer than vanilla's
+        message: LimitedString<MAX_STRING_CHARS>,
+        timestamp: i64,
+        salt: i64,
+        has_signature: bool,
+        signature: {has_signature} && [u8; 256],
+        message_count: VarInt,
+        acknowledged: [u8; 3],
+    },
+    handler |this, connection, assets| {
+        if let Some(error) = too_long(&this.message.0, &assets, "Chat messages") {
+            return Ok(vec![error]);
+        }
+        // signatures aren't validated, the message is relayed as unsigned chat
+        let chat_type = assets.registry.chat_type_id("minecraft:chat").ok_or(ProtError::Any("Registry has no minecraft:chat chat type".into()))?;
+        let message = client::DisguisedChatMessage::from_player(&connection.read().unwrap(), Chat::new_text(this.message.0.clone()), chat_type);
+        serve::broadcast(&assets, None, ClientPackets::DisguisedChatMessage(message)).await;
+        Ok(vec![])
+    }
+);
+
+packet!(
+    SetHeldItem 0x2b {
+        slot: i16,
+    },
+    handler |this, connection, assets| {
+        if !(0..=8).contains(&this.slot) {
+            return Err(ProtError::InvalidHeldSlot(this.slot));
+        }
+        connection.write().unwrap().held_slot = this.slot as u8;
+        Ok(vec![])
+    }
+);
+
 packet!(
     PlayerCommand 0x21 {
         entity: VarInt,
@@ -297,18 +659,213 @@ packet!(
         sequence: VarInt,
     },
     handler |this, connection, assets| {
+        // the client waits for an acknowledgement of the sequence before it stops predicting the block change
+        let ack = ClientPackets::AcknowledgeBlockChange(client::AcknowledgeBlockChange::new(this.sequence));
         match this.action {
             PlayerActions::FinishDig => {
-                let mut world = assets.world.write();
-                let mut world = world.as_mut().unwrap();
-                return Ok(world.set_block(this.position, 0).unwrap());
+                let (uuid, player) = {
+                    let connection = connection.read().unwrap();
+                    (connection.uuid, connection.position.clone())
+                };
+                let (mut packets, sound) = {
+                    let mut world = assets.world.write().await;
+                    let before = world.block(this.position);
+                    let packets = world.dig_block(&player, this.position, &assets.global_palette).await;
+                    // the player breaking the block plays the sound themselves
+                    let sound = before
+                        .filter(|block| *block != AIR && world.block(this.position) == Some(AIR))
+                        .and_then(|block| assets.global_palette.block_name(block))
+                        .map(|name| world.play_sound_at(this.position, &Sound::block_break(&name.name)));
+                    (packets, sound)
+                };
+                if let Some((listeners, packet)) = sound {
+                    let listeners: Vec<Uuid> = listeners.into_iter().filter(|listener| *listener != uuid).collect();
+                    serve::send_to(&assets, &listeners, packet).await;
+                }
+                packets.push(ack);
+                Ok(packets)
+            }
+            PlayerActions::StartDig | PlayerActions::CancelDig => Ok(vec![ack]),
+            _ => {
+                debug!("PlayerAction: {:?}", this.action);
+                Ok(vec![])
+            }
+        }
+    }
+);
+
+packet!(
+    PickItem 0x1c {
+        // index into the player's inventory, the hotbar (0 to 8) followed by the main inventory
+        slot_to_use: VarInt,
+    },
+    handler |this, connection, assets| {
+        // sent when middle-clicking a block whose item is in the inventory, which is swapped into the selected hotbar slot
+        let Some(slot) = Inventory::inventory_slot(this.slot_to_use.value) else {
+            debug!("Ignoring pick item of inventory slot {}", this.slot_to_use);
+            return Ok(vec![]);
+        };
+        let mut connection = connection.write().unwrap();
+        let held = Inventory::hotbar_slot(connection.held_slot);
+        connection.inventory.swap(slot, held);
+        Ok(vec![connection.inventory.slot_update(held), connection.inventory.slot_update(slot)])
+    }
+);
+
+packet!(
+    PlayerAbilities 0x1f {
+        // only the flying bit is used
+        flags: u8,
+    },
+    handler |this, connection, assets| {
+        let mut connection = connection.write().unwrap();
+        let flying = this.flags & client::ABILITY_FLYING != 0;
+        let may_fly = matches!(connection.game_mode, GameMode::Creative | GameMode::Spectator);
+        if flying && !may_fly {
+            debug!("Ignoring {} starting to fly in {:?} mode", connection.username, connection.game_mode);
+            return Ok(vec![]);
+        }
+        connection.flying = flying;
+        Ok(vec![])
+    }
+);
+
+packet!(
+    SetCreativeModeSlot 0x2e {
+        // window slot of the player's inventory, -1 to drop the item
+        slot: i16,
+        clicked_item: Slot,
+    },
+    handler |this, connection, assets| {
+        let mut connection = connection.write().unwrap();
+        if !matches!(connection.game_mode, GameMode::Creative) {
+            debug!("Ignoring creative inventory action outside of creative mode");
+            return Ok(vec![]);
+        }
+        if !(0..INVENTORY_SIZE as i16).contains(&this.slot) {
+            debug!("Ignoring creative inventory action for slot {}", this.slot);
+            return Ok(vec![]);
+        }
+        connection.inventory.set(this.slot as usize, this.clicked_item.clone().into());
+        Ok(vec![])
+    }
+);
+
+// command block flags
+const COMMAND_BLOCK_TRACK_OUTPUT: u8 = 0x01;
+const COMMAND_BLOCK_AUTOMATIC: u8 = 0x04;
+
+packet!(
+    ProgramCommandBlock 0x2c {
+        location: Position,
+        command: String,
+        mode: CommandBlockMode,
+        flags: u8,
+    },
+    handler |this, connection, assets| {
+        // vanilla only lets operators in creative mode edit command blocks
+        {
+            let connection = connection.read().unwrap();
+            if !connection.is_op(GAMEMASTER_LEVEL) || !matches!(connection.game_mode, GameMode::Creative) {
+                debug!("Ignoring command block edit of {}, who isn't an operator in creative mode", connection.username);
+                return Ok(vec![]);
             }
-            _ => println!("PlayerAction: {:?}", this.action)
         }
+        // commands aren't run yet, the block entity just keeps what the player entered
+        let mut nbt = NbtCompound::new();
+        nbt.insert("id", "minecraft:command_block");
+        nbt.insert("Command", this.command.clone());
+        nbt.insert("TrackOutput", this.flags & COMMAND_BLOCK_TRACK_OUTPUT != 0);
+        nbt.insert("auto", this.flags & COMMAND_BLOCK_AUTOMATIC != 0);
+        debug!("Command block at {:?} set to {:?} ({:?})", this.location, this.command, this.mode);
+        let mut world = assets.world.write().await;
+        world.set_block_entity(this.location, nbt);
+        // the client's copy of the block entity, shown when reopening the block, is only updated by the server
+        Ok(world.block_entity_update(this.location, client::BLOCK_ENTITY_COMMAND_BLOCK).into_iter().collect())
+    }
+);
+
+packet!(
+    ProgramCommandBlockMinecart 0x2d {
+        entity_id: VarInt,
+        command: String,
+        track_output: bool,
+    },
+    handler |this, connection, assets| {
+        if !connection.read().unwrap().is_op(GAMEMASTER_LEVEL) {
+            debug!("Ignoring command block minecart edit of {}, who isn't an operator", connection.read().unwrap().username);
+            return Ok(vec![]);
+        }
+        // there are no minecart entities to store the command in
+        debug!("Ignoring command {:?} for command block minecart {}", this.command, this.entity_id);
+        Ok(vec![])
+    }
+);
+
+packet!(
+    SwingArm 0x32 {
+        hand: Hand,
+    },
+    handler |this, connection, assets| {
+        let (uuid, entity_id) = {
+            let connection = connection.read().unwrap();
+            (connection.uuid, connection.entity_id)
+        };
+        let animation = match this.hand {
+            Hand::MainHand => client::ANIMATION_SWING_MAIN_ARM,
+            Hand::OffHand => client::ANIMATION_SWING_OFFHAND,
+        };
+        let packet = client::EntityAnimation::new(entity_id.into(), animation);
+        serve::broadcast(&assets, Some(uuid), ClientPackets::EntityAnimation(packet)).await;
+        Ok(vec![])
+    }
+);
+
+packet!(
+    UseItem 0x35 {
+        hand: Hand,
+        sequence: VarInt,
+    },
+    handler |this, connection, assets| {
+        // items don't have any behavior of their own yet, e.g. written books would be opened with OpenBook
+        debug!("{} used the item in their {:?} (sequence {})", connection.read().unwrap().username, this.hand, this.sequence);
         Ok(vec![])
     }
 );
 
+packet!(
+    UseItemOn 0x34 {
+        hand: Hand,
+        location: Position,
+        face: BlockFace,
+        cursor_x: f32,
+        cursor_y: f32,
+        cursor_z: f32,
+        inside_block: bool,
+        sequence: VarInt,
+    },
+    handler |this, connection, assets| {
+        let (player, held) = {
+            let connection = connection.read().unwrap();
+            let slot = Inventory::hand_slot(this.hand, connection.held_slot);
+            (connection.position.clone(), connection.inventory.get(slot).map(|stack| stack.item_id))
+        };
+        let ack = ClientPackets::AcknowledgeBlockChange(client::AcknowledgeBlockChange::new(this.sequence));
+        // block items share the name of the block they place
+        let Some(block) = held
+            .and_then(|item_id| assets.items.item_name(item_id))
+            .and_then(|name| assets.global_palette.default_state(name))
+            .filter(|block| *block != AIR) else {
+            debug!("{} used {:?} on {:?}, which doesn't place a block", connection.read().unwrap().username, held, this.location);
+            return Ok(vec![ack]);
+        };
+        let target = this.location.offset(this.face);
+        let mut packets = assets.world.write().await.place_block(&player, target, block, &assets.global_palette).await;
+        packets.push(ack);
+        Ok(packets)
+    }
+);
+
 // async fn get_chunks() -> Vec<u8> {
 //     let mut chunks = vec![];
 //     for i in 0..COLUMN_HEIGHT {
@@ -328,41 +885,735 @@ packet!(
         teleport_id: VarInt,
     },
     handler |this, connection, assets| {
-        let expected_id = connection.read().unwrap().teleport_id;
-        if expected_id == this.teleport_id {
-            let p6 = client::SetDefaultSpawnPosition::new(Position {x:0, y:0, z:0}, 0.0);
-            let p7 = client::SetCenterChunk::new(0.into(), 0.into());
-            let mut to_send = vec![ClientPackets::SetDefaultSpawnPosition(p6), ClientPackets::SetCenterChunk(p7)];
-
-            let chunks = {
-                let chunks = assets.world.read().unwrap();
-                let chunks = chunks.get_chunk_radius(connection.read().unwrap().position.clone().into(), 3);
-                chunks
+        if !connection.write().unwrap().confirm_teleport(this.teleport_id)? {
+            debug!("Ignoring confirmation of teleport {} that isn't pending", this.teleport_id);
+            return Ok(vec![]);
+        }
+        {
+            let (world_spawn, border) = {
+                let world = assets.world.read().await;
+                (world.spawn, world.border.packets())
             };
+            let spawn = connection.read().unwrap().respawn_position.unwrap_or(world_spawn);
+            let center = {
+                let mut connection = connection.write().unwrap();
+                let center = column_of(connection.position.x, connection.position.z);
+                connection.center_chunk = Some(center);
+                center
+            };
+            let p6 = client::SetDefaultSpawnPosition::new(spawn, 0.0);
+            let p7 = client::SetCenterChunk::new(center.0.into(), center.1.into());
+            let p8 = client::SendGameEvent::new(GameEvent::StartWaitingForLevelChunks);
+            let mut to_send = vec![
+                ClientPackets::SetDefaultSpawnPosition(p6),
+                ClientPackets::SetCenterChunk(p7),
+                ClientPackets::SendGameEvent(p8),
+            ];
+            to_send.extend(border);
+
+            // the columns are copied, so the world isn't locked while they are serialized and sent
+            let position = connection.read().unwrap().position.clone().into();
+            let chunks = assets.world.write().await.get_chunk_radius(position, VIEW_RADIUS, &assets.global_palette).await;
             for (x, z, chunk) in chunks {
-                let mut col_bytes = vec![];
-                for section in chunk {
-                    section.write(&mut col_bytes).await.unwrap();
-                }
                 to_send.push(ClientPackets::ChunkDataAndUpdateLight(
-                    ChunkDataAndUpdateLight::new(
-                        x,
-                        z,
-                        NbtCompound::new(),
-                        col_bytes.into(),
-                        vec![].into(),
-                        BitSet(vec![].into()),
-                        BitSet(vec![].into()),
-                        BitSet(vec![].into()),
-                        BitSet(vec![].into()),
-                        vec![].into(),
-                        vec![].into(),
-                    )
+                    ChunkDataAndUpdateLight::from_column(x, z, &chunk).await?
                 ));
             }
             Ok(to_send)
-        } else {
-            Err(TeleportIdMismatch(expected_id, this.teleport_id))
         }
     }
 );
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol_types::traits::WriteProtPacket;
+    use crate::inventory::ItemStack;
+
+    #[tokio::test]
+    async fn login_before_ready_rejected() {
+        let assets = Assets::for_test().await;
+        assets.ready.store(false, Ordering::Release);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let packet = LoginStart { name: "steve".into() };
+        let response = packet.handle(connection.clone(), Arc::new(assets)).await.unwrap();
+        match response.as_slice() {
+            [disconnect @ ClientPackets::LoginDisconnect(_)] => {
+                let mut buf = vec![];
+                disconnect.write(&mut buf, connection).await.unwrap();
+                assert!(String::from_utf8_lossy(&buf).contains("still starting"));
+            }
+            _ => panic!("Expected a login disconnect"),
+        }
+    }
+
+    #[tokio::test]
+    async fn offline_login_sets_uuid() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let res = LoginStart { name: "steve".into() }.handle(connection.clone(), assets).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::LoginSuccess(_)]));
+        let uuid = connection.read().unwrap().uuid;
+        assert!(!uuid.is_nil());
+        assert_eq!(uuid, name_uuid("OfflinePlayer:steve".into()));
+    }
+
+    async fn serialize(packets: &[ClientPackets]) -> Vec<u8> {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let mut buf = vec![];
+        for packet in packets {
+            packet.write(&mut buf, connection.clone()).await.unwrap();
+        }
+        buf
+    }
+
+    #[tokio::test]
+    async fn finish_dig_breaks_block() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().position.y = -16.0;
+        let target = Position::new(0, -17, 0);
+        assert_ne!(assets.world.read().await.block(target), Some(AIR));
+
+        let mut other = ConnectionInfo::new();
+        other.uuid = Uuid::from_u128(2);
+        assets.world.write().await.set_player(WorldPlayer { uuid: other.uuid, username: "alex".into(), position: Position::new(4, -16, 0) });
+        let (handle, mut receiver) = serve::ConnectionActorHandle::detached(Arc::new(RwLock::new(other)));
+        assets.connections.write().await.push(handle);
+
+        let packet = PlayerAction { action: PlayerActions::FinishDig, position: target, face: 1, sequence: 7.into() };
+        let response = packet.handle(connection, assets.clone()).await.unwrap();
+        assert_eq!(assets.world.read().await.block(target), Some(AIR));
+        let expected = [
+            ClientPackets::BlockUpdate(client::BlockUpdate::new(target, AIR.into())),
+            ClientPackets::AcknowledgeBlockChange(client::AcknowledgeBlockChange::new(7.into())),
+        ];
+        assert_eq!(serialize(&response).await, serialize(&expected).await);
+        // players nearby hear the block break
+        assert!(matches!(receiver.try_recv(), Ok(serve::ConnectionActorMessage::SendPacket(ClientPackets::SoundEffect(_)))));
+    }
+
+    #[tokio::test]
+    async fn login_ack_sends_brand() {
+        let mut assets = Assets::for_test().await;
+        assets.brand = "testcraft".into();
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let response = LoginAck {}.handle(connection, Arc::new(assets)).await.unwrap();
+        match response.first() {
+            Some(brand @ ClientPackets::ConfigurationPluginMessage(_)) => {
+                let mut expected = vec![];
+                0x00u8.write(&mut expected).await.unwrap();
+                "minecraft:brand".to_string().write(&mut expected).await.unwrap();
+                "testcraft".to_string().write(&mut expected).await.unwrap();
+                let mut packet = vec![];
+                VarInt::from(expected.len()).write(&mut packet).await.unwrap();
+                packet.append(&mut expected);
+                assert_eq!(serialize(&[brand.clone()]).await, packet);
+            }
+            _ => panic!("Expected the brand plugin message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn use_item_parses_hand_and_sequence() {
+        let mut buf = vec![];
+        VarInt::from(1).write(&mut buf).await.unwrap();
+        VarInt::from(300).write(&mut buf).await.unwrap();
+        let packet = UseItem::read(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(packet.hand, Hand::OffHand);
+        assert_eq!(packet.sequence, VarInt::from(300));
+
+        let mut buf = vec![];
+        VarInt::from(2).write(&mut buf).await.unwrap();
+        VarInt::from(0).write(&mut buf).await.unwrap();
+        assert!(UseItem::read(&mut buf.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn command_block_program_stored() {
+        let mut buf = vec![];
+        Position::new(1, 64, -2).write(&mut buf).await.unwrap();
+        "say hi".to_string().write(&mut buf).await.unwrap();
+        VarInt::from(1).write(&mut buf).await.unwrap();
+        (COMMAND_BLOCK_TRACK_OUTPUT | COMMAND_BLOCK_AUTOMATIC).write(&mut buf).await.unwrap();
+        let packet = ProgramCommandBlock::read(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(packet.command, "say hi");
+        assert_eq!(packet.mode, CommandBlockMode::Auto);
+
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().game_mode = GameMode::Creative;
+        packet.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(assets.world.read().await.block_entity(Position::new(1, 64, -2)).is_none());
+
+        connection.write().unwrap().op_level = GAMEMASTER_LEVEL;
+        let res = packet.handle(connection, assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::BlockEntityData(_)]));
+        let world = assets.world.read().await;
+        let nbt = world.block_entity(Position::new(1, 64, -2)).unwrap();
+        assert_eq!(nbt.get::<_, &str>("Command").unwrap(), "say hi");
+        assert!(nbt.get::<_, bool>("auto").unwrap());
+    }
+
+    #[tokio::test]
+    async fn position_before_teleport_confirm_ignored() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let superseded_id = connection.write().unwrap().begin_teleport();
+        let teleport_id = connection.write().unwrap().begin_teleport();
+
+        let early = SetPlayerPositionAndRotation { x: 100.0, y: 80.0, z: 100.0, yaw: 0.0, pitch: 0.0, on_ground: true };
+        assert!(early.handle(connection.clone(), assets.clone()).await.is_ok());
+        assert_eq!(connection.read().unwrap().position.x, 0.0);
+
+        let stale = ConfirmTeleportation { teleport_id: superseded_id };
+        assert!(stale.handle(connection.clone(), assets.clone()).await.unwrap().is_empty());
+        assert!(connection.read().unwrap().awaiting_teleport);
+
+        let confirm = ConfirmTeleportation { teleport_id };
+        assert!(!confirm.handle(connection.clone(), assets.clone()).await.unwrap().is_empty());
+        assert!(!connection.read().unwrap().awaiting_teleport);
+
+        let moved = SetPlayerPositionAndRotation { x: 1.0, y: 2.0, z: 3.0, yaw: 0.0, pitch: 0.0, on_ground: true };
+        moved.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(connection.read().unwrap().position.x, 1.0);
+    }
+
+    #[tokio::test]
+    async fn world_position_follows_movement() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let uuid = connection.read().unwrap().uuid;
+        assets.world.write().await.set_player(WorldPlayer { uuid, username: "steve".into(), position: Position::new(0, 0, 0) });
+
+        let moved = SetPlayerPositionAndRotation { x: -0.5, y: 2.0, z: 3.7, yaw: 0.0, pitch: 0.0, on_ground: true };
+        moved.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(assets.world.read().await.player(uuid).unwrap().position, Position::new(-1, 2, 3));
+
+        let moved = SetPlayerPosition { x: -2.25, y: -0.5, z: 3.0, on_ground: true };
+        moved.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(assets.world.read().await.player(uuid).unwrap().position, Position::new(-3, -1, 3));
+    }
+
+    // fails every write made while the world is locked
+    struct WorldUnlockedWriter(Arc<Assets>);
+
+    impl tokio::io::AsyncWrite for WorldUnlockedWriter {
+        fn poll_write(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, buf: &[u8]) -> std::task::Poll<std::io::Result<usize>> {
+            assert!(self.0.world.try_write().is_ok(), "World locked during network write");
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn chunks_sent_without_world_lock() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let teleport_id = connection.write().unwrap().begin_teleport();
+
+        let packets = ConfirmTeleportation { teleport_id }.handle(connection.clone(), assets.clone()).await.unwrap();
+        let chunks = packets.iter().filter(|p| matches!(p, ClientPackets::ChunkDataAndUpdateLight(_))).count();
+        let waiting = packets.iter().position(|p| matches!(p, ClientPackets::SendGameEvent(_))).unwrap();
+        let first_chunk = packets.iter().position(|p| matches!(p, ClientPackets::ChunkDataAndUpdateLight(_))).unwrap();
+        assert!(waiting < first_chunk);
+        assert_eq!(chunks, ((2 * VIEW_RADIUS + 1) * (2 * VIEW_RADIUS + 1)) as usize);
+        let mut write = WorldUnlockedWriter(assets.clone());
+        for packet in packets {
+            packet.write(&mut write, connection.clone()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn client_brand_decoded() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Configuration);
+
+        let mut packet = vec![];
+        "minecraft:brand".to_string().write(&mut packet).await.unwrap();
+        "vanilla".to_string().write(&mut packet).await.unwrap();
+        let message = ServerboundPluginMessage::read(&mut packet.as_slice()).await.unwrap();
+
+        let res = message.handle(connection.clone(), assets).await.unwrap();
+        assert_eq!(connection.read().unwrap().client_brand.as_deref(), Some("vanilla"));
+        assert!(matches!(res.as_slice(), [ClientPackets::ConfigurationPluginMessage(_)]));
+    }
+
+    #[tokio::test]
+    async fn transfer_handshake_records_origin() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let handshake = Handshake {
+            prot_version: 764.into(),
+            server_address: "lobby.example.com".into(),
+            server_port: 25566,
+            next_state: 3.into(),
+        };
+        handshake.handle(connection.clone(), assets).await.unwrap();
+        let connection = connection.read().unwrap();
+        assert!(matches!(connection.state(), ConnectionState::Login));
+        assert_eq!(connection.transfer_origin, Some(("lobby.example.com".to_string(), 25566)));
+    }
+
+    #[tokio::test]
+    async fn joining_spawns_existing_players() {
+        let assets = Arc::new(Assets::for_test().await);
+        for name in ["alice", "bob"] {
+            let other = Arc::new(RwLock::new(ConnectionInfo::new()));
+            {
+                let mut other = other.write().unwrap();
+                other.username = name.into();
+                other.uuid = name_uuid(name.into());
+                other.set_state(ConnectionState::Play);
+            }
+            assets.connections.write().await.push(serve::ConnectionActorHandle::detached(other).0);
+        }
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Configuration);
+        assets.connections.write().await.push(serve::ConnectionActorHandle::detached(connection.clone()).0);
+
+        let res = ConfigurationFinish {}.handle(connection, assets).await.unwrap();
+        let spawns = res.iter().filter(|p| matches!(p, ClientPackets::SpawnPlayer(_))).count();
+        assert_eq!(spawns, 2);
+        assert!(matches!(res.first(), Some(ClientPackets::PlayLogin(_))));
+    }
+
+    #[tokio::test]
+    async fn joining_adds_world_player() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let uuid = name_uuid("OfflinePlayer:steve".into());
+        {
+            let mut connection = connection.write().unwrap();
+            connection.username = "steve".into();
+            connection.uuid = uuid;
+            connection.set_state(ConnectionState::Configuration);
+        }
+        assets.connections.write().await.push(serve::ConnectionActorHandle::detached(connection.clone()).0);
+
+        ConfigurationFinish {}.handle(connection.clone(), assets.clone()).await.unwrap();
+        {
+            let world = assets.world.read().await;
+            assert_eq!(world.players().count(), 1);
+            assert_eq!(world.player(uuid).unwrap().username, "steve");
+        }
+        serve::leave(&connection, &assets).await;
+        assert!(assets.world.read().await.player(uuid).is_none());
+    }
+
+    fn client_info() -> ClientInfo {
+        ClientInfo {
+            locale: "en_us".into(),
+            view_distance: 10,
+            chat_mode: 0.into(),
+            chat_colors: true,
+            displayed_skin_parts: 0x7f,
+            main_hand: 1.into(),
+            enable_text_filtering: false,
+            allow_server_listings: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn configuration_waits_for_pong() {
+        let mut assets = Assets::for_test().await;
+        assets.configuration_ping = true;
+        let assets = Arc::new(assets);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Configuration);
+
+        let res = client_info().handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::ConfigurationPing(_)]));
+        let id = connection.read().unwrap().configuration_ping_id.unwrap();
+        let res = ConfigurationPong { id: id.wrapping_add(1) }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(res.is_empty());
+        let res = ConfigurationPong { id }.handle(connection.clone(), assets).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::ConfigurationFinish(_)]));
+        assert_eq!(connection.read().unwrap().configuration_ping_id, None);
+    }
+
+    #[tokio::test]
+    async fn configuration_finishes_without_ping() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let res = client_info().handle(connection, assets).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::ConfigurationFinish(_)]));
+    }
+
+    #[tokio::test]
+    async fn login_attempts_limited_per_address() {
+        let assets = Arc::new(Assets::for_test().await);
+        let login = |address: &str| {
+            let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+            connection.write().unwrap().address = Some(address.parse().unwrap());
+            let assets = assets.clone();
+            async move { LoginStart { name: "steve".into() }.handle(connection, assets).await.unwrap() }
+        };
+        // the test assets allow three attempts per minute
+        for _ in 0..3 {
+            assert!(matches!(login("10.0.0.1").await.as_slice(), [ClientPackets::LoginSuccess(_)]));
+        }
+        assert!(matches!(login("10.0.0.1").await.as_slice(), [ClientPackets::LoginDisconnect(_)]));
+        assert!(matches!(login("10.0.0.2").await.as_slice(), [ClientPackets::LoginSuccess(_)]));
+    }
+
+    #[tokio::test]
+    async fn forwarded_login_skips_authentication() {
+        let mut assets = Assets::for_test().await;
+        assets.online = true;
+        assets.bungee_forwarding = true;
+        let assets = Arc::new(assets);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let handshake = Handshake {
+            prot_version: 764.into(),
+            server_address: "localhost\x00203.0.113.7\x00069a79f444e94726a5befca90e38aaf5".into(),
+            server_port: 25565,
+            next_state: 2.into(),
+        };
+        assert!(handshake.handle(connection.clone(), assets.clone()).await.unwrap().is_empty());
+        assert_eq!(connection.read().unwrap().address, Some("203.0.113.7".parse().unwrap()));
+
+        let res = LoginStart { name: "notch".into() }.handle(connection.clone(), assets).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::LoginSuccess(_)]));
+        assert_eq!(connection.read().unwrap().uuid.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+    }
+
+    #[tokio::test]
+    async fn unforwarded_handshake_rejected() {
+        let mut assets = Assets::for_test().await;
+        assets.bungee_forwarding = true;
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let handshake = Handshake {
+            prot_version: 764.into(),
+            server_address: "localhost".into(),
+            server_port: 25565,
+            next_state: 2.into(),
+        };
+        match handshake.handle(connection.clone(), Arc::new(assets)).await {
+            Err(e) => assert!(matches!(e, ProtError::NotForwarded) && e.is_fatal()),
+            Ok(_) => panic!("Expected the handshake to be rejected"),
+        }
+        assert!(matches!(connection.read().unwrap().state(), ConnectionState::Login));
+    }
+
+    #[tokio::test]
+    async fn picked_item_swapped_into_selected_slot() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let stone = ItemStack::new(assets.items.item_id("minecraft:stone").unwrap(), 5);
+        {
+            let mut connection = connection.write().unwrap();
+            connection.held_slot = 2;
+            connection.inventory.set(20, Some(stone.clone()));
+        }
+
+        let res = PickItem { slot_to_use: 20.into() }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::SetContainerSlot(_), ClientPackets::SetContainerSlot(_)]));
+        {
+            let connection = connection.read().unwrap();
+            assert_eq!(connection.inventory.get(Inventory::hotbar_slot(2)), Some(&stone));
+            assert_eq!(connection.inventory.get(20), None);
+        }
+
+        // the hotbar comes first in the player's inventory
+        let res = PickItem { slot_to_use: 2.into() }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(connection.read().unwrap().inventory.get(Inventory::hotbar_slot(2)), Some(&stone));
+        let res = PickItem { slot_to_use: 36.into() }.handle(connection.clone(), assets).await.unwrap();
+        assert!(res.is_empty());
+    }
+
+    #[tokio::test]
+    async fn held_block_placed() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        {
+            let mut connection = connection.write().unwrap();
+            connection.position = PosRotGround { x: 5.5, y: -16.0, z: 7.5, ..connection.position.clone() };
+        }
+        // the top of the filled sections of new_grass is at y -17
+        let place = |hand| UseItemOn {
+            hand,
+            location: Position::new(5, -17, 5),
+            face: BlockFace::Top,
+            cursor_x: 0.5,
+            cursor_y: 1.0,
+            cursor_z: 0.5,
+            inside_block: false,
+            sequence: 1.into(),
+        };
+        let target = Position::new(5, -16, 5);
+
+        let res = place(Hand::MainHand).handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::AcknowledgeBlockChange(_)]));
+        assert_eq!(assets.world.read().await.block(target), Some(AIR));
+
+        let planks = assets.items.item_id("minecraft:oak_planks").unwrap();
+        connection.write().unwrap().inventory.set(Inventory::hand_slot(Hand::OffHand, 0), Some(ItemStack::new(planks, 1)));
+        let res = place(Hand::OffHand).handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::BlockUpdate(_), ClientPackets::AcknowledgeBlockChange(_)]));
+        let block = assets.world.read().await.block(target).unwrap();
+        assert_eq!(assets.global_palette.block_name(block).unwrap().name, "minecraft:oak_planks");
+    }
+
+    #[tokio::test]
+    async fn swing_arm_broadcast() {
+        let assets = Arc::new(Assets::for_test().await);
+        let (handle, mut receiver) = serve::ConnectionActorHandle::detached(Arc::new(RwLock::new(ConnectionInfo::new())));
+        assets.connections.write().await.push(handle);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let uuid = name_uuid("OfflinePlayer:steve".into());
+        {
+            let mut connection = connection.write().unwrap();
+            connection.uuid = uuid;
+            connection.entity_id = 7;
+        }
+
+        let res = SwingArm { hand: Hand::MainHand }.handle(connection.clone(), assets).await.unwrap();
+        assert!(res.is_empty());
+        let expected = client::EntityAnimation::new(7.into(), 0);
+        match receiver.recv().await {
+            Some(serve::ConnectionActorMessage::Broadcast(Some(except), ClientPackets::EntityAnimation(animation))) => {
+                assert_eq!(except, uuid);
+                assert_eq!(format!("{animation:?}"), format!("{expected:?}"));
+            }
+            _ => panic!("Expected an entity animation broadcast"),
+        }
+    }
+
+    #[tokio::test]
+    async fn creative_mode_slot_set() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().game_mode = GameMode::Creative;
+        let stone = ItemStack::new(1, 64);
+
+        let packet = SetCreativeModeSlot { slot: 36, clicked_item: stone.clone().into() };
+        packet.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(connection.read().unwrap().inventory.get(36), Some(&stone));
+
+        let packet = SetCreativeModeSlot { slot: INVENTORY_SIZE as i16, clicked_item: stone.into() };
+        packet.handle(connection.clone(), assets.clone()).await.unwrap();
+        let packet = SetCreativeModeSlot { slot: 36, clicked_item: Slot::empty() };
+        connection.write().unwrap().game_mode = GameMode::Survival;
+        packet.handle(connection.clone(), assets).await.unwrap();
+        assert_eq!(connection.read().unwrap().inventory.get(36).map(|stack| stack.count), Some(64));
+    }
+
+    #[tokio::test]
+    async fn respawn_only_after_death() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let res = ClientStatus { action: 0.into() }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(res.is_empty());
+
+        connection.write().unwrap().op_level = GAMEMASTER_LEVEL;
+        let res = commands::execute(&connection, &assets, "kill").await;
+        assert!(matches!(res.as_slice(), [ClientPackets::SetHealth(_)]));
+        assert!(connection.read().unwrap().is_dead());
+        let res = ClientStatus { action: 0.into() }.handle(connection.clone(), assets).await.unwrap();
+        assert!(matches!(res.first(), Some(ClientPackets::Respawn(_))));
+        assert!(!connection.read().unwrap().is_dead());
+    }
+
+    #[tokio::test]
+    async fn flying_tracked_if_allowed() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        PlayerAbilities { flags: client::ABILITY_FLYING }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(!connection.read().unwrap().flying);
+
+        connection.write().unwrap().game_mode = GameMode::Creative;
+        PlayerAbilities { flags: client::ABILITY_FLYING }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(connection.read().unwrap().flying);
+        PlayerAbilities { flags: 0 }.handle(connection.clone(), assets).await.unwrap();
+        assert!(!connection.read().unwrap().flying);
+    }
+
+    #[tokio::test]
+    async fn held_slot_validated() {
+        let assets = Arc::new(Assets::for_test().await);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+
+        SetHeldItem { slot: 3 }.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(connection.read().unwrap().held_slot, 3);
+
+        let invalid = SetHeldItem { slot: 9 };
+        match invalid.handle(connection.clone(), assets).await {
+            Err(err) => assert!(matches!(err, ProtError::InvalidHeldSlot(9)) && err.is_fatal()),
+            Ok(_) => panic!("Expected slot 9 to be rejected"),
+        }
+        assert_eq!(connection.read().unwrap().held_slot, 3);
+    }
+
+    async fn fly_up(allow_flight: bool) -> Result<(), ProtError> {
+        let mut assets = Assets::for_test().await;
+        assets.allow_flight = allow_flight;
+        let assets = Arc::new(assets);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        for i in 0..200 {
+            let update = SetPlayerPosition { x: 0.0, y: 64.0 + i as f64 * 0.1, z: 0.0, on_ground: false };
+            update.handle(connection.clone(), assets.clone()).await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flying_kicked_unless_allowed() {
+        match fly_up(false).await {
+            Err(e) => {
+                assert!(matches!(e, ProtError::FlyingNotAllowed) && e.is_fatal());
+                assert_eq!(e.disconnect_reason(), Chat::new_text("Flying is not enabled on this server".into()));
+            }
+            Ok(_) => panic!("Expected the flying player to be kicked"),
+        }
+        assert!(fly_up(true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn chat_command_dispatched() {
+        let assets = Arc::new(Assets::for_test().await);
+        let mut packet = vec![];
+        "tp 0 64 0".to_string().write(&mut packet).await.unwrap();
+        0i64.write(&mut packet).await.unwrap(); // timestamp
+        0i64.write(&mut packet).await.unwrap(); // salt
+        // a signed argument, which isn't checked
+        VarInt::from(1).write(&mut packet).await.unwrap();
+        "location".to_string().write(&mut packet).await.unwrap();
+        [0u8; 256].write(&mut packet).await.unwrap();
+        VarInt::from(0).write(&mut packet).await.unwrap();
+        [0u8; 3].write(&mut packet).await.unwrap();
+        let mut slice = packet.as_slice();
+        let command = ChatCommand::read(&mut slice).await.unwrap();
+        assert!(slice.is_empty());
+        assert_eq!(command.command.0, "tp 0 64 0");
+
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().op_level = GAMEMASTER_LEVEL;
+        let response = command.handle(connection.clone(), assets).await.unwrap();
+        assert!(matches!(response.as_slice(), [ClientPackets::SynchronizePlayerPosition(_)]));
+        let position = connection.read().unwrap().position.clone();
+        assert_eq!((position.x, position.y, position.z), (0.0, 64.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn chat_message_broadcast() {
+        let assets = Arc::new(Assets::for_test().await);
+        let other = Arc::new(RwLock::new(ConnectionInfo::new()));
+        other.write().unwrap().set_state(ConnectionState::Play);
+        let (handle, mut receiver) = serve::ConnectionActorHandle::detached(other);
+        assets.connections.write().await.push(handle);
+
+        let mut packet = vec![];
+        "hi".to_string().write(&mut packet).await.unwrap();
+        0i64.write(&mut packet).await.unwrap();
+        0i64.write(&mut packet).await.unwrap();
+        false.write(&mut packet).await.unwrap();
+        VarInt::from(0).write(&mut packet).await.unwrap();
+        [0u8; 3].write(&mut packet).await.unwrap();
+        let mut slice = packet.as_slice();
+        let message = ChatMessage::read(&mut slice).await.unwrap();
+        assert!(slice.is_empty());
+
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().username = "steve".into();
+        message.handle(connection.clone(), assets).await.unwrap();
+        // the client renders this as "<steve> hi" using the minecraft:chat chat type
+        let expected = client::DisguisedChatMessage::from_player(&connection.read().unwrap(), Chat::new_text("hi".into()), 0);
+        match receiver.recv().await {
+            Some(serve::ConnectionActorMessage::Broadcast(None, ClientPackets::DisguisedChatMessage(chat))) => {
+                assert_eq!(format!("{chat:?}"), format!("{expected:?}"));
+            }
+            _ => panic!("Expected a chat message broadcast"),
+        }
+    }
+
+    #[tokio::test]
+    async fn long_chat_message_rejected() {
+        let mut assets = Assets::for_test().await;
+        assets.max_chat_length = 5;
+        let assets = Arc::new(assets);
+        let other = Arc::new(RwLock::new(ConnectionInfo::new()));
+        other.write().unwrap().set_state(ConnectionState::Play);
+        let (handle, mut receiver) = serve::ConnectionActorHandle::detached(other);
+        assets.connections.write().await.push(handle);
+
+        let message = |text: &str| ChatMessage {
+            message: LimitedString(text.into()),
+            timestamp: 0,
+            salt: 0,
+            has_signature: false,
+            signature: None,
+            message_count: 0.into(),
+            acknowledged: [0; 3],
+        };
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let res = message("hello world").handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+        assert!(receiver.try_recv().is_err());
+
+        // the limit counts characters, not bytes
+        let res = message("héllo").handle(connection, assets).await.unwrap();
+        assert!(res.is_empty());
+        assert!(matches!(receiver.recv().await, Some(serve::ConnectionActorMessage::Broadcast(None, ClientPackets::DisguisedChatMessage(_)))));
+    }
+
+    #[tokio::test]
+    async fn nick_updates_tab_list() {
+        let assets = Arc::new(Assets::for_test().await);
+        let (handle, mut receiver) = serve::ConnectionActorHandle::detached(Arc::new(RwLock::new(ConnectionInfo::new())));
+        assets.connections.write().await.push(handle);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().uuid = Uuid::from_u128(7);
+        let nick = ChatCommand {
+            command: LimitedString("nick Bob".into()),
+            timestamp: 0,
+            salt: 0,
+            argument_signatures: vec![].into(),
+            message_count: 0.into(),
+            acknowledged: [0; 3],
+        };
+        nick.handle(connection, assets).await.unwrap();
+        let Some(serve::ConnectionActorMessage::Broadcast(None, info @ ClientPackets::PlayerInfoUpdate(_))) = receiver.recv().await else {
+            panic!("Expected a player info broadcast");
+        };
+        let buf = serialize(&[info]).await;
+        // the display name action with a single entry, which has a display name
+        assert_eq!(&buf[1..4], &[0x3c, client::PLAYER_INFO_DISPLAY_NAME, 1]);
+        assert_eq!(&buf[4..20], &7u128.to_be_bytes());
+        assert_eq!(buf[20], 1);
+    }
+
+    #[tokio::test]
+    async fn long_command_rejected() {
+        let mut assets = Assets::for_test().await;
+        assets.max_chat_length = 300;
+        let assets = Arc::new(assets);
+        let command = |text: String| ChatCommand {
+            command: LimitedString(text),
+            timestamp: 0,
+            salt: 0,
+            argument_signatures: vec![].into(),
+            message_count: 0.into(),
+            acknowledged: [0; 3],
+        };
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let res = command(format!("nick {}", "a".repeat(300))).handle(connection.clone(), assets.clone()).await.unwrap();
+        assert!(matches!(res.as_slice(), [ClientPackets::SystemChatMessage(_)]));
+        assert_eq!(connection.read().unwrap().display_name, None);
+
+        // longer than vanilla allows, but within the server's limit
+        let res = command(format!("nick {}", "a".repeat(290))).handle(connection.clone(), assets).await.unwrap();
+        assert!(res.is_empty());
+        assert!(connection.read().unwrap().display_name.is_some());
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
 use async_nbt::io::Flavor;
 use async_nbt::NbtCompound;
 use async_trait::async_trait;
@@ -5,14 +9,15 @@ use serde_json::Map;
 use tokio::io::{AsyncRead, AsyncWrite};
 use uuid::Uuid;
 
-use rustcraft_derive::{ReadProt, SizedProt, WriteProt};
+use rustcraft_derive::{ProtEnum, ReadProt, SizedProt, WriteProt};
 
+use crate::err::ProtoError;
 use crate::protocol_types::primitives::{SizedVec, VarInt};
 use crate::protocol_types::traits::{ReadProt, SizedProt, WriteProt};
 
 #[async_trait]
 impl ReadProt for Uuid {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
@@ -26,7 +31,7 @@ impl ReadProt for Uuid {
 
 #[async_trait]
 impl WriteProt for Uuid {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let buf = self.as_u128();
         let buf1 = (buf >> 8 * 8) as u64;
         let buf2 = buf as u64;
@@ -42,6 +47,75 @@ impl SizedProt for Uuid {
     }
 }
 
+/**
+ * A namespaced identifier like `minecraft:stone`, serialized as a string.
+ * The namespace defaults to `minecraft` if it is omitted.
+ */
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub(crate) struct Identifier {
+    pub(crate) namespace: String,
+    pub(crate) path: String,
+}
+
+impl Identifier {
+    pub(crate) fn new(namespace: &str, path: &str) -> Result<Self, ProtoError> {
+        let valid_namespace = namespace
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '.' | '-'));
+        let valid_path = path
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '.' | '-' | '/'));
+        if namespace.is_empty() || path.is_empty() || !valid_namespace || !valid_path {
+            return Err(ProtoError::InvalidIdentifier(format!("{namespace}:{path}")));
+        }
+        Ok(Self {
+            namespace: namespace.into(),
+            path: path.into(),
+        })
+    }
+}
+
+impl FromStr for Identifier {
+    type Err = ProtoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((namespace, path)) => Self::new(namespace, path),
+            None => Self::new("minecraft", s),
+        }
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+#[async_trait]
+impl ReadProt for Identifier {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        String::read(stream).await?.parse()
+    }
+}
+
+#[async_trait]
+impl WriteProt for Identifier {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.to_string().write(stream).await
+    }
+}
+
+impl SizedProt for Identifier {
+    fn prot_size(&self) -> usize {
+        let len = self.namespace.len() + 1 + self.path.len();
+        VarInt::from(len).prot_size() + len
+    }
+}
+
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub(crate) struct Position {
     pub(crate) x: i32,
@@ -55,11 +129,36 @@ impl Position {
     pub(crate) fn new(x: i32, y: i32, z: i32) -> Self {
         Self { x, y, z }
     }
+
+    /**
+     * Returns the position of the neighboring block in the direction of the given face.
+     */
+    pub(crate) fn offset(&self, face: BlockFace) -> Self {
+        let (dx, dy, dz) = match face {
+            BlockFace::Bottom => (0, -1, 0),
+            BlockFace::Top => (0, 1, 0),
+            BlockFace::North => (0, 0, -1),
+            BlockFace::South => (0, 0, 1),
+            BlockFace::West => (-1, 0, 0),
+            BlockFace::East => (1, 0, 0),
+        };
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    // whether the coordinates fit into the 26, 26 and 12 bits they are packed into
+    fn encodable(&self) -> bool {
+        const HORIZONTAL: std::ops::Range<i32> = -(1 << 25)..(1 << 25);
+        const VERTICAL: std::ops::Range<i32> = -(1 << 11)..(1 << 11);
+        HORIZONTAL.contains(&self.x) && HORIZONTAL.contains(&self.z) && VERTICAL.contains(&self.y)
+    }
 }
 
 #[async_trait]
 impl WriteProt for Position {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        if !self.encodable() {
+            return Err(ProtoError::PositionOutOfRange(*self));
+        }
         let int = (((self.x & 0x3FFFFFF) as i64) << 38)
             | (((self.z & 0x3FFFFFF) as i64) << 12)
             | (self.y as i64 & 0xFFF);
@@ -69,7 +168,7 @@ impl WriteProt for Position {
 
 #[async_trait]
 impl ReadProt for Position {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
@@ -90,23 +189,23 @@ impl SizedProt for Position {
 
 #[async_trait]
 impl WriteProt for NbtCompound {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         async_nbt::io::write_nbt(stream, None, self, Flavor::Uncompressed)
             .await
-            .or_else(|x| Err(format!("NBT error: {:?}", x)))?;
+            .or_else(|x| Err(ProtoError::Nbt(format!("{:?}", x))))?;
         Ok(())
     }
 }
 
 #[async_trait]
 impl ReadProt for NbtCompound {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         Ok(async_nbt::io::read_nbt(stream, Flavor::Uncompressed, true)
             .await
-            .or_else(|x| Err(format!("NBT error: {:?}", x)))?
+            .or_else(|x| Err(ProtoError::Nbt(format!("{:?}", x))))?
             .0)
     }
 }
@@ -174,7 +273,7 @@ enum RecipeKind {
 
 #[async_trait]
 impl ReadProt for Recipe {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
@@ -267,14 +366,14 @@ impl ReadProt for Recipe {
                     addition: RecipeIngredient::read(stream).await?,
                 },
             },
-            _ => return Err("Unknown recipe type".into()),
+            _ => return Err(ProtoError::InvalidEnumTag("recipe type", typ)),
         })
     }
 }
 
 #[async_trait]
 impl WriteProt for Recipe {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         self.typ.write(stream).await?;
         self.id.write(stream).await?;
         match &self.data {
@@ -435,16 +534,22 @@ impl SizedProt for Recipe {
 type RecipeIngredient = SizedVec<Slot>;
 
 #[derive(SizedProt, WriteProt, Debug, Clone)]
-struct Slot {
-    present: bool,
-    item_id: Option<VarInt>,
-    item_count: Option<u8>,
-    nbt: Option<NbtCompound>,
+pub(crate) struct Slot {
+    pub(crate) present: bool,
+    pub(crate) item_id: Option<VarInt>,
+    pub(crate) item_count: Option<u8>,
+    pub(crate) nbt: Option<NbtCompound>,
+}
+
+impl Slot {
+    pub(crate) fn empty() -> Self {
+        Self { present: false, item_id: None, item_count: None, nbt: None }
+    }
 }
 
 #[async_trait]
 impl ReadProt for Slot {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
@@ -473,6 +578,115 @@ impl ReadProt for Slot {
     }
 }
 
+/**
+ * Entry of a Player Info Update packet. Only the fields of the packet's actions are set: the name and properties
+ * for Add Player, `listed` for Update Listed and the display name for Update Display Name.
+ */
+#[derive(SizedProt, WriteProt, Debug, Clone)]
+pub(crate) struct PlayerInfoEntry {
+    pub(crate) uuid: Uuid,
+    pub(crate) name: Option<String>,
+    pub(crate) num_properties: Option<VarInt>,
+    pub(crate) listed: Option<bool>,
+    pub(crate) has_display_name: Option<bool>,
+    pub(crate) display_name: Option<Chat>,
+}
+
+impl PlayerInfoEntry {
+    /**
+     * The entry adding a listed player without properties, along with their display name.
+     */
+    pub(crate) fn added(uuid: Uuid, name: String, display_name: Option<Chat>) -> Self {
+        Self {
+            name: Some(name),
+            num_properties: Some(0.into()),
+            listed: Some(true),
+            ..Self::display_name(uuid, display_name)
+        }
+    }
+
+    /**
+     * The entry updating the display name of a player, None resets it to their username.
+     */
+    pub(crate) fn display_name(uuid: Uuid, display_name: Option<Chat>) -> Self {
+        Self {
+            uuid,
+            name: None,
+            num_properties: None,
+            listed: None,
+            has_display_name: Some(display_name.is_some()),
+            display_name,
+        }
+    }
+}
+
+// node types of the command graph, stored in the lowest two bits of the flags
+const COMMAND_NODE_ROOT: u8 = 0;
+const COMMAND_NODE_LITERAL: u8 = 1;
+const COMMAND_NODE_ARGUMENT: u8 = 2;
+const COMMAND_NODE_EXECUTABLE: u8 = 0x04;
+
+/**
+ * A node of the command graph sent in the Commands packet, children are indices into the node list.
+ * Only parsers without properties are supported, redirects and custom suggestions aren't.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct CommandNode {
+    flags: u8,
+    children: SizedVec<VarInt>,
+    name: Option<String>,
+    parser: Option<VarInt>,
+}
+
+impl CommandNode {
+    pub(crate) fn root(children: Vec<VarInt>) -> Self {
+        Self { flags: COMMAND_NODE_ROOT, children: children.into(), name: None, parser: None }
+    }
+
+    pub(crate) fn literal(name: &str, children: Vec<VarInt>, executable: bool) -> Self {
+        let flags = COMMAND_NODE_LITERAL | if executable { COMMAND_NODE_EXECUTABLE } else { 0 };
+        Self { flags, children: children.into(), name: Some(name.into()), parser: None }
+    }
+
+    pub(crate) fn argument(name: &str, parser: VarInt, children: Vec<VarInt>, executable: bool) -> Self {
+        let flags = COMMAND_NODE_ARGUMENT | if executable { COMMAND_NODE_EXECUTABLE } else { 0 };
+        Self { flags, children: children.into(), name: Some(name.into()), parser: Some(parser) }
+    }
+}
+
+#[async_trait]
+impl WriteProt for CommandNode {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.flags.write(stream).await?;
+        self.children.write(stream).await?;
+        if let Some(name) = &self.name {
+            name.write(stream).await?;
+        }
+        if let Some(parser) = &self.parser {
+            parser.write(stream).await?;
+        }
+        Ok(())
+    }
+}
+
+impl SizedProt for CommandNode {
+    fn prot_size(&self) -> usize {
+        self.flags.prot_size()
+            + self.children.prot_size()
+            + self.name.as_ref().map_or(0, |name| name.prot_size())
+            + self.parser.as_ref().map_or(0, |parser| parser.prot_size())
+    }
+}
+
+/**
+ * Signature of a message argument of a chat command.
+ */
+#[derive(SizedProt, ReadProt, Debug, Clone)]
+pub(crate) struct ArgumentSignature {
+    name: String,
+    signature: [u8; 256],
+}
+
 #[derive(SizedProt, WriteProt, ReadProt, Debug, Clone)]
 pub(crate) struct TagGroup {
     typ: String, // minecraft:block, minecraft:item, minecraft:fluid, minecraft:entity_type, and minecraft:game_event
@@ -513,12 +727,31 @@ pub(crate) struct BlockEntity {
 #[derive(Debug, Clone)]
 pub(crate) struct Chat {
     map: Map<String, serde_json::Value>,
+    // serialized form of `map`, shared by `prot_size` and `write` and reset by every builder method
+    json: OnceLock<String>,
 }
+
+impl PartialEq for Chat {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
 impl Chat {
     pub(crate) fn new_text(text: String) -> Self {
         let mut map = serde_json::map::Map::new();
         map.insert("text".into(), serde_json::Value::String(text));
-        Self { map }
+        Self { map, json: OnceLock::new() }
+    }
+
+    pub(crate) fn from_json(json: &str) -> Result<Self, ProtoError> {
+        let map = serde_json::from_str(json)?;
+        Ok(Self { map, json: OnceLock::from(json.to_string()) })
+    }
+
+    pub(crate) fn json(&self) -> &String {
+        self.json
+            .get_or_init(|| serde_json::to_string(&serde_json::Value::Object(self.map.clone())).unwrap())
     }
 
     pub(crate) fn with_extra(mut self, new_extra: Chat) -> Self {
@@ -533,24 +766,28 @@ impl Chat {
                 serde_json::Value::Array(vec![serde_json::Value::Object(new_extra.map)]),
             );
         }
+        self.json = OnceLock::new();
         self
     }
 
     pub(crate) fn with_bold(mut self, bold: bool) -> Self {
         self.map
             .insert("bold".into(), serde_json::Value::Bool(bold));
+        self.json = OnceLock::new();
         self
     }
 
     pub(crate) fn with_italic(mut self, italic: bool) -> Self {
         self.map
             .insert("italic".into(), serde_json::Value::Bool(italic));
+        self.json = OnceLock::new();
         self
     }
 
     pub(crate) fn with_underlined(mut self, underlined: bool) -> Self {
         self.map
             .insert("underlined".into(), serde_json::Value::Bool(underlined));
+        self.json = OnceLock::new();
         self
     }
 
@@ -559,48 +796,47 @@ impl Chat {
             "strikethrough".into(),
             serde_json::Value::Bool(strikethrough),
         );
+        self.json = OnceLock::new();
         self
     }
 
     pub(crate) fn with_obfuscated(mut self, obfuscated: bool) -> Self {
         self.map
             .insert("obfuscated".into(), serde_json::Value::Bool(obfuscated));
+        self.json = OnceLock::new();
         self
     }
 
     pub(crate) fn with_color(mut self, color: String) -> Self {
         self.map
             .insert("color".into(), serde_json::Value::String(color));
+        self.json = OnceLock::new();
         self
     }
 }
 
 #[async_trait]
 impl ReadProt for Chat {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> {
-        let json = String::read(stream).await?;
-        let map = serde_json::from_str(&json).unwrap();
-        Ok(Self { map })
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError> {
+        Self::from_json(&String::read(stream).await?)
     }
 }
 
 #[async_trait]
 impl WriteProt for Chat {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        let json = serde_json::to_string(&serde_json::Value::Object(self.map.clone())).unwrap();
-        json.write(stream).await?;
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.json().write(stream).await?;
         Ok(())
     }
 }
 
 impl SizedProt for Chat {
     fn prot_size(&self) -> usize {
-        let json = serde_json::to_string(&serde_json::Value::Object(self.map.clone())).unwrap();
-        json.prot_size()
+        self.json().prot_size()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum GameMode {
     Survival,
     Creative,
@@ -608,6 +844,17 @@ pub(crate) enum GameMode {
     Spectator,
 }
 
+impl GameMode {
+    pub(crate) fn id(&self) -> u8 {
+        match self {
+            GameMode::Survival => 0,
+            GameMode::Creative => 1,
+            GameMode::Adventure => 2,
+            GameMode::Spectator => 3,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) enum WinGame {
     JustRespawn,
@@ -627,12 +874,14 @@ pub(crate) enum GameEvent {
     PufferfishSting,
     ElderGuardianAppearance,
     SetRespawnScreen(bool),
-    SetLimitedCrafting(bool)
+    SetLimitedCrafting(bool),
+    // keeps the loading screen up until the chunks around the player have arrived
+    StartWaitingForLevelChunks,
 }
 
 #[async_trait]
 impl WriteProt for GameEvent {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         match self {
             GameEvent::NoRespawnBlock => {
                 0u8.write(stream).await?;
@@ -648,13 +897,7 @@ impl WriteProt for GameEvent {
             }
             GameEvent::SetGameMode(mode) => {
                 3u8.write(stream).await?;
-                let val = match mode {
-                    GameMode::Survival => 0,
-                    GameMode::Creative => 1,
-                    GameMode::Adventure => 2,
-                    GameMode::Spectator => 3
-                };
-                (val as f32).write(stream).await?;
+                (mode.id() as f32).write(stream).await?;
             }
             GameEvent::WinGame(mode) => {
                 4u8.write(stream).await?;
@@ -700,6 +943,10 @@ impl WriteProt for GameEvent {
                 };
                 (val as f32).write(stream).await?;
             }
+            GameEvent::StartWaitingForLevelChunks => {
+                13u8.write(stream).await?;
+                0f32.write(stream).await?;
+            }
         }
         Ok(())
     }
@@ -711,6 +958,117 @@ impl SizedProt for GameEvent {
     }
 }
 
+// flags of a boss bar
+pub(crate) const BOSS_BAR_DARKEN_SKY: u8 = 0x01;
+pub(crate) const BOSS_BAR_DRAGON_MUSIC: u8 = 0x02;
+pub(crate) const BOSS_BAR_FOG: u8 = 0x04;
+
+/**
+ * What a BossBar packet does with the boss bar it refers to. Health goes from 0 to 1, the color and
+ * division (number of notches) are indexes into their lists in the protocol.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum BossBarAction {
+    Add {
+        title: Chat,
+        health: f32,
+        color: VarInt,
+        division: VarInt,
+        flags: u8,
+    },
+    Remove,
+    UpdateHealth(f32),
+    UpdateTitle(Chat),
+    UpdateStyle {
+        color: VarInt,
+        division: VarInt,
+    },
+    UpdateFlags(u8),
+}
+
+impl BossBarAction {
+    fn action_id(&self) -> VarInt {
+        match self {
+            BossBarAction::Add { .. } => 0,
+            BossBarAction::Remove => 1,
+            BossBarAction::UpdateHealth(_) => 2,
+            BossBarAction::UpdateTitle(_) => 3,
+            BossBarAction::UpdateStyle { .. } => 4,
+            BossBarAction::UpdateFlags(_) => 5,
+        }
+        .into()
+    }
+}
+
+#[async_trait]
+impl WriteProt for BossBarAction {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.action_id().write(stream).await?;
+        match self {
+            BossBarAction::Add { title, health, color, division, flags } => {
+                title.write(stream).await?;
+                health.write(stream).await?;
+                color.write(stream).await?;
+                division.write(stream).await?;
+                flags.write(stream).await?;
+            }
+            BossBarAction::Remove => {}
+            BossBarAction::UpdateHealth(health) => health.write(stream).await?,
+            BossBarAction::UpdateTitle(title) => title.write(stream).await?,
+            BossBarAction::UpdateStyle { color, division } => {
+                color.write(stream).await?;
+                division.write(stream).await?;
+            }
+            BossBarAction::UpdateFlags(flags) => flags.write(stream).await?,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReadProt for BossBarAction {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        let action = VarInt::read(stream).await?;
+        Ok(match action.value {
+            0 => BossBarAction::Add {
+                title: Chat::read(stream).await?,
+                health: f32::read(stream).await?,
+                color: VarInt::read(stream).await?,
+                division: VarInt::read(stream).await?,
+                flags: u8::read(stream).await?,
+            },
+            1 => BossBarAction::Remove,
+            2 => BossBarAction::UpdateHealth(f32::read(stream).await?),
+            3 => BossBarAction::UpdateTitle(Chat::read(stream).await?),
+            4 => BossBarAction::UpdateStyle {
+                color: VarInt::read(stream).await?,
+                division: VarInt::read(stream).await?,
+            },
+            5 => BossBarAction::UpdateFlags(u8::read(stream).await?),
+            _ => return Err(ProtoError::InvalidEnumTag("boss bar action", action.to_string())),
+        })
+    }
+}
+
+impl SizedProt for BossBarAction {
+    fn prot_size(&self) -> usize {
+        self.action_id().prot_size()
+            + match self {
+                BossBarAction::Add { title, color, division, .. } => {
+                    title.prot_size() + 4 + color.prot_size() + division.prot_size() + 1
+                }
+                BossBarAction::Remove => 0,
+                BossBarAction::UpdateHealth(_) => 4,
+                BossBarAction::UpdateTitle(title) => title.prot_size(),
+                BossBarAction::UpdateStyle { color, division } => color.prot_size() + division.prot_size(),
+                BossBarAction::UpdateFlags(_) => 1,
+            }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct PosRotGround {
     pub(crate) x: f64,
@@ -721,6 +1079,42 @@ pub(crate) struct PosRotGround {
     pub(crate) on_ground: bool,
 }
 
+impl PosRotGround {
+    /**
+     * Encodes the movement from this position to `new` as the deltas used by the entity movement packets,
+     * or None if the player moved more than 8 blocks along an axis and has to be teleported instead.
+     */
+    pub(crate) fn delta_to(&self, new: &PosRotGround) -> Option<(i16, i16, i16)> {
+        fn delta(new: f64, old: f64) -> Option<i16> {
+            let delta = ((new * 32.0 - old * 32.0) * 128.0) as i64;
+            i16::try_from(delta).ok()
+        }
+        Some((delta(new.x, self.x)?, delta(new.y, self.y)?, delta(new.z, self.z)?))
+    }
+}
+
+/**
+ * A rotation in steps of 1/256 of a full turn.
+ */
+#[derive(ReadProt, WriteProt, SizedProt, Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Angle(pub(crate) u8);
+
+/**
+ * The offset of a block from another one, each coordinate within -128..=127.
+ */
+#[derive(ReadProt, WriteProt, SizedProt, Debug, Copy, Clone, PartialEq)]
+pub(crate) struct BlockOffset {
+    pub(crate) x: i8,
+    pub(crate) y: i8,
+    pub(crate) z: i8,
+}
+
+impl From<f64> for Angle {
+    fn from(degrees: f64) -> Self {
+        Self((degrees.rem_euclid(360.0) / 360.0 * 256.0) as u8)
+    }
+}
+
 impl From<PosRotGround> for Position {
     fn from(pos: PosRotGround) -> Self {
         Self {
@@ -732,7 +1126,7 @@ impl From<PosRotGround> for Position {
 }
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, ProtEnum)]
 pub(crate) enum PlayerActions {
     StartDig,
     CancelDig,
@@ -743,25 +1137,308 @@ pub(crate) enum PlayerActions {
     SwapHands,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum BlockFace {
+    Bottom,
+    Top,
+    North,
+    South,
+    West,
+    East,
+}
+
 #[async_trait]
-impl ReadProt for PlayerActions {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> where Self: Sized {
-        let action = VarInt::read(stream).await?;
-        match action.value {
-            0 => Ok(PlayerActions::StartDig),
-            1 => Ok(PlayerActions::CancelDig),
-            2 => Ok(PlayerActions::FinishDig),
-            3 => Ok(PlayerActions::DropStack),
-            4 => Ok(PlayerActions::DropItem),
-            5 => Ok(PlayerActions::ShootArrowFinishEating),
-            6 => Ok(PlayerActions::SwapHands),
-            _ => Err(format!("Invalid player action: {}", action.value))
+impl ReadProt for BlockFace {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError> where Self: Sized {
+        let face = VarInt::read(stream).await?;
+        match face.value {
+            0 => Ok(BlockFace::Bottom),
+            1 => Ok(BlockFace::Top),
+            2 => Ok(BlockFace::North),
+            3 => Ok(BlockFace::South),
+            4 => Ok(BlockFace::West),
+            5 => Ok(BlockFace::East),
+            _ => Err(ProtoError::InvalidEnumTag("block face", face.value.to_string()))
         }
     }
 }
 
-impl SizedProt for PlayerActions {
+impl SizedProt for BlockFace {
     fn prot_size(&self) -> usize {
         VarInt::from(0).prot_size()
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ProtEnum)]
+pub(crate) enum Hand {
+    MainHand,
+    OffHand,
+}
+
+// volume slider in the client's sound options that a sound is played at
+#[derive(Debug, Copy, Clone, PartialEq, ProtEnum)]
+pub(crate) enum SoundCategory {
+    Master,
+    Music,
+    Record,
+    Weather,
+    Block,
+    Hostile,
+    Neutral,
+    Player,
+    Ambient,
+    Voice,
+}
+
+/**
+ * When a command block runs its command: after the block pointing at it (chain), every tick (repeating),
+ * or when powered (impulse).
+ */
+#[derive(Debug, Copy, Clone, PartialEq, ProtEnum)]
+pub(crate) enum CommandBlockMode {
+    Sequence,
+    Auto,
+    Redstone,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn boss_bar_actions_round_trip() {
+        let title = Chat::new_text("Ender Dragon".into());
+        let mut title_bytes = vec![];
+        title.write(&mut title_bytes).await.unwrap();
+        let half = 0.5f32.to_be_bytes().to_vec();
+        let actions = [
+            (
+                BossBarAction::Add { title: title.clone(), health: 0.5, color: 2.into(), division: 1.into(), flags: BOSS_BAR_FOG },
+                [vec![0], title_bytes.clone(), half.clone(), vec![2, 1, 0x04]].concat(),
+            ),
+            (BossBarAction::Remove, vec![1]),
+            (BossBarAction::UpdateHealth(0.5), [vec![2], half].concat()),
+            (BossBarAction::UpdateTitle(title), [vec![3], title_bytes].concat()),
+            (BossBarAction::UpdateStyle { color: 5.into(), division: 4.into() }, vec![4, 5, 4]),
+            (BossBarAction::UpdateFlags(BOSS_BAR_DARKEN_SKY | BOSS_BAR_DRAGON_MUSIC), vec![5, 0x03]),
+        ];
+        for (action, expected) in actions {
+            let mut buf = vec![];
+            action.write(&mut buf).await.unwrap();
+            assert_eq!(buf, expected);
+            assert_eq!(action.prot_size(), buf.len());
+            assert_eq!(BossBarAction::read(&mut buf.as_slice()).await.unwrap(), action);
+        }
+        assert!(BossBarAction::read(&mut [6u8].as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn start_waiting_for_level_chunks_event() {
+        let event = GameEvent::StartWaitingForLevelChunks;
+        let mut buf = vec![];
+        event.write(&mut buf).await.unwrap();
+        assert_eq!(buf, [13, 0, 0, 0, 0]);
+        assert_eq!(event.prot_size(), buf.len());
+    }
+
+    #[derive(Debug, PartialEq, ProtEnum)]
+    enum Weather {
+        Clear,
+        Rain = 5,
+        Thunder,
+    }
+
+    #[derive(Debug, PartialEq, ReadProt, WriteProt, SizedProt)]
+    struct Pair(VarInt, String);
+
+    #[derive(Debug, PartialEq, ReadProt, WriteProt, SizedProt)]
+    struct Nothing;
+
+    #[tokio::test]
+    async fn derive_tuple_and_unit_structs() {
+        let pair = Pair(VarInt::from(300), "hi".into());
+        let mut buf = vec![];
+        pair.write(&mut buf).await.unwrap();
+        assert_eq!(buf, vec![0xac, 0x02, 2, b'h', b'i']);
+        assert_eq!(pair.prot_size(), buf.len());
+        assert_eq!(Pair::read(&mut buf.as_slice()).await.unwrap(), pair);
+
+        let mut buf = vec![];
+        Nothing.write(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(Nothing.prot_size(), 0);
+        assert_eq!(Nothing::read(&mut buf.as_slice()).await.unwrap(), Nothing);
+    }
+
+    #[tokio::test]
+    async fn prot_enum_round_trip() {
+        for (weather, tag) in [(Weather::Clear, 0), (Weather::Rain, 5), (Weather::Thunder, 6)] {
+            let mut buf = vec![];
+            weather.write(&mut buf).await.unwrap();
+            assert_eq!(buf, vec![tag]);
+            assert_eq!(weather.prot_size(), buf.len());
+            assert_eq!(Weather::read(&mut buf.as_slice()).await.unwrap(), weather);
+        }
+        let mut buf = vec![];
+        VarInt::from(1).write(&mut buf).await.unwrap();
+        assert!(matches!(
+            Weather::read(&mut buf.as_slice()).await,
+            Err(ProtoError::InvalidEnumTag("Weather", _))
+        ));
+    }
+
+    async fn recipe_bytes(typ: &str, id: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        typ.to_string().write(&mut buf).await.unwrap();
+        id.to_string().write(&mut buf).await.unwrap();
+        match typ {
+            "minecraft:stonecutting" => {
+                "".to_string().write(&mut buf).await.unwrap();
+                // ingredient with two empty slots, then an empty result slot
+                VarInt::from(2).write(&mut buf).await.unwrap();
+                false.write(&mut buf).await.unwrap();
+                false.write(&mut buf).await.unwrap();
+                false.write(&mut buf).await.unwrap();
+            }
+            _ => VarInt::from(1).write(&mut buf).await.unwrap(),
+        }
+        buf
+    }
+
+    #[tokio::test]
+    async fn sized_vec_recipe_round_trip() {
+        for count in [0, 2, 500] {
+            let mut buf = vec![];
+            VarInt::from(count).write(&mut buf).await.unwrap();
+            for i in 0..count {
+                if i % 2 == 0 {
+                    buf.extend(recipe_bytes("minecraft:stonecutting", &format!("recipe_{i}")).await);
+                } else {
+                    buf.extend(recipe_bytes("minecraft:crafting_special_armordye", &format!("recipe_{i}")).await);
+                }
+            }
+            let mut slice = buf.as_slice();
+            let recipes = SizedVec::<Recipe>::read(&mut slice).await.unwrap();
+            assert!(slice.is_empty());
+            assert_eq!(recipes.vec.len(), count as usize);
+            assert_eq!(recipes.prot_size(), buf.len());
+
+            let mut written = vec![];
+            recipes.write(&mut written).await.unwrap();
+            assert_eq!(written, buf);
+        }
+    }
+
+    #[test]
+    fn movement_delta() {
+        let old = PosRotGround { x: 0.0, y: 64.0, z: 0.0, pitch: 0.0, yaw: 0.0, on_ground: true };
+        let new = PosRotGround { x: 1.5, y: 63.0, z: -0.25, ..old.clone() };
+        assert_eq!(old.delta_to(&new), Some((6144, -4096, -1024)));
+        let far = PosRotGround { x: 8.5, ..old.clone() };
+        assert_eq!(old.delta_to(&far), None);
+    }
+
+    #[test]
+    fn angle_wraps() {
+        assert_eq!(Angle::from(90.0), Angle(64));
+        assert_eq!(Angle::from(-90.0), Angle(192));
+        assert_eq!(Angle::from(360.0), Angle(0));
+    }
+
+    async fn position_round_trip(position: Position) -> Result<Position, ProtoError> {
+        let mut buf = vec![];
+        position.write(&mut buf).await?;
+        Position::read(&mut buf.as_slice()).await
+    }
+
+    #[tokio::test]
+    async fn position_packing_edges() {
+        for position in [
+            Position::new(-5, -60, 100000),
+            Position::new(33554431, 2047, 33554431),
+            Position::new(-33554432, -2048, -33554432),
+            Position::new(-1, -1, -1),
+        ] {
+            assert_eq!(position_round_trip(position).await.unwrap(), position);
+        }
+        for position in [
+            Position::new(33554432, 0, 0),
+            Position::new(0, 0, -33554433),
+            Position::new(0, 2048, 0),
+            Position::new(0, -2049, 0),
+        ] {
+            assert!(matches!(position_round_trip(position).await, Err(ProtoError::PositionOutOfRange(_))));
+        }
+    }
+
+    #[test]
+    fn position_offset_by_face() {
+        let pos = Position::new(10, -5, 3);
+        assert_eq!(pos.offset(BlockFace::Bottom), Position::new(10, -6, 3));
+        assert_eq!(pos.offset(BlockFace::Top), Position::new(10, -4, 3));
+        assert_eq!(pos.offset(BlockFace::North), Position::new(10, -5, 2));
+        assert_eq!(pos.offset(BlockFace::South), Position::new(10, -5, 4));
+        assert_eq!(pos.offset(BlockFace::West), Position::new(9, -5, 3));
+        assert_eq!(pos.offset(BlockFace::East), Position::new(11, -5, 3));
+    }
+
+    #[test]
+    fn identifier_default_namespace() {
+        let id: Identifier = "stone".parse().unwrap();
+        assert_eq!(id, Identifier::new("minecraft", "stone").unwrap());
+    }
+
+    #[test]
+    fn identifier_with_namespace() {
+        let id: Identifier = "minecraft:stone".parse().unwrap();
+        assert_eq!(id.namespace, "minecraft");
+        assert_eq!(id.path, "stone");
+        let id: Identifier = "rustcraft:blocks/fancy_stone".parse().unwrap();
+        assert_eq!(id.to_string(), "rustcraft:blocks/fancy_stone");
+    }
+
+    #[test]
+    fn identifier_rejects_uppercase() {
+        assert!(matches!("minecraft:Stone".parse::<Identifier>(), Err(ProtoError::InvalidIdentifier(_))));
+        assert!("Minecraft:stone".parse::<Identifier>().is_err());
+        assert!("mine/craft:stone".parse::<Identifier>().is_err());
+    }
+
+    #[tokio::test]
+    async fn identifier_round_trip() {
+        let id: Identifier = "overworld".parse().unwrap();
+        let mut buf = vec![];
+        id.write(&mut buf).await.unwrap();
+        assert_eq!(buf.len(), id.prot_size());
+        assert_eq!(Identifier::read(&mut buf.as_slice()).await.unwrap(), id);
+
+        let mut buf = vec![];
+        "Not Valid".to_string().write(&mut buf).await.unwrap();
+        assert!(Identifier::read(&mut buf.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn chat_size_matches_written_bytes() {
+        let chat = Chat::new_text("Hällo ".repeat(40))
+            .with_bold(true)
+            .with_color("gold".into())
+            .with_extra(Chat::new_text("wörld §".into()).with_italic(true))
+            .with_extra(Chat::new_text("!".into()).with_obfuscated(true));
+        let size = chat.prot_size();
+        let mut buf = vec![];
+        chat.write(&mut buf).await.unwrap();
+        assert_eq!(size, buf.len());
+
+        let read = Chat::read(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(read, chat);
+        assert_eq!(read.prot_size(), size);
+    }
+
+    #[test]
+    fn chat_builder_resets_cached_json() {
+        let chat = Chat::new_text("a".into());
+        let size = chat.prot_size();
+        let chat = chat.with_underlined(true);
+        assert!(chat.prot_size() > size);
+    }
+}
@@ -3,13 +3,17 @@ use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::net::tcp::OwnedReadHalf;
 
 use crate::connection::ConnectionInfo;
+use crate::err::ProtoError;
 use crate::protocol_types::traits::{ReadProt, SizedProt, WriteProt};
 
 const SEGMENT_BITS: u8 = 0x7f;
 const CONTINUE_BIT: u8 = 0x80;
+// longest string the protocol allows, in characters
+pub(crate) const MAX_STRING_CHARS: usize = 32767;
+// longest chat message or command vanilla clients send, in characters
+pub(crate) const MAX_CHAT_CHARS: usize = 256;
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
 pub(crate) struct VarInt {
@@ -17,33 +21,26 @@ pub(crate) struct VarInt {
 }
 
 impl VarInt {
-    async fn get_byte(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<u8, String> {
-        let mut buf = vec![0u8; 1];
-        stream
-            .read_exact(&mut buf)
-            .await
-            .or_else(|x| Err(format!("Trying to read byte: {:?}", x)))?;
-        Ok(buf[0])
+    async fn get_byte(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<u8, ProtoError> {
+        Ok(stream.read_u8().await?)
     }
 
     async fn get_byte_decrypt(
-        stream: &mut OwnedReadHalf,
+        stream: &mut (impl AsyncRead + Unpin + Send),
         crypter: Arc<RwLock<ConnectionInfo>>,
-    ) -> Result<u8, String> {
-        let mut temp = vec![0u8; 1];
+    ) -> Result<u8, ProtoError> {
+        let mut temp = [0u8; 1];
         let byte = Self::get_byte(stream).await?;
         let mut crypter = crypter.write().unwrap();
         let crypter = crypter.decrypter.as_mut().unwrap();
-        crypter
-            .update(&[byte], &mut temp)
-            .or_else(|x| Err(format!("Crypter error: {:?}", x)))?;
+        crypter.update(&[byte], &mut temp)?;
         Ok(temp[0])
     }
 
     pub(crate) async fn read_decrypt(
-        stream: &mut OwnedReadHalf,
+        stream: &mut (impl AsyncRead + Unpin + Send),
         crypter: Arc<RwLock<ConnectionInfo>>,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, ProtoError> {
         let mut value: i32 = 0;
         let mut pos: u32 = 0;
         let mut current_byte: u8;
@@ -55,7 +52,7 @@ impl VarInt {
             }
             pos += 7;
             if pos >= 32 {
-                return Err("VarInt is too big".into());
+                return Err(ProtoError::VarIntTooLong);
             }
         }
     }
@@ -118,31 +115,36 @@ impl Debug for VarLong {
     }
 }
 
-#[async_trait]
-impl ReadProt for VarInt {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> {
+impl VarInt {
+    /**
+     * Reads a VarInt along with the number of bytes it took up, which may be more than `prot_size` if
+     * the value wasn't encoded in its shortest form.
+     */
+    pub(crate) async fn read_with_len(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<(Self, usize), ProtoError> {
         let mut value: i32 = 0;
         let mut pos: u32 = 0;
         let mut current_byte: u8;
         loop {
-            let mut buf = vec![0u8; 1];
-            stream
-                .read_exact(&mut buf)
-                .await
-                .or_else(|x| Err(format!("IO error: {:?}", x)))?;
-            current_byte = buf[0];
+            current_byte = Self::get_byte(stream).await?;
             value |= ((current_byte & SEGMENT_BITS) as i32) << pos;
             if current_byte & CONTINUE_BIT == 0 {
-                return Ok(Self { value });
+                return Ok((Self { value }, (pos / 7 + 1) as usize));
             }
             pos += 7;
             if pos >= 32 {
-                return Err("VarInt is too big".into());
+                return Err(ProtoError::VarIntTooLong);
             }
         }
     }
 }
 
+#[async_trait]
+impl ReadProt for VarInt {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError> {
+        Ok(Self::read_with_len(stream).await?.0)
+    }
+}
+
 impl SizedProt for VarInt {
     fn prot_size(&self) -> usize {
         let mut x = self.value as u32;
@@ -160,7 +162,7 @@ impl SizedProt for VarInt {
 
 #[async_trait]
 impl WriteProt for VarInt {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let mut x = self.value as u32;
         loop {
             let mut temp = (x & 0b0111_1111) as u8;
@@ -169,10 +171,7 @@ impl WriteProt for VarInt {
                 temp |= 0b1000_0000;
             }
 
-            stream
-                .write_all(&[temp])
-                .await
-                .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+            stream.write_all(&[temp]).await?;
 
             if x == 0 {
                 break;
@@ -182,36 +181,60 @@ impl WriteProt for VarInt {
     }
 }
 
-#[async_trait]
-impl ReadProt for VarLong {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> {
-        let mut result = 0;
-        let mut num_read = 0;
+impl VarLong {
+    // only frame lengths are read from the encrypted stream directly so far, which are VarInts
+    #[allow(dead_code)]
+    pub(crate) async fn read_decrypt(
+        stream: &mut (impl AsyncRead + Unpin + Send),
+        crypter: Arc<RwLock<ConnectionInfo>>,
+    ) -> Result<Self, ProtoError> {
+        let mut value: i64 = 0;
+        let mut pos: u32 = 0;
+        let mut current_byte: u8;
         loop {
-            let mut buf = vec![0u8; 1];
-            stream
-                .read_exact(&mut buf)
-                .await
-                .or_else(|x| Err(format!("IO error: {:?}", x)))?;
-            let read = buf[0];
-            let value = i64::from(read & 0b0111_1111);
-            result |= value.overflowing_shl(7 * num_read).0;
-
-            num_read += 1;
-
-            if num_read > 10 {
-                break Err(format!(
-                    "VarInt too long (max length: 5, value read so far: {})",
-                    result
-                ));
+            current_byte = VarInt::get_byte_decrypt(stream, crypter.clone()).await?;
+            value |= ((current_byte & SEGMENT_BITS) as i64) << pos;
+            if current_byte & CONTINUE_BIT == 0 {
+                return Ok(Self { value });
             }
-            if read & 0b1000_0000 == 0 {
-                break Ok(Self { value: result });
+            pos += 7;
+            if pos >= 64 {
+                return Err(ProtoError::VarLongTooLong);
+            }
+        }
+    }
+}
+
+impl VarLong {
+    /**
+     * Reads a VarLong along with the number of bytes it took up.
+     */
+    pub(crate) async fn read_with_len(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<(Self, usize), ProtoError> {
+        let mut value: i64 = 0;
+        let mut pos: u32 = 0;
+        let mut current_byte: u8;
+        loop {
+            current_byte = VarInt::get_byte(stream).await?;
+            value |= ((current_byte & SEGMENT_BITS) as i64) << pos;
+            if current_byte & CONTINUE_BIT == 0 {
+                return Ok((Self { value }, (pos / 7 + 1) as usize));
+            }
+            pos += 7;
+            // a VarLong is at most 10 bytes long
+            if pos >= 64 {
+                return Err(ProtoError::VarLongTooLong);
             }
         }
     }
 }
 
+#[async_trait]
+impl ReadProt for VarLong {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError> {
+        Ok(Self::read_with_len(stream).await?.0)
+    }
+}
+
 impl SizedProt for VarLong {
     fn prot_size(&self) -> usize {
         let mut x = self.value as u64;
@@ -229,7 +252,7 @@ impl SizedProt for VarLong {
 
 #[async_trait]
 impl WriteProt for VarLong {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let mut x = self.value as u64;
         loop {
             let mut temp = (x & 0b0111_1111) as u8;
@@ -238,10 +261,7 @@ impl WriteProt for VarLong {
                 temp |= 0b1000_0000;
             }
 
-            stream
-                .write_all(&[temp])
-                .await
-                .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+            stream.write_all(&[temp]).await?;
 
             if x == 0 {
                 break;
@@ -251,37 +271,80 @@ impl WriteProt for VarLong {
     }
 }
 
+// An optional value sent as a VarInt, 0 if absent and the value plus one otherwise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct OptVarInt(pub(crate) Option<i32>);
+
+impl OptVarInt {
+    fn encoded(&self) -> VarInt {
+        VarInt::from(self.0.map_or(0, |value| value + 1))
+    }
+}
+
 #[async_trait]
-impl ReadProt for String {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+impl ReadProt for OptVarInt {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
-        let len = VarInt::read(stream).await?;
-        let len = len.value as u32;
-        if len > 32767 * 4 + 3 {
-            return Err(format!("String too long: {} B", len));
-        }
+        let value = VarInt::read(stream).await?.value;
+        Ok(Self((value != 0).then(|| value - 1)))
+    }
+}
 
-        let mut data = stream.take(len as u64);
-        let mut buf = vec![];
-        data.read_to_end(&mut buf)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
-        let value = String::from_utf8(buf).or_else(|x| Err(format!("UTF8 error: {:?}", x)))?;
-        Ok(value)
+#[async_trait]
+impl WriteProt for OptVarInt {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.encoded().write(stream).await
+    }
+}
+
+impl SizedProt for OptVarInt {
+    fn prot_size(&self) -> usize {
+        self.encoded().prot_size()
+    }
+}
+
+/**
+ * Reads a string of at most `max_chars` characters. The length prefix counts bytes, so it can only be checked
+ * against the most bytes that many characters take up in UTF-8 before the characters are counted after decoding.
+ */
+pub(crate) async fn read_limited(stream: &mut (impl AsyncRead + Unpin + Send), max_chars: usize) -> Result<String, ProtoError> {
+    let len = VarInt::read(stream).await?;
+    let len = len.value as u32 as usize;
+    if len > max_chars * 4 {
+        return Err(ProtoError::StringTooLong(len));
+    }
+
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+    let value = String::from_utf8(buf)?;
+    let chars = value.chars().count();
+    if chars > max_chars {
+        return Err(ProtoError::TooManyChars(chars, max_chars));
+    }
+    Ok(value)
+}
+
+#[async_trait]
+impl ReadProt for String {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        read_limited(stream, MAX_STRING_CHARS).await
     }
 }
 
 #[async_trait]
 impl WriteProt for String {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         (VarInt {
             value: self.len() as i32,
         })
         .write(stream)
         .await?;
-        stream.write_all(self.as_bytes()).await.unwrap();
+        stream.write_all(self.as_bytes()).await?;
         Ok(())
     }
 }
@@ -306,11 +369,6 @@ fn u64tou8abe(v: u64) -> [u8; 8] {
     ]
 }
 
-#[inline]
-fn u32tou8abe(v: u32) -> [u8; 4] {
-    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
-}
-
 #[inline]
 fn u16tou8abe(v: u16) -> [u8; 2] {
     [(v >> 8) as u8, v as u8]
@@ -318,36 +376,18 @@ fn u16tou8abe(v: u16) -> [u8; 2] {
 
 #[async_trait]
 impl ReadProt for i32 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
-        let mut buffer = [0; 4];
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
-        let mut value: u32 = buffer[0] as u32;
-        value <<= 8;
-        value |= buffer[1] as u32;
-        value <<= 8;
-        value |= buffer[2] as u32;
-        value <<= 8;
-        value |= buffer[3] as u32;
-
-        Ok(value as i32)
+        Ok(stream.read_i32().await?)
     }
 }
 
 #[async_trait]
 impl WriteProt for i32 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        let data = u32tou8abe(*self as u32);
-        stream
-            .write_all(&data)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
-        Ok(())
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_i32(*self).await?)
     }
 }
 
@@ -357,36 +397,43 @@ impl SizedProt for i32 {
     }
 }
 
+#[async_trait]
+impl ReadProt for u32 {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        Ok(stream.read_u32().await?)
+    }
+}
+
 #[async_trait]
 impl WriteProt for u32 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_u32(*self)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_u32(*self).await?)
+    }
+}
+
+impl SizedProt for u32 {
+    fn prot_size(&self) -> usize {
+        4
     }
 }
 
 #[async_trait]
 impl ReadProt for i16 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
-        stream
-            .read_i16()
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+        Ok(stream.read_i16().await?)
     }
 }
 
 #[async_trait]
 impl WriteProt for i16 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_i16(*self)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_i16(*self).await?)
     }
 }
 
@@ -399,15 +446,12 @@ impl SizedProt for i16 {
 
 #[async_trait]
 impl ReadProt for u8 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let mut buffer = [0; 1];
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.read_exact(&mut buffer).await?;
 
         let value = buffer[0];
         Ok(value)
@@ -416,11 +460,8 @@ impl ReadProt for u8 {
 
 #[async_trait]
 impl WriteProt for u8 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_all(&[*self])
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        stream.write_all(&[*self]).await?;
         Ok(())
     }
 }
@@ -431,9 +472,37 @@ impl SizedProt for u8 {
     }
 }
 
+#[async_trait]
+impl ReadProt for i8 {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        let mut buffer = [0; 1];
+        stream.read_exact(&mut buffer).await?;
+
+        let value = buffer[0] as i8;
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl WriteProt for i8 {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        stream.write_all(&[*self as u8]).await?;
+        Ok(())
+    }
+}
+
+impl SizedProt for i8 {
+    fn prot_size(&self) -> usize {
+        1
+    }
+}
+
 #[async_trait]
 impl ReadProt for bool {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
@@ -443,10 +512,9 @@ impl ReadProt for bool {
 
 #[async_trait]
 impl WriteProt for bool {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         u8::write(&if *self { 0x01 } else { 0x00 }, stream)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?; // 0x01 = true, 0x00 = false
+            .await?; // 0x01 = true, 0x00 = false
         Ok(())
     }
 }
@@ -459,15 +527,12 @@ impl SizedProt for bool {
 
 #[async_trait]
 impl ReadProt for u16 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let mut buffer = [0; 2];
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.read_exact(&mut buffer).await?;
 
         let value = ((buffer[0] as u16) << 8) | buffer[1] as u16;
         Ok(value)
@@ -476,12 +541,9 @@ impl ReadProt for u16 {
 
 #[async_trait]
 impl WriteProt for u16 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let data = u16tou8abe(*self);
-        stream
-            .write_all(&data)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.write_all(&data).await?;
         Ok(())
     }
 }
@@ -494,15 +556,12 @@ impl SizedProt for u16 {
 
 #[async_trait]
 impl ReadProt for i64 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let mut buffer = [0; 8];
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.read_exact(&mut buffer).await?;
         let mut value: u64 = buffer[0] as u64;
         value <<= 8;
         value |= buffer[1] as u64;
@@ -525,12 +584,9 @@ impl ReadProt for i64 {
 
 #[async_trait]
 impl WriteProt for i64 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let data = u64tou8abe(*self as u64);
-        stream
-            .write_all(&data)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.write_all(&data).await?;
         Ok(())
     }
 }
@@ -543,15 +599,12 @@ impl SizedProt for i64 {
 
 #[async_trait]
 impl ReadProt for u64 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let mut buffer = [0; 8];
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.read_exact(&mut buffer).await?;
         let mut value: u64 = buffer[0] as u64;
         value <<= 8;
         value |= buffer[1] as u64;
@@ -574,12 +627,9 @@ impl ReadProt for u64 {
 
 #[async_trait]
 impl WriteProt for u64 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         let data = u64tou8abe(*self as u64);
-        stream
-            .write_all(&data)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.write_all(&data).await?;
         Ok(())
     }
 }
@@ -590,26 +640,67 @@ impl SizedProt for u64 {
     }
 }
 
+// raw 128-bit values, big-endian like the Uuid impl
+#[async_trait]
+impl ReadProt for u128 {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        Ok(stream.read_u128().await?)
+    }
+}
+
+#[async_trait]
+impl WriteProt for u128 {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_u128(*self).await?)
+    }
+}
+
+impl SizedProt for u128 {
+    fn prot_size(&self) -> usize {
+        16
+    }
+}
+
+#[async_trait]
+impl ReadProt for i128 {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        Ok(stream.read_i128().await?)
+    }
+}
+
+#[async_trait]
+impl WriteProt for i128 {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_i128(*self).await?)
+    }
+}
+
+impl SizedProt for i128 {
+    fn prot_size(&self) -> usize {
+        16
+    }
+}
+
 #[async_trait]
 impl WriteProt for f32 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_f32(*self)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_f32(*self).await?)
     }
 }
 
 #[async_trait]
 impl ReadProt for f32 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
-        stream
-            .read_f32()
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+        Ok(stream.read_f32().await?)
     }
 }
 
@@ -621,24 +712,18 @@ impl SizedProt for f32 {
 
 #[async_trait]
 impl WriteProt for f64 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_f64(*self)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        Ok(stream.write_f64(*self).await?)
     }
 }
 
 #[async_trait]
 impl ReadProt for f64 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
-        stream
-            .read_f64()
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))
+        Ok(stream.read_f64().await?)
     }
 }
 
@@ -686,7 +771,7 @@ impl<T> WriteProt for SizedVec<T>
 where
     T: WriteProt + Sync + Send,
 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         VarInt::from(self.vec.len()).write(stream).await?;
         for item in &self.vec {
             item.write(stream).await?;
@@ -695,26 +780,20 @@ where
     }
 }
 
-// Reading a Vec<u8> assumes the length of the vec is announced as a VarInt in the stream just before the bytearray.
+// Reading a SizedVec assumes the number of elements is announced as a VarInt in the stream just before the elements.
 #[async_trait]
 impl<T> ReadProt for SizedVec<T>
 where
-    T: ReadProt + Sync + SizedProt + Send,
+    T: ReadProt + Sync + Send,
 {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let len = VarInt::read(stream).await?;
-        let len = len.value as usize;
-        let mut bytes_so_far = 0;
-        let mut buf = vec![];
-        loop {
-            if bytes_so_far == len {
-                break;
-            }
+        let mut buf = Vec::with_capacity((len.value.max(0) as usize).min(1024));
+        for _ in 0..len.value {
             buf.push(T::read(stream).await?);
-            bytes_so_far += buf.last().unwrap().prot_size();
         }
         Ok(Self { vec: buf })
     }
@@ -730,28 +809,88 @@ where
     }
 }
 
+// A string that is rejected when read if it's longer than MAX characters, written like any other string.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LimitedString<const MAX: usize>(pub(crate) String);
+
+#[async_trait]
+impl<const MAX: usize> ReadProt for LimitedString<MAX> {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        Ok(Self(read_limited(stream, MAX).await?))
+    }
+}
+
+#[async_trait]
+impl<const MAX: usize> WriteProt for LimitedString<MAX> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        self.0.write(stream).await
+    }
+}
+
+impl<const MAX: usize> SizedProt for LimitedString<MAX> {
+    fn prot_size(&self) -> usize {
+        self.0.prot_size()
+    }
+}
+
+// Bytes that make up the rest of a packet, written without a length prefix.
+#[derive(Clone)]
+pub(crate) struct RawBytes(pub(crate) Vec<u8>);
+
+impl Debug for RawBytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RawBytes(len={})", self.0.len())
+    }
+}
+
+#[async_trait]
+impl WriteProt for RawBytes {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        stream.write_all(&self.0).await?;
+        Ok(())
+    }
+}
+
+/**
+ * Reads until the end of the stream, so the stream has to be limited to the remainder of the packet.
+ */
+#[async_trait]
+impl ReadProt for RawBytes {
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
+    where
+        Self: Sized,
+    {
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await?;
+        Ok(Self(buf))
+    }
+}
+
+impl SizedProt for RawBytes {
+    fn prot_size(&self) -> usize {
+        self.0.len()
+    }
+}
+
 #[async_trait]
 impl<const N: usize> WriteProt for [u8; N] {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
-        stream
-            .write_all(self)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
+        stream.write_all(self).await?;
         Ok(())
     }
 }
 
 #[async_trait]
 impl<const N: usize> ReadProt for [u8; N] {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized,
     {
         let mut buf = [0u8; N];
-        stream
-            .read_exact(&mut buf)
-            .await
-            .or_else(|x| Err(format!("IO error: {:?}", x)))?;
+        stream.read_exact(&mut buf).await?;
         Ok(buf)
     }
 }
@@ -779,7 +918,7 @@ impl<T> WriteProt for Option<T>
 where
     T: WriteProt + Sync,
 {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String> {
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError> {
         match self {
             Some(x) => {
                 x.write(stream).await?;
@@ -792,10 +931,187 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::{VarInt, VarLong, WriteProt};
+    use std::sync::{Arc, RwLock};
+
+    use openssl::symm::{Cipher, Crypter, Mode};
+
+    use super::{ConnectionInfo, LimitedString, OptVarInt, ProtoError, ReadProt, SizedProt, SizedVec, VarInt, VarLong, WriteProt};
+
+    async fn sized_vec_round_trip<T>(vec: Vec<T>) -> Result<(), ProtoError>
+    where
+        T: ReadProt + WriteProt + SizedProt + PartialEq + std::fmt::Debug + Clone + Send + Sync,
+    {
+        let value = SizedVec::from(vec.clone());
+        let mut buf: Vec<u8> = vec![];
+        value.write(&mut buf).await?;
+        assert_eq!(buf.len(), value.prot_size());
+        let mut slice = buf.as_slice();
+        assert_eq!(SizedVec::<T>::read(&mut slice).await?.vec, vec);
+        assert!(slice.is_empty());
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn varint_0() -> Result<(), String> {
+    async fn sized_vec_string() -> Result<(), ProtoError> {
+        sized_vec_round_trip(vec!["minecraft:stone".to_string(), String::new(), "ä".to_string()]).await?;
+        sized_vec_round_trip::<String>(vec![]).await?;
+        sized_vec_round_trip((0..1000).map(|i| i.to_string()).collect()).await
+    }
+
+    #[tokio::test]
+    async fn sized_vec_varint() -> Result<(), ProtoError> {
+        sized_vec_round_trip::<VarInt>(vec![0.into(), 300.into(), (-1).into(), i32::MAX.into()]).await?;
+        sized_vec_round_trip::<VarInt>(vec![]).await?;
+        sized_vec_round_trip((0..1000).map(|i: i32| (i * 1000).into()).collect::<Vec<VarInt>>()).await
+    }
+
+    #[tokio::test]
+    async fn sized_vec_u8() -> Result<(), ProtoError> {
+        sized_vec_round_trip::<u8>(vec![1, 2, 3]).await?;
+        sized_vec_round_trip::<u8>(vec![]).await?;
+        sized_vec_round_trip((0..100_000).map(|i| i as u8).collect::<Vec<u8>>()).await
+    }
+
+    #[tokio::test]
+    async fn u128_max() -> Result<(), ProtoError> {
+        let mut buf: Vec<u8> = vec![];
+        u128::MAX.write(&mut buf).await?;
+        assert_eq!(buf, vec![0xff; 16]);
+        assert_eq!(buf.len(), u128::MAX.prot_size());
+        assert_eq!(u128::read(&mut buf.as_slice()).await?, u128::MAX);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn i128_round_trip() -> Result<(), ProtoError> {
+        for value in [i128::MIN, -1, 0, i128::MAX] {
+            let mut buf: Vec<u8> = vec![];
+            value.write(&mut buf).await?;
+            assert_eq!(i128::read(&mut buf.as_slice()).await?, value);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn u128_matches_uuid_layout() -> Result<(), ProtoError> {
+        let value = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210u128;
+        let mut raw: Vec<u8> = vec![];
+        value.write(&mut raw).await?;
+        let mut uuid: Vec<u8> = vec![];
+        uuid::Uuid::from_u128(value).write(&mut uuid).await?;
+        assert_eq!(raw, uuid);
+        Ok(())
+    }
+
+    async fn i8_round_trip(value: i8) -> Result<(), ProtoError> {
+        let mut buf: Vec<u8> = vec![];
+        value.write(&mut buf).await?;
+        assert_eq!(buf.len(), value.prot_size());
+        assert_eq!(buf[0], value as u8);
+        assert_eq!(i8::read(&mut buf.as_slice()).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn i8_n128() -> Result<(), ProtoError> {
+        i8_round_trip(-128).await
+    }
+
+    #[tokio::test]
+    async fn i8_n1() -> Result<(), ProtoError> {
+        i8_round_trip(-1).await
+    }
+
+    #[tokio::test]
+    async fn i8_0() -> Result<(), ProtoError> {
+        i8_round_trip(0).await
+    }
+
+    #[tokio::test]
+    async fn i8_127() -> Result<(), ProtoError> {
+        i8_round_trip(127).await
+    }
+
+    async fn u32_round_trip(value: u32, expected: [u8; 4]) -> Result<(), ProtoError> {
+        let mut buf: Vec<u8> = vec![];
+        value.write(&mut buf).await?;
+        assert_eq!(buf, expected);
+        assert_eq!(buf.len(), value.prot_size());
+        assert_eq!(u32::read(&mut buf.as_slice()).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn u32_0() -> Result<(), ProtoError> {
+        u32_round_trip(0, [0, 0, 0, 0]).await
+    }
+
+    #[tokio::test]
+    async fn u32_max() -> Result<(), ProtoError> {
+        u32_round_trip(0xFFFFFFFF, [0xFF, 0xFF, 0xFF, 0xFF]).await
+    }
+
+    #[tokio::test]
+    async fn u32_0x01020304() -> Result<(), ProtoError> {
+        u32_round_trip(0x01020304, [1, 2, 3, 4]).await
+    }
+
+    #[tokio::test]
+    async fn truncated_stream_is_io_error() {
+        let buf: Vec<u8> = vec![0, 0, 1];
+        assert!(matches!(i32::read(&mut buf.as_slice()).await, Err(ProtoError::Io(_))));
+        let buf: Vec<u8> = vec![5, b'a', b'b'];
+        assert!(matches!(String::read(&mut buf.as_slice()).await, Err(ProtoError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn varint_too_long() {
+        let buf: Vec<u8> = vec![0xff; 6];
+        assert!(matches!(VarInt::read(&mut buf.as_slice()).await, Err(ProtoError::VarIntTooLong)));
+    }
+
+    #[tokio::test]
+    async fn read_with_len_counts_bytes() {
+        let buf: Vec<u8> = vec![0xff, 0xff, 0x7f];
+        assert_eq!(VarInt::read_with_len(&mut buf.as_slice()).await.unwrap(), (VarInt::from(2097151), 3));
+        // 1 padded to three bytes, longer than its shortest encoding
+        let buf: Vec<u8> = vec![0x81, 0x80, 0x00];
+        let (value, len) = VarInt::read_with_len(&mut buf.as_slice()).await.unwrap();
+        assert_eq!((value, len), (VarInt::from(1), 3));
+        assert_eq!(value.prot_size(), 1);
+        let buf: Vec<u8> = vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert_eq!(VarLong::read_with_len(&mut buf.as_slice()).await.unwrap(), (VarLong::from(1 << 35), 6));
+    }
+
+    #[tokio::test]
+    async fn varlong_max_length() {
+        let mut buf: Vec<u8> = vec![0xff; 9];
+        buf.push(0x01);
+        assert_eq!(VarLong::read(&mut buf.as_slice()).await.unwrap(), VarLong::from(-1));
+        let buf: Vec<u8> = vec![0xff; 10];
+        assert!(matches!(VarLong::read(&mut buf.as_slice()).await, Err(ProtoError::VarLongTooLong)));
+    }
+
+    #[tokio::test]
+    async fn varlong_decrypt_round_trip() -> Result<(), ProtoError> {
+        let key = [7u8; 16];
+        let cipher = Cipher::aes_128_cfb8();
+        let mut encrypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&key))?;
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().decrypter = Some(Crypter::new(cipher, Mode::Decrypt, &key, Some(&key))?);
+
+        let value = VarLong::from(-9223372036854775808);
+        let mut plain: Vec<u8> = vec![];
+        value.write(&mut plain).await?;
+        let mut encrypted = vec![0u8; plain.len()];
+        encrypter.update(&plain, &mut encrypted)?;
+
+        assert_eq!(VarLong::read_decrypt(&mut encrypted.as_slice(), connection).await?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn varint_0() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 0 }.write(&mut buf).await?;
         assert_eq!(buf[0], 0);
@@ -803,7 +1119,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_1() -> Result<(), String> {
+    async fn varint_1() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 1 }.write(&mut buf).await?;
         assert_eq!(buf[0], 1);
@@ -811,7 +1127,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_2() -> Result<(), String> {
+    async fn varint_2() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 2 }.write(&mut buf).await?;
         assert_eq!(buf[0], 2);
@@ -819,7 +1135,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_127() -> Result<(), String> {
+    async fn varint_127() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 127 }.write(&mut buf).await?;
         assert_eq!(buf[0], 127);
@@ -827,7 +1143,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_128() -> Result<(), String> {
+    async fn varint_128() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 128 }.write(&mut buf).await?;
         assert_eq!(buf[0], 128);
@@ -836,7 +1152,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_255() -> Result<(), String> {
+    async fn varint_255() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 255 }.write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -845,7 +1161,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_25565() -> Result<(), String> {
+    async fn varint_25565() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 25565 }.write(&mut buf).await?;
         assert_eq!(buf[0], 221);
@@ -855,7 +1171,42 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_2097151() -> Result<(), String> {
+    async fn opt_varint_round_trip() -> Result<(), ProtoError> {
+        for (value, encoded) in [(OptVarInt(None), 0u8), (OptVarInt(Some(5)), 6), (OptVarInt(Some(0)), 1)] {
+            let mut buf = vec![];
+            value.write(&mut buf).await?;
+            assert_eq!(buf, [encoded]);
+            assert_eq!(value.prot_size(), 1);
+            assert_eq!(OptVarInt::read(&mut buf.as_slice()).await?, value);
+        }
+        Ok(())
+    }
+
+    async fn read_chat(value: &str) -> Result<LimitedString<256>, ProtoError> {
+        let mut buf = vec![];
+        value.to_string().write(&mut buf).await?;
+        LimitedString::read(&mut buf.as_slice()).await
+    }
+
+    #[tokio::test]
+    async fn string_limit_counts_characters() {
+        // two bytes per character, so the byte length is well above the limit
+        let accented = "é".repeat(256);
+        assert_eq!(read_chat(&accented).await.unwrap().0, accented);
+        assert!(matches!(read_chat(&"é".repeat(257)).await, Err(ProtoError::TooManyChars(257, 256))));
+        // four bytes per character, the most a character can take up
+        let emoji = "🦀".repeat(256);
+        assert_eq!(read_chat(&emoji).await.unwrap().0, emoji);
+        assert!(matches!(read_chat(&"🦀".repeat(257)).await, Err(ProtoError::StringTooLong(1028))));
+        assert!(matches!(read_chat(&format!("{}a", "🦀".repeat(255))).await, Ok(_)));
+
+        let mut buf = vec![];
+        "€".repeat(32767).write(&mut buf).await.unwrap();
+        assert_eq!(String::read(&mut buf.as_slice()).await.unwrap().chars().count(), 32767);
+    }
+
+    #[tokio::test]
+    async fn varint_2097151() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 2097151 }.write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -865,7 +1216,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_2147483647() -> Result<(), String> {
+    async fn varint_2147483647() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: 2147483647 }.write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -877,7 +1228,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_n1() -> Result<(), String> {
+    async fn varint_n1() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: -1 }.write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -889,7 +1240,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varint_n2147483648() -> Result<(), String> {
+    async fn varint_n2147483648() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         VarInt { value: -2147483648 }.write(&mut buf).await?;
         assert_eq!(buf[0], 128);
@@ -901,7 +1252,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_0() -> Result<(), String> {
+    async fn varlong_0() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 0 }).write(&mut buf).await?;
         assert_eq!(buf[0], 0);
@@ -909,7 +1260,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_1() -> Result<(), String> {
+    async fn varlong_1() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 1 }).write(&mut buf).await?;
         assert_eq!(buf[0], 1);
@@ -917,7 +1268,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_2() -> Result<(), String> {
+    async fn varlong_2() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 2 }).write(&mut buf).await?;
         assert_eq!(buf[0], 2);
@@ -925,7 +1276,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_127() -> Result<(), String> {
+    async fn varlong_127() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 127 }).write(&mut buf).await?;
         assert_eq!(buf[0], 127);
@@ -933,7 +1284,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_128() -> Result<(), String> {
+    async fn varlong_128() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 128 }).write(&mut buf).await?;
         assert_eq!(buf[0], 128);
@@ -942,7 +1293,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_255() -> Result<(), String> {
+    async fn varlong_255() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 255 }).write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -951,7 +1302,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_2147483647() -> Result<(), String> {
+    async fn varlong_2147483647() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: 2147483647 }).write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -963,7 +1314,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_9223372036854775807() -> Result<(), String> {
+    async fn varlong_9223372036854775807() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong {
             value: 9223372036854775807,
@@ -983,7 +1334,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_n1() -> Result<(), String> {
+    async fn varlong_n1() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: -1 }).write(&mut buf).await?;
         assert_eq!(buf[0], 255);
@@ -1000,7 +1351,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_n2147483648() -> Result<(), String> {
+    async fn varlong_n2147483648() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong { value: -2147483648 }).write(&mut buf).await?;
         assert_eq!(buf[0], 128);
@@ -1017,7 +1368,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn varlong_n9223372036854775808() -> Result<(), String> {
+    async fn varlong_n9223372036854775808() -> Result<(), ProtoError> {
         let mut buf: Vec<u8> = vec![];
         (VarLong {
             value: -9223372036854775808,
@@ -1,5 +1,5 @@
 use crate::connection::ConnectionInfo;
-use crate::err::ProtError;
+use crate::err::{ProtError, ProtoError};
 use crate::packets::client::ClientPackets;
 use crate::Assets;
 use async_trait::async_trait;
@@ -30,21 +30,21 @@ pub(crate) trait ClientPacket: SizedProt + WriteProtPacket + Debug + Display {
 
 #[async_trait]
 pub(crate) trait ReadProt {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized;
 }
 
 #[async_trait]
 pub(crate) trait ReadProtPacket {
-    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String>
+    async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError>
     where
         Self: Sized;
 }
 
 #[async_trait]
 pub(crate) trait WriteProt {
-    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), String>;
+    async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send)) -> Result<(), ProtoError>;
 }
 
 #[async_trait]
@@ -53,7 +53,7 @@ pub(crate) trait WriteProtPacket {
         &self,
         stream: &mut (impl AsyncWrite + Unpin + Send),
         connection: Arc<RwLock<ConnectionInfo>>,
-    ) -> Result<(), String>;
+    ) -> Result<(), ProtoError>;
 }
 
 pub(crate) trait SizedProt {
@@ -1,8 +1,13 @@
 use md5::{Digest, Md5};
 use std::ascii::escape_default;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::env;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use uuid::Uuid;
 
+use crate::connection::ConnectionInfo;
+use crate::err::ProtoError;
+
 // Visualize u8 slice in hex
 pub(crate) fn show(bs: &[u8]) -> String {
     let mut visible = String::new();
@@ -14,6 +19,34 @@ pub(crate) fn show(bs: &[u8]) -> String {
     visible
 }
 
+// how packets are printed when LOG_PACKETS is enabled, chosen with PACKET_LOG_FORMAT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PacketLogFormat {
+    // only the parsed packet
+    Debug,
+    // the parsed packet along with the raw bytes of its frame
+    Hex,
+}
+
+impl PacketLogFormat {
+    fn parse(format: Option<&str>) -> Self {
+        match format {
+            Some("hex") => PacketLogFormat::Hex,
+            _ => PacketLogFormat::Debug,
+        }
+    }
+
+    /**
+     * The configured format, or None if packets aren't logged at all.
+     */
+    pub(crate) fn from_env() -> Option<Self> {
+        if !env::var("LOG_PACKETS").is_ok_and(|s| s == "true") {
+            return None;
+        }
+        Some(Self::parse(env::var("PACKET_LOG_FORMAT").ok().as_deref()))
+    }
+}
+
 pub(crate) fn name_uuid(name: String) -> Uuid {
     // MD5 digest of name
     let mut hasher = Md5::new();
@@ -29,16 +62,48 @@ pub(crate) fn name_uuid(name: String) -> Uuid {
 pub(crate) async fn skip(
     stream: &mut (impl AsyncRead + Unpin + Send),
     n: u64,
-) -> Result<(), String> {
+) -> Result<(), ProtoError> {
     // skip n bytes in the given stream
     let mut took = stream.take(n);
     let mut buf = Vec::with_capacity(n as usize);
-    took.read_to_end(&mut buf)
-        .await
-        .or_else(|err| Err(format!("{err}")))?;
+    took.read_to_end(&mut buf).await?;
     Ok(())
 }
 
+/**
+ * Encrypts the framed packet in `buf` if encryption is enabled and writes it to the stream.
+ * The buffer is handed back to the connection's buffer pool afterwards. Nothing is written if the
+ * frame isn't `frame_size` bytes long, as the client would misread every packet after it.
+ */
+pub(crate) async fn send_packet_buf(
+    stream: &mut (impl AsyncWrite + Unpin + Send),
+    connection: &Arc<RwLock<ConnectionInfo>>,
+    mut buf: Vec<u8>,
+    frame_size: usize,
+) -> Result<(), ProtoError> {
+    if buf.len() != frame_size {
+        let written = buf.len();
+        connection.write().unwrap().buffer_pool.give(buf);
+        return Err(ProtoError::FrameSizeMismatch(frame_size, written));
+    }
+    let is_encrypted = connection.read().unwrap().encrypter.is_some();
+    if is_encrypted {
+        let mut encrypted_buf = {
+            let mut connection = connection.write().unwrap();
+            let mut encrypted_buf = connection.buffer_pool.take(buf.len());
+            encrypted_buf.resize(buf.len(), 0);
+            let encrypter = connection.encrypter.as_mut().unwrap();
+            encrypter.update(buf.as_slice(), &mut encrypted_buf).unwrap();
+            encrypted_buf
+        };
+        std::mem::swap(&mut buf, &mut encrypted_buf);
+        connection.write().unwrap().buffer_pool.give(encrypted_buf);
+    }
+    let result = stream.write_all(&buf).await.or_else(|err| Err(ProtoError::Io(err)));
+    connection.write().unwrap().buffer_pool.give(buf);
+    result
+}
+
 #[macro_export]
 macro_rules! packet_base {
     ($packet_name:ident $id:literal {
@@ -106,10 +171,14 @@ macro_rules! packet {
         #[async_trait]
         impl ReadProtPacket for $packet_name {
             #[allow(unused)]
-            async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, String> where Self: Sized {
+            async fn read(stream: &mut (impl AsyncRead + Unpin + Send)) -> Result<Self, ProtoError> where Self: Sized {
+                // fields are read into locals first so that conditions can refer to earlier fields
+                $(
+                    let $field = packet_base!(@read stream, $field_type, $($cond)?);
+                )*
                 Ok($packet_name {
                     $(
-                        $field: packet_base!(@read stream, $field_type, $($cond)?),
+                        $field,
                     )*
                 })
             }
@@ -142,31 +211,48 @@ macro_rules! packet {
         #[async_trait]
         impl WriteProtPacket for $packet_name {
             #[allow(unused)]
-            async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send), connection: Arc<RwLock<ConnectionInfo>>) -> Result<(), String> {
-                if env::var("LOG_PACKETS").is_ok_and(|s| s == "true") {
+            async fn write(&self, stream: &mut (impl AsyncWrite + Unpin + Send), connection: Arc<RwLock<ConnectionInfo>>) -> Result<(), ProtoError> {
+                let log_format = $crate::protocol_util::PacketLogFormat::from_env();
+                if log_format.is_some() {
                     debug!("Outbound packet: {self:?} (len {})", self.prot_size() + VarInt::from(self.prot_size()).prot_size());
                 }
-                let mut buf: Vec<u8> = Vec::with_capacity(self.prot_size() + VarInt::from(self.prot_size()).prot_size());
+                let len = self.prot_size() + VarInt::from(self.prot_size()).prot_size();
+                let mut buf: Vec<u8> = connection.write().unwrap().buffer_pool.take(len);
                 VarInt::from(self.prot_size()).write(&mut buf).await?;
                 VarInt::from(Self::id() as usize).write(&mut buf).await?;
                 $(
                     self.$field.write(&mut buf).await?;
                 )*
-                // encrypt `buf` with AES/CFB8 using `shared_secret` as the key.
-                let is_encrypted = connection.read().unwrap().encrypter.is_some();
-                if is_encrypted {
-                    let mut encrypted_buf = vec![0u8; buf.len()];
-                    {
-                        let mut encrypter = connection.write().unwrap();
-                        let encrypter = encrypter.encrypter.as_mut().unwrap();
-                        encrypter.update(buf.as_slice(), &mut encrypted_buf).unwrap();
-                    }
-                    stream.write_all(&encrypted_buf).await.or_else(|err| Err(format!("{err}")))?;
-                } else {
-                    stream.write_all(&buf).await.or_else(|err| Err(format!("{err}")))?;
+                if log_format == Some($crate::protocol_util::PacketLogFormat::Hex) {
+                    debug!("Outbound frame: {}", $crate::protocol_util::show(&buf));
                 }
-                Ok(())
+                $crate::protocol_util::send_packet_buf(stream, &connection, buf, len).await
             }
         }
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn mismatched_frame_not_sent() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let mut stream = vec![];
+        let result = send_packet_buf(&mut stream, &connection, vec![2, 0x50, 0, 0], 3).await;
+        assert!(matches!(result, Err(ProtoError::FrameSizeMismatch(3, 4))));
+        assert!(stream.is_empty());
+
+        send_packet_buf(&mut stream, &connection, vec![2, 0x50, 0], 3).await.unwrap();
+        assert_eq!(stream, vec![2, 0x50, 0]);
+    }
+
+    #[test]
+    fn hex_log_escapes_bytes() {
+        assert_eq!(show(&[0x03, 0x00, b'h', b'i', 0xff, b'\n', b'"']), "\\x03\\x00hi\\xff\\n\\\"");
+        assert_eq!(PacketLogFormat::parse(Some("hex")), PacketLogFormat::Hex);
+        assert_eq!(PacketLogFormat::parse(Some("debug")), PacketLogFormat::Debug);
+        assert_eq!(PacketLogFormat::parse(None), PacketLogFormat::Debug);
+    }
+}
@@ -0,0 +1,56 @@
+use std::net::IpAddr;
+
+use uuid::Uuid;
+
+/**
+ * Player data a BungeeCord-style proxy forwards in the server address of the Handshake packet,
+ * as `host\0clientIP\0uuid\0properties`. Velocity's legacy forwarding uses the same format.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ForwardedHandshake {
+    pub(crate) host: String,
+    pub(crate) client_ip: IpAddr,
+    // the uuid the proxy authenticated the player with
+    pub(crate) uuid: Uuid,
+    // JSON array of the player's profile properties, e.g. their skin
+    pub(crate) properties: Option<String>,
+}
+
+impl ForwardedHandshake {
+    pub(crate) fn parse(server_address: &str) -> Result<Self, String> {
+        let mut parts = server_address.split('\0');
+        let host = parts.next().unwrap_or_default().to_string();
+        let client_ip = parts
+            .next()
+            .ok_or("Handshake doesn't contain forwarded data")?
+            .parse()
+            .or_else(|err| Err(format!("Invalid forwarded client address: {err}")))?;
+        let uuid = parts
+            .next()
+            .ok_or("Forwarded data doesn't contain a uuid")?
+            .parse()
+            .or_else(|err| Err(format!("Invalid forwarded uuid: {err}")))?;
+        let properties = parts.next().map(String::from);
+        Ok(Self { host, client_ip, uuid, properties })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_forwarded_handshake() {
+        let address = "mc.example.com\x00203.0.113.7\x00069a79f444e94726a5befca90e38aaf5\x00[{\"name\":\"textures\",\"value\":\"abc\"}]";
+        let forwarded = ForwardedHandshake::parse(address).unwrap();
+        assert_eq!(forwarded.host, "mc.example.com");
+        assert_eq!(forwarded.client_ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.uuid.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(forwarded.properties.as_deref(), Some("[{\"name\":\"textures\",\"value\":\"abc\"}]"));
+
+        let without_properties = ForwardedHandshake::parse("localhost\x00::1\x00069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        assert_eq!(without_properties.properties, None);
+        assert!(ForwardedHandshake::parse("localhost").is_err());
+        assert!(ForwardedHandshake::parse("localhost\x00not an ip\x00069a79f444e94726a5befca90e38aaf5").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+use serde_json::Value;
+use tokio::net::UdpSocket;
+
+use crate::Assets;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const TYPE_HANDSHAKE: u8 = 9;
+const TYPE_STAT: u8 = 0;
+// only the lower 4 bits of each byte of the session id are used
+const SESSION_ID_MASK: i32 = 0x0f0f0f0f;
+// time a client may use its challenge token for stat requests
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+// full stat requests pad the challenge token with four bytes
+const FULL_STAT_LENGTH: usize = 15;
+const GAME_TYPE: &str = "SMP";
+const GAME_ID: &str = "MINECRAFT";
+const VERSION: &str = "1.20.2";
+const MAP: &str = "world";
+
+/**
+ * Challenge tokens handed out in query handshakes. Stat requests are only answered if they carry the
+ * token issued to their address, so the query port can't be used to reflect traffic at spoofed addresses.
+ */
+pub(crate) struct ChallengeTokens {
+    tokens: HashMap<SocketAddr, (i32, Instant)>,
+}
+
+impl ChallengeTokens {
+    pub(crate) fn new() -> Self {
+        Self { tokens: HashMap::new() }
+    }
+
+    pub(crate) fn issue(&mut self, addr: SocketAddr, now: Instant) -> i32 {
+        self.tokens.retain(|_, (_, issued)| now.duration_since(*issued) < TOKEN_LIFETIME);
+        let token = rand::random::<i32>() & i32::MAX;
+        self.tokens.insert(addr, (token, now));
+        token
+    }
+
+    pub(crate) fn verify(&self, addr: SocketAddr, token: i32, now: Instant) -> bool {
+        self.tokens
+            .get(&addr)
+            .is_some_and(|(issued_token, issued)| *issued_token == token && now.duration_since(*issued) < TOKEN_LIFETIME)
+    }
+}
+
+/**
+ * What the query reports about the server.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct ServerInfo {
+    pub(crate) motd: String,
+    pub(crate) players: Vec<String>,
+    pub(crate) max_players: i64,
+    pub(crate) host_port: u16,
+    pub(crate) host_ip: String,
+}
+
+impl ServerInfo {
+    async fn from_assets(assets: &Assets, addr: SocketAddr) -> Self {
+        // the MOTD is the status response template, its description is what the server list shows
+        let status: Value = serde_json::from_str(&assets.motd).unwrap_or_default();
+        let motd = match &status["description"] {
+            Value::String(text) => text.clone(),
+            description => description["text"].as_str().unwrap_or_default().to_string(),
+        };
+        Self {
+            motd,
+            players: assets.world.read().await.players().map(|player| player.username.clone()).collect(),
+            max_players: status["players"]["max"].as_i64().unwrap_or_default(),
+            host_port: addr.port(),
+            host_ip: addr.ip().to_string(),
+        }
+    }
+}
+
+fn header(typ: u8, session_id: i32) -> Vec<u8> {
+    let mut buf = vec![typ];
+    buf.extend(session_id.to_be_bytes());
+    buf
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend(s.as_bytes());
+    buf.push(0);
+}
+
+pub(crate) fn handshake_response(session_id: i32, token: i32) -> Vec<u8> {
+    let mut buf = header(TYPE_HANDSHAKE, session_id);
+    push_str(&mut buf, &token.to_string());
+    buf
+}
+
+pub(crate) fn basic_stat(session_id: i32, info: &ServerInfo) -> Vec<u8> {
+    let mut buf = header(TYPE_STAT, session_id);
+    push_str(&mut buf, &info.motd);
+    push_str(&mut buf, GAME_TYPE);
+    push_str(&mut buf, MAP);
+    push_str(&mut buf, &info.players.len().to_string());
+    push_str(&mut buf, &info.max_players.to_string());
+    // the port is the only little-endian value of the protocol
+    buf.extend(info.host_port.to_le_bytes());
+    push_str(&mut buf, &info.host_ip);
+    buf
+}
+
+pub(crate) fn full_stat(session_id: i32, info: &ServerInfo) -> Vec<u8> {
+    let mut buf = header(TYPE_STAT, session_id);
+    buf.extend(b"splitnum\0\x80\0");
+    let num_players = info.players.len().to_string();
+    let max_players = info.max_players.to_string();
+    let host_port = info.host_port.to_string();
+    let values = [
+        ("hostname", info.motd.as_str()),
+        ("gametype", GAME_TYPE),
+        ("game_id", GAME_ID),
+        ("version", VERSION),
+        ("plugins", ""),
+        ("map", MAP),
+        ("numplayers", &num_players),
+        ("maxplayers", &max_players),
+        ("hostport", &host_port),
+        ("hostip", &info.host_ip),
+    ];
+    for (key, value) in values {
+        push_str(&mut buf, key);
+        push_str(&mut buf, value);
+    }
+    buf.push(0);
+    buf.extend(b"\x01player_\0\0");
+    for player in &info.players {
+        push_str(&mut buf, player);
+    }
+    buf.push(0);
+    buf
+}
+
+/**
+ * Answers a query request, None if it is malformed or carries an invalid challenge token.
+ * The server info is only gathered for valid stat requests.
+ */
+pub(crate) async fn respond<F>(
+    request: &[u8],
+    addr: SocketAddr,
+    tokens: &mut ChallengeTokens,
+    now: Instant,
+    info: impl FnOnce() -> F,
+) -> Option<Vec<u8>>
+where
+    F: std::future::Future<Output = ServerInfo>,
+{
+    if request.len() < 7 || request[..2] != MAGIC {
+        return None;
+    }
+    let session_id = i32::from_be_bytes(request[3..7].try_into().ok()?) & SESSION_ID_MASK;
+    match request[2] {
+        TYPE_HANDSHAKE => Some(handshake_response(session_id, tokens.issue(addr, now))),
+        TYPE_STAT if request.len() >= 11 => {
+            let token = i32::from_be_bytes(request[7..11].try_into().ok()?);
+            if !tokens.verify(addr, token, now) {
+                debug!("Query from {addr} with invalid challenge token");
+                return None;
+            }
+            let info = info().await;
+            if request.len() >= FULL_STAT_LENGTH {
+                Some(full_stat(session_id, &info))
+            } else {
+                Some(basic_stat(session_id, &info))
+            }
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Answers query requests on the UDP port of the same number as the server.
+ */
+pub(crate) async fn serve(port: u16, assets: Arc<Assets>) {
+    info!("Starting up query on port {port}...");
+    let socket = UdpSocket::bind(SocketAddr::from(([127, 0, 0, 1], port)))
+        .await
+        .unwrap();
+    let local_addr = socket.local_addr().unwrap();
+    let mut tokens = ChallengeTokens::new();
+    let mut buf = [0u8; 1460];
+    loop {
+        let Ok((len, addr)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        let response = respond(&buf[..len], addr, &mut tokens, Instant::now(), || {
+            ServerInfo::from_assets(&assets, local_addr)
+        })
+        .await;
+        if let Some(response) = response {
+            if let Err(e) = socket.send_to(&response, addr).await {
+                debug!("Couldn't answer query from {addr}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn info() -> ServerInfo {
+        ServerInfo {
+            motd: "A Minecraft Server".into(),
+            players: vec!["steve".into(), "alex".into()],
+            max_players: 20,
+            host_port: 25565,
+            host_ip: "127.0.0.1".into(),
+        }
+    }
+
+    #[test]
+    fn challenge_tokens_bound_to_address_and_time() {
+        let mut tokens = ChallengeTokens::new();
+        let now = Instant::now();
+        let client: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let other: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        let token = tokens.issue(client, now);
+        assert!(token >= 0);
+        assert!(tokens.verify(client, token, now + Duration::from_secs(29)));
+        assert!(!tokens.verify(client, token.wrapping_add(1), now));
+        assert!(!tokens.verify(other, token, now));
+        assert!(!tokens.verify(client, token, now + TOKEN_LIFETIME));
+
+        let response = handshake_response(1, 9513307);
+        assert_eq!(response, b"\x09\0\0\0\x019513307\0");
+    }
+
+    #[test]
+    fn basic_stat_layout() {
+        let response = basic_stat(1, &info());
+        let mut expected = b"\x00\0\0\0\x01A Minecraft Server\0SMP\0world\x002\x0020\0".to_vec();
+        expected.extend([0xdd, 0x63]);
+        expected.extend(b"127.0.0.1\0");
+        assert_eq!(response, expected);
+    }
+
+    #[tokio::test]
+    async fn stat_requires_token() {
+        let mut tokens = ChallengeTokens::new();
+        let now = Instant::now();
+        let client: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let handshake = [0xfe, 0xfd, 9, 0, 0, 0, 1];
+        let response = respond(&handshake, client, &mut tokens, now, || async { info() }).await.unwrap();
+        let token: i32 = std::str::from_utf8(&response[5..response.len() - 1]).unwrap().parse().unwrap();
+
+        let mut stat = vec![0xfe, 0xfd, 0, 0, 0, 0, 1];
+        stat.extend(token.wrapping_add(1).to_be_bytes());
+        assert!(respond(&stat, client, &mut tokens, now, || async { info() }).await.is_none());
+
+        stat.truncate(7);
+        stat.extend(token.to_be_bytes());
+        assert_eq!(respond(&stat, client, &mut tokens, now, || async { info() }).await.unwrap(), basic_stat(1, &info()));
+        stat.extend([0; 4]);
+        let full = respond(&stat, client, &mut tokens, now, || async { info() }).await.unwrap();
+        assert!(full.ends_with(b"\x01player_\0\0steve\0alex\0\0"));
+    }
+}
@@ -0,0 +1,192 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+use crate::serve::ConnectionActorHandle;
+
+// default port of the RCON listener if RUSTCRAFT_RCON_PORT isn't set
+pub(crate) const RCON_PORT: u16 = 25575;
+
+// packet types, the auth response shares its value with EXECCOMMAND
+const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_AUTH: i32 = 3;
+// request id of the auth response if the password was wrong
+const AUTH_FAILED_ID: i32 = -1;
+// request id, type and the two null terminators
+const MIN_PACKET_LENGTH: i32 = 10;
+const MAX_PACKET_LENGTH: i32 = 4096;
+// time a connection has to answer a request for its player info before it's left out of the player list
+const PLAYER_INFO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/**
+ * A packet of the Source RCON protocol: a little-endian length, request id and type followed by a
+ * null-terminated ASCII payload and an empty null-terminated string.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RconPacket {
+    pub(crate) request_id: i32,
+    pub(crate) typ: i32,
+    pub(crate) payload: String,
+}
+
+impl RconPacket {
+    pub(crate) fn new(request_id: i32, typ: i32, payload: String) -> Self {
+        Self { request_id, typ, payload }
+    }
+
+    pub(crate) async fn read(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Self> {
+        let length = stream.read_i32_le().await?;
+        if !(MIN_PACKET_LENGTH..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid RCON packet length {length}")));
+        }
+        let request_id = stream.read_i32_le().await?;
+        let typ = stream.read_i32_le().await?;
+        let mut body = vec![0u8; length as usize - 8];
+        stream.read_exact(&mut body).await?;
+        if !body.ends_with(&[0, 0]) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "RCON payload isn't null-terminated"));
+        }
+        body.truncate(body.len() - 2);
+        let payload = String::from_utf8(body).or_else(|err| Err(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+        Ok(Self { request_id, typ, payload })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let length = self.payload.len() as i32 + MIN_PACKET_LENGTH;
+        let mut buf = Vec::with_capacity(length as usize + 4);
+        buf.extend(length.to_le_bytes());
+        buf.extend(self.request_id.to_le_bytes());
+        buf.extend(self.typ.to_le_bytes());
+        buf.extend(self.payload.as_bytes());
+        buf.extend([0, 0]);
+        buf
+    }
+
+    pub(crate) async fn write(&self, stream: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        stream.write_all(&self.encode()).await
+    }
+}
+
+/**
+ * Accepts RCON clients on the given port. Clients have to authenticate with the password before
+ * their commands are run.
+ */
+pub(crate) async fn serve(port: u16, password: String, connections: Arc<RwLock<Vec<ConnectionActorHandle>>>) {
+    info!("Starting up RCON on port {port}...");
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))
+        .await
+        .unwrap();
+    loop {
+        let Ok((stream, addr)) = listener.accept().await else {
+            continue;
+        };
+        debug!("RCON connection from {addr}");
+        let password = password.clone();
+        let connections = connections.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, &password, connections).await {
+                debug!("RCON connection from {addr} closed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    mut stream: TcpStream,
+    password: &str,
+    connections: Arc<RwLock<Vec<ConnectionActorHandle>>>,
+) -> io::Result<()> {
+    let mut authenticated = false;
+    loop {
+        let packet = RconPacket::read(&mut stream).await?;
+        match packet.typ {
+            SERVERDATA_AUTH => {
+                authenticated = packet.payload == password;
+                if !authenticated {
+                    warn!("RCON client {:?} sent a wrong password", stream.peer_addr());
+                }
+                let id = if authenticated { packet.request_id } else { AUTH_FAILED_ID };
+                RconPacket::new(id, SERVERDATA_AUTH_RESPONSE, String::new()).write(&mut stream).await?;
+            }
+            SERVERDATA_EXECCOMMAND if authenticated => {
+                let response = execute(&packet.payload, &connections).await;
+                RconPacket::new(packet.request_id, SERVERDATA_RESPONSE_VALUE, response).write(&mut stream).await?;
+            }
+            SERVERDATA_EXECCOMMAND => {
+                RconPacket::new(AUTH_FAILED_ID, SERVERDATA_AUTH_RESPONSE, String::new()).write(&mut stream).await?;
+            }
+            typ => debug!("Ignoring RCON packet of type {typ}"),
+        }
+    }
+}
+
+/**
+ * Runs a console command and returns its output.
+ */
+async fn execute(command: &str, connections: &RwLock<Vec<ConnectionActorHandle>>) -> String {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    match name {
+        "list" => {
+            let players = online_players(connections).await;
+            format!("There are {} players online: {}", players.len(), players.join(", "))
+        }
+        _ => format!("Unknown command: {name}"),
+    }
+}
+
+async fn online_players(connections: &RwLock<Vec<ConnectionActorHandle>>) -> Vec<String> {
+    let mut players = vec![];
+    // the handles are copied out so connections can join or leave while waiting for the actors to respond
+    let connections = connections.read().await.clone();
+    for connection in connections.iter() {
+        match connection.player_info(PLAYER_INFO_TIMEOUT).await {
+            // connections that haven't logged in yet, e.g. server list pings, have no username
+            Some(player) if !player.username.is_empty() => players.push(player.username),
+            _ => {}
+        }
+    }
+    players
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn packet_round_trips() {
+        let packet = RconPacket::new(7, SERVERDATA_EXECCOMMAND, "list".into());
+        let bytes = packet.encode();
+        assert_eq!(bytes, [
+            14, 0, 0, 0, // length
+            7, 0, 0, 0, // request id
+            2, 0, 0, 0, // type
+            b'l', b'i', b's', b't', 0, 0,
+        ]);
+        assert_eq!(RconPacket::read(&mut bytes.as_slice()).await.unwrap(), packet);
+    }
+
+    #[tokio::test]
+    async fn malformed_packets_rejected() {
+        let too_short = [4u8, 0, 0, 0, 1, 0, 0, 0];
+        assert!(RconPacket::read(&mut too_short.as_slice()).await.is_err());
+
+        let mut unterminated = RconPacket::new(1, SERVERDATA_AUTH, "secret".into()).encode();
+        let last = unterminated.len() - 1;
+        unterminated[last] = b'!';
+        assert!(RconPacket::read(&mut unterminated.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn list_without_players() {
+        let connections = RwLock::new(vec![]);
+        assert_eq!(execute("list", &connections).await, "There are 0 players online: ");
+    }
+}
@@ -1,30 +1,70 @@
+use std::collections::HashMap;
 use std::env;
 use std::net::SocketAddr;
+use std::str::FromStr;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use base64::Engine;
-use base64::engine::general_purpose;
 use dotenv::dotenv;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use openssl::rsa::Rsa;
 use tokio::fs;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::sync::{mpsc, oneshot};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::error::SendTimeoutError;
 use rustcraft_lib::web::dto;
+use uuid::Uuid;
 
-use crate::{Assets, MSG, ONLINE, PORT, web};
-use crate::chunk::world::World;
-use crate::connection::{ConnectionInfo, ConnectionState};
+use crate::{Assets, MSG, ONLINE, PORT, REGION_DIR, query, rcon, status, web};
+use crate::chunk::anvil;
+use crate::chunk::section::ChunkSection;
+use crate::chunk::world::{World, TICKS_PER_SECOND};
+use crate::connection::{ConnectionInfo, ConnectionState, EntityIds};
+use crate::data::global_palette::{load_global_palette, GlobalPalette};
+use crate::data::items::load_items;
+use crate::data::ops::load_ops;
 use crate::data::registry::load_registry;
 use crate::err::ProtError;
+use crate::login_limiter::LoginLimiter;
 use crate::packets::{client, parse};
 use crate::packets::client::ClientPackets;
 use crate::protocol_types::traits::WriteProtPacket;
-use crate::serve::ConnectionActorMessage::{PlayerInfo, SendPacket};
+use crate::protocol_types::compound::{BossBarAction, Chat};
+use crate::protocol_types::primitives::MAX_CHAT_CHARS;
+use crate::protocol_util::PacketLogFormat;
+use crate::serve::ConnectionActorMessage::{Broadcast, Close, PlayerInfo, SendPacket};
 
 
+// how often chunks are unloaded if the number of loaded chunks is capped
+const CHUNK_UNLOAD_INTERVAL: Duration = Duration::from_secs(30);
+// columns within this many chunks of a player are never unloaded, the view distance sent in Login (play)
+const UNLOAD_KEEP_RADIUS: i32 = 10;
+// time a message may wait for room in the send queue, or a packet may take to be written, before the client is considered too slow
+const SLOW_CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// messages handled before the packets they wrote are flushed, even if more are queued
+const MAX_WRITE_BATCH: usize = 64;
+
+/**
+ * Queues a message for the message handler. Fails if the queue stays full for longer than the timeout,
+ * which means the client doesn't read its packets fast enough.
+ */
+async fn enqueue(
+    sender: &Sender<ConnectionActorMessage>,
+    msg: ConnectionActorMessage,
+    timeout: Duration,
+) -> Result<(), ProtError> {
+    match sender.send_timeout(msg, timeout).await {
+        Ok(()) => Ok(()),
+        Err(SendTimeoutError::Timeout(_)) => Err(ProtError::SlowClient),
+        Err(SendTimeoutError::Closed(_)) => Err(ProtError::ChannelClosed),
+    }
+}
+
 async fn accept_packet(
     read: &mut OwnedReadHalf,
     connection: Arc<RwLock<ConnectionInfo>>,
@@ -35,7 +75,7 @@ async fn accept_packet(
         let packet = parse::parse_packet(read, connection.clone()).await;
         match packet {
             Ok(p) => {
-                if env::var("LOG_PACKETS").is_ok_and(|s| s == "true") {
+                if PacketLogFormat::from_env().is_some() {
                     debug!("{} Inbound packet: {p:?}", read.peer_addr().unwrap());
                 }
                 let res = p.handle(connection.clone(), assets.clone()).await;
@@ -43,14 +83,14 @@ async fn accept_packet(
             }
             Err(err) => {
                 error!("Couldn't parse packet: {err}");
-                return Err(ProtError::Any(err));
+                return Err(err);
             }
         }
     };
 
     if let Ok(ps) = result {
         for packet in ps {
-            sender.send(SendPacket(packet)).await.unwrap();
+            enqueue(&sender, SendPacket(packet), SLOW_CLIENT_TIMEOUT).await?;
         }
     } else if let Err(e) = result {
         error!("Couldn't handle packet {e}");
@@ -73,10 +113,10 @@ struct ConnectionActor {
 }
 
 impl ConnectionActor {
-    fn new(receiver: Receiver<ConnectionActorMessage>) -> Self {
+    fn new(receiver: Receiver<ConnectionActorMessage>, connection: Arc<RwLock<ConnectionInfo>>) -> Self {
         Self {
             receiver,
-            connection: Arc::new(RwLock::new(ConnectionInfo::new())),
+            connection,
         }
     }
 
@@ -88,44 +128,80 @@ impl ConnectionActor {
         assets: Arc<Assets>,
     ) {
         let connection = self.connection.clone();
-        let assets = assets.clone();
+        let assets_clone = assets.clone();
         let sender_clone = sender.clone();
         tokio::spawn(
-            async move { run_packet_handler(connection, read, sender_clone, assets).await },
+            async move { run_packet_handler(connection, read, sender_clone, assets_clone).await },
         );
         let connection = self.connection.clone();
         tokio::spawn(async move { run_heartbeat(connection, sender).await });
         self.run_msg_handler(write).await;
+        self.shut_down(&assets).await;
+    }
+
+    /**
+     * Closes the queue once the message handler has stopped, then leaves the server. Tasks still waiting for
+     * room in the queue fail right away instead of holding up others while this connection is removed.
+     */
+    async fn shut_down(&mut self, assets: &Assets) {
+        self.receiver.close();
+        // messages still queued are dropped, which also answers pending player info requests
+        while self.receiver.try_recv().is_ok() {}
+        leave(&self.connection, assets).await;
     }
 
     /**
      * Runs the internal channel message handler.
      * Primarily, messages sent over this channel tell the handler task to send packets over the TCP connection.
      * Another use case is the web interface requesting player information from the connection object.
+     * Packets are written to a buffer which is flushed once the queued messages are drained, so bursts
+     * like the initial chunks don't cost a syscall per packet.
      */
-    async fn run_msg_handler(&mut self, mut write: OwnedWriteHalf) {
+    async fn run_msg_handler(&mut self, write: impl AsyncWrite + Unpin + Send) {
+        let mut write = BufWriter::new(write);
         while let Some(msg) = self.receiver.recv().await {
-            let result = self.handle(msg, &mut write).await;
-            if let Err(e) = result {
-                if e.is_fatal() {
-                    self.connection.write().unwrap().close();
-                    break;
+            let mut next = Some(msg);
+            let mut batch = 0;
+            while let Some(msg) = next.take() {
+                self.handle_timed(msg, &mut write).await;
+                batch += 1;
+                if batch < MAX_WRITE_BATCH && !self.connection.read().unwrap().closed() {
+                    next = self.receiver.try_recv().ok();
                 }
             }
+            // also flushed when closing, as the batch may end with a disconnect packet
+            if !matches!(tokio::time::timeout(SLOW_CLIENT_TIMEOUT, write.flush()).await, Ok(Ok(()))) {
+                self.connection.write().unwrap().close();
+            }
+            if self.connection.read().unwrap().closed() {
+                break;
+            }
+        }
+    }
+
+    // handles a message, closing the connection if that fails fatally or takes too long
+    async fn handle_timed(&mut self, msg: ConnectionActorMessage, write: &mut (impl AsyncWrite + Unpin + Send)) {
+        let result = match tokio::time::timeout(SLOW_CLIENT_TIMEOUT, self.handle(msg, write)).await {
+            Ok(result) => result,
+            Err(_) => Err(ProtError::SlowClient),
+        };
+        if let Err(e) = result {
+            if e.is_fatal() {
+                self.connection.write().unwrap().close();
+            }
         }
     }
 
     async fn handle(
         &mut self,
         msg: ConnectionActorMessage,
-        write: &mut OwnedWriteHalf,
+        write: &mut (impl AsyncWrite + Unpin + Send),
     ) -> Result<(), ProtError> {
         match msg {
             SendPacket(packet) => {
                 packet
                     .write(write, self.connection.clone())
-                    .await
-                    .or_else(|err| Err(ProtError::Any(err)))?;
+                    .await?;
             },
             PlayerInfo(sender) => {
                 let player = {
@@ -133,6 +209,9 @@ impl ConnectionActor {
                     dto::Player {
                         username: connection.username.clone(),
                         uuid: connection.uuid.to_string(),
+                        brand: connection.client_brand.clone(),
+                        // filled in by the caller, which holds the sending end of the queue
+                        send_queue_depth: 0,
                         position: dto::Position {
                             x: connection.position.x,
                             y: connection.position.y,
@@ -143,7 +222,22 @@ impl ConnectionActor {
                         },
                     }
                 };
-                sender.send(player).unwrap();
+                // the requester may have stopped waiting for the answer
+                let _ = sender.send(player);
+            }
+            Close => {
+                self.connection.write().unwrap().close();
+            }
+            Broadcast(except, packet) => {
+                let skip = {
+                    let connection = self.connection.read().unwrap();
+                    except == Some(connection.uuid) || !matches!(connection.state(), ConnectionState::Play)
+                };
+                if !skip {
+                    packet
+                        .write(write, self.connection.clone())
+                        .await?;
+                }
             }
         }
         Ok(())
@@ -192,12 +286,48 @@ async fn run_packet_handler(
         .await;
 
         if let Err(e) = result {
-            if e.is_fatal() {
+            if matches!(e, ProtError::SlowClient) {
+                // a disconnect packet would end up in the full queue as well
+                info!("Connection {:?} is too slow, closing it.", address);
                 connection.write().unwrap().close();
                 break;
             }
+            if e.is_fatal() {
+                disconnect(&connection, &sender, e.disconnect_reason()).await;
+                break;
+            }
+        }
+    }
+    // wake up the message handler so the actor can shut down, the connection registry keeps the channel open
+    let _ = sender.try_send(Close);
+}
+
+/**
+ * Sends the client a disconnect packet with the given reason, if its current state has one, and closes the connection
+ * once the packet has been written.
+ */
+async fn disconnect(
+    connection: &Arc<RwLock<ConnectionInfo>>,
+    sender: &Sender<ConnectionActorMessage>,
+    reason: Chat,
+) {
+    let state = connection.read().unwrap().state().clone();
+    info!("Disconnecting {}.", connection.read().unwrap().username);
+    let packet = match state {
+        ConnectionState::Login => Some(ClientPackets::LoginDisconnect(client::LoginDisconnect::new(reason))),
+        ConnectionState::Configuration => Some(ClientPackets::ConfigurationDisconnect(client::ConfigurationDisconnect::new(reason))),
+        ConnectionState::Play => Some(ClientPackets::PlayDisconnect(client::PlayDisconnect::new(reason))),
+        _ => None,
+    };
+    if let Some(packet) = packet {
+        if sender.send(SendPacket(packet)).await.is_err() {
+            connection.write().unwrap().close();
+            return;
         }
     }
+    if sender.send(Close).await.is_err() {
+        connection.write().unwrap().close();
+    }
 }
 
 /**
@@ -213,29 +343,86 @@ async fn run_heartbeat(
         if connection.read().unwrap().closed() {
             break;
         }
-        let keep_alive_id = rand::random::<i64>();
-        let state = {
+        let timed_out = connection.read().unwrap().keep_alive.timed_out(Instant::now());
+        if timed_out {
+            disconnect(&connection, &sender, Chat::new_text("timed out".into())).await;
+            break;
+        }
+        let (keep_alive_id, state) = {
             let mut connection = connection.write();
             let connection = connection.as_mut().unwrap();
-            connection.keep_alive_id = keep_alive_id;
-            connection.state().clone()
+            // wait for the client to answer the previous keep alive before sending a new one
+            if connection.keep_alive.pending() {
+                continue;
+            }
+            (connection.keep_alive.send(Instant::now()), connection.state().clone())
         };
-        match state {
+        let packet = match state {
             ConnectionState::Configuration => {
-                let packet = client::ConfigurationKeepAlive::new(keep_alive_id);
-                sender
-                    .send(SendPacket(ClientPackets::ConfigurationKeepAlive(packet)))
-                    .await
-                    .unwrap();
-            }
-            ConnectionState::Play => {
-                let packet = client::PlayKeepAlive::new(keep_alive_id);
-                sender
-                    .send(SendPacket(ClientPackets::PlayKeepAlive(packet)))
-                    .await
-                    .unwrap();
+                ClientPackets::ConfigurationKeepAlive(client::ConfigurationKeepAlive::new(keep_alive_id))
             }
+            ConnectionState::Play => ClientPackets::PlayKeepAlive(client::PlayKeepAlive::new(keep_alive_id)),
             _ => break,
+        };
+        // the queue is closed once the actor shuts down
+        if sender.send(SendPacket(packet)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/**
+ * Advances the time of day of the world every second and tells all players about it.
+ */
+async fn run_day_cycle(assets: Arc<Assets>) {
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        tick_day_cycle(&assets).await;
+    }
+}
+
+/**
+ * Advances the time of day by a second, unless the server pauses while nobody is online.
+ */
+async fn tick_day_cycle(assets: &Assets) {
+    if assets.pause_when_empty && assets.online_players.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let (world_age, time_of_day) = {
+        let mut world = assets.world.write().await;
+        world.tick_time(TICKS_PER_SECOND);
+        (world.world_age, world.time_of_day)
+    };
+    let packet = client::UpdateTime::new(world_age, time_of_day);
+    broadcast(assets, None, ClientPackets::UpdateTime(packet)).await;
+}
+
+/**
+ * Periodically unloads the columns beyond `max_columns` that no player is near. Unloaded columns are
+ * written to the region files first, so they are back after a restart.
+ */
+async fn run_chunk_unloader(assets: Arc<Assets>, max_columns: usize) {
+    loop {
+        tokio::time::sleep(CHUNK_UNLOAD_INTERVAL).await;
+        let unloaded = {
+            let mut world = assets.world.write().await;
+            let candidates = world.unload_candidates(max_columns, UNLOAD_KEEP_RADIUS);
+            world.unload_columns(&candidates)
+        };
+        if unloaded.is_empty() {
+            continue;
+        }
+        debug!("Unloading {} chunk columns", unloaded.len());
+        let columns: HashMap<(i32, i32), Vec<&ChunkSection>> = unloaded
+            .iter()
+            .map(|(pos, column)| (*pos, column.iter().collect()))
+            .collect();
+        if let Err(e) = anvil::write_regions(Path::new(REGION_DIR), &columns, &assets.global_palette).await {
+            error!("Couldn't save unloaded chunks, keeping them loaded: {e}");
+            let mut world = assets.world.write().await;
+            for ((x, z), column) in unloaded {
+                world.insert_column(x, z, column);
+            }
         }
     }
 }
@@ -243,65 +430,537 @@ async fn run_heartbeat(
 #[derive(Clone)]
 pub(crate) struct ConnectionActorHandle {
     sender: Sender<ConnectionActorMessage>,
+    // shared with the actor, lets other connections look up the player without a round trip over the channel
+    connection: Arc<RwLock<ConnectionInfo>>,
 }
 
 impl ConnectionActorHandle {
     pub fn new(stream: TcpStream, assets: Arc<Assets>) -> Self {
         let (sender, receiver) = mpsc::channel(8);
+        let address = stream.peer_addr().ok().map(|addr| addr.ip());
         let (read, write) = stream.into_split();
-        let mut actor = ConnectionActor::new(receiver);
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().address = address;
+        let mut actor = ConnectionActor::new(receiver, connection.clone());
         let sender_clone = sender.clone();
         tokio::spawn(async move {
             actor.run(read, write, sender_clone, assets).await;
         });
 
-        Self { sender }
+        Self { sender, connection }
+    }
+
+    // a handle without an actor behind it, messages sent to it end up in the returned receiver
+    #[cfg(test)]
+    pub(crate) fn detached(connection: Arc<RwLock<ConnectionInfo>>) -> (Self, Receiver<ConnectionActorMessage>) {
+        let (sender, receiver) = mpsc::channel(8);
+        (Self { sender, connection }, receiver)
+    }
+
+    // number of messages waiting in the send queue of the connection
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+
+    pub(crate) fn connection(&self) -> &Arc<RwLock<ConnectionInfo>> {
+        &self.connection
+    }
+
+    /**
+     * Queues a message for the connection and returns whether it was queued. If the queue stays full for too
+     * long the client is too slow and its connection is closed, like when it can't keep up with its own packets.
+     */
+    pub async fn send(&self, msg: ConnectionActorMessage) -> bool {
+        match enqueue(&self.sender, msg, SLOW_CLIENT_TIMEOUT).await {
+            Ok(()) => true,
+            // the actor has shut down already, there's nobody to send the message to
+            Err(ProtError::ChannelClosed) => false,
+            Err(_) => {
+                info!("{} is too slow, closing the connection.", self.connection.read().unwrap().username);
+                self.connection.write().unwrap().close();
+                false
+            }
+        }
+    }
+
+    /**
+     * Queues a message only if there is room for it right away, for callers that must not wait on slow clients.
+     */
+    pub(crate) fn try_send(&self, msg: ConnectionActorMessage) -> bool {
+        self.sender.try_send(msg).is_ok()
+    }
+
+    /**
+     * Asks the actor for the player's info. None if the actor has shut down or doesn't answer in time.
+     */
+    pub(crate) async fn player_info(&self, timeout: Duration) -> Option<dto::Player> {
+        let (sender, receiver) = oneshot::channel();
+        if !self.try_send(PlayerInfo(sender)) {
+            return None;
+        }
+        // the actor drops the sender without responding if it shuts down in the meantime
+        tokio::time::timeout(timeout, receiver).await.ok()?.ok()
+    }
+
+    /**
+     * Shows, updates or removes the boss bar with the given id for this connection.
+     */
+    pub(crate) async fn show_bossbar(&self, id: Uuid, action: BossBarAction) {
+        self.send(SendPacket(ClientPackets::BossBar(client::BossBar::new(id, action)))).await;
+    }
+
+    /**
+     * Shows a title with an optional subtitle in the middle of the screen. The times are in ticks, the
+     * subtitle is sent first because the client only displays it along with the next title.
+     */
+    pub(crate) async fn send_title(&self, title: Chat, subtitle: Option<Chat>, fade_in: i32, stay: i32, fade_out: i32) {
+        let times = client::SetTitleAnimationTimes::new(fade_in, stay, fade_out);
+        self.send(SendPacket(ClientPackets::SetTitleAnimationTimes(times))).await;
+        if let Some(subtitle) = subtitle {
+            self.send(SendPacket(ClientPackets::SetSubtitleText(client::SetSubtitleText::new(subtitle)))).await;
+        }
+        self.send(SendPacket(ClientPackets::SetTitleText(client::SetTitleText::new(title)))).await;
+    }
+}
+
+/**
+ * Cleans up after a closed connection: the player is removed from the world and the connection registry,
+ * and the remaining players are told to remove its entity and tab list entry.
+ */
+pub(crate) async fn leave(connection: &Arc<RwLock<ConnectionInfo>>, assets: &Assets) {
+    // the registry is only locked for removing the handle, never while waiting for other connections
+    assets.connections.write().await.retain(|handle| !Arc::ptr_eq(handle.connection(), connection));
+    let (uuid, entity_id, in_play) = {
+        let connection = connection.read().unwrap();
+        (connection.uuid, connection.entity_id, matches!(connection.state(), ConnectionState::Play))
+    };
+    if !in_play {
+        return;
+    }
+    assets.online_players.fetch_sub(1, Ordering::AcqRel);
+    assets.world.write().await.remove_player(uuid);
+    let remove_entity = client::RemoveEntities::new(vec![entity_id.into()].into());
+    broadcast(assets, Some(uuid), ClientPackets::RemoveEntities(remove_entity)).await;
+    let remove_info = client::PlayerInfoRemove::new(vec![uuid].into());
+    broadcast(assets, Some(uuid), ClientPackets::PlayerInfoRemove(remove_info)).await;
+}
+
+/**
+ * Sends a packet to every player in the Play state, except the one with the given uuid if there is one.
+ */
+pub(crate) async fn broadcast(assets: &Assets, except: Option<Uuid>, packet: ClientPackets) {
+    // the handles are copied out so the registry isn't locked while waiting for room in a slow client's queue
+    let handles = assets.connections.read().await.clone();
+    for handle in handles {
+        handle.send(Broadcast(except, packet.clone())).await;
     }
+}
 
-    pub async fn send(&self, msg: ConnectionActorMessage) {
-        self.sender.send(msg).await.unwrap();
+/**
+ * Sends a packet to the players with the given uuids.
+ */
+pub(crate) async fn send_to(assets: &Assets, players: &[Uuid], packet: ClientPackets) {
+    let handles: Vec<ConnectionActorHandle> = assets
+        .connections
+        .read()
+        .await
+        .iter()
+        .filter(|handle| players.contains(&handle.connection().read().unwrap().uuid))
+        .cloned()
+        .collect();
+    for handle in handles {
+        handle.send(SendPacket(packet.clone())).await;
     }
 }
 
 pub(crate) enum ConnectionActorMessage {
     SendPacket(ClientPackets),
-    PlayerInfo(oneshot::Sender<dto::Player>)
+    PlayerInfo(oneshot::Sender<dto::Player>),
+    // closes the connection once all previously queued packets have been sent
+    Close,
+    // sends the packet unless this connection belongs to the given player or isn't in the Play state
+    Broadcast(Option<Uuid>, ClientPackets),
+}
+
+/**
+ * Parses the value of a setting, falling back to `default` if the value is missing or invalid.
+ */
+fn parse_or<T: FromStr>(name: &str, value: Option<String>, default: T) -> T {
+    match value.map(|value| value.trim().parse::<T>()) {
+        Some(Ok(parsed)) => parsed,
+        Some(Err(_)) => {
+            warn!("Invalid value for {name}, using the default");
+            default
+        }
+        None => default,
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    parse_or(name, env::var(name).ok(), default)
+}
+
+/**
+ * Reads the status response template from the file at RUSTCRAFT_MOTD_PATH, or uses the built-in one.
+ */
+async fn load_motd() -> String {
+    let Ok(path) = env::var("RUSTCRAFT_MOTD_PATH") else {
+        return MSG.to_string();
+    };
+    match fs::read_to_string(&path).await {
+        Ok(motd) => motd,
+        Err(e) => {
+            warn!("Couldn't read the MOTD from {path}, using the default: {e}");
+            MSG.to_string()
+        }
+    }
+}
+
+/**
+ * Loads the world from REGION_DIR, or generates a new one if nothing was saved there yet.
+ */
+async fn load_world(palette: &GlobalPalette) -> World {
+    let mut world = if Path::new(REGION_DIR).exists() {
+        info!("Loading world from {REGION_DIR}...");
+        World::load_region(Path::new(REGION_DIR), palette).await.unwrap()
+    } else {
+        World::new_generated(rand::random::<u64>(), 3)
+    };
+    world.region = Some(REGION_DIR.into());
+    world
 }
 
 pub(crate) async fn start_server() {
-    let icon = fs::read("icon.png").await.unwrap();
+    let port: u16 = env_or("RUSTCRAFT_PORT", PORT);
+    let online: bool = env_or("RUSTCRAFT_ONLINE", ONLINE);
+    info!("Starting up server on port {port}...");
+    // clients connecting while the server is starting get a status response saying so and can't log in yet
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))
+        .await
+        .unwrap();
+    let icon = match fs::read("icon.png").await {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            warn!("Couldn't read the server icon, the status won't have a favicon: {e}");
+            None
+        }
+    };
     let rsa = Rsa::generate(1024).unwrap();
-    let motd =
-        String::from(MSG).replacen("§§§", &general_purpose::STANDARD.encode(icon.as_slice()), 1);
+    let motd = load_motd().await;
+    let motd = status::with_favicon(&motd, icon.as_deref()).unwrap_or_else(|e| {
+        warn!("The status response template isn't valid JSON: {e}");
+        motd
+    });
     let registry = load_registry().await.unwrap();
+    let global_palette = load_global_palette().await.unwrap();
+    let items = load_items().await.unwrap();
+    let ops = load_ops().await.unwrap();
 
     let assets = Assets {
         pub_key: rsa.public_key_to_der().unwrap(),
         key: rsa,
-        online: ONLINE,
+        online,
+        bungee_forwarding: env::var("RUSTCRAFT_PROXY").is_ok_and(|s| s == "bungee"),
         motd,
+        brand: env::var("SERVER_BRAND").unwrap_or("rustcraft".into()),
+        allow_flight: env::var("ALLOW_FLIGHT").is_ok_and(|s| s == "true"),
+        status_sample: status::static_sample_from_env(),
+        hide_player_sample: env::var("HIDE_PLAYER_SAMPLE").is_ok_and(|s| s == "true"),
+        configuration_ping: env::var("CONFIGURATION_PING").is_ok_and(|s| s == "true"),
+        max_loaded_columns: env::var("MAX_LOADED_CHUNKS").ok().and_then(|s| s.parse().ok()),
         registry,
-        world: RwLock::new(World::new_grass()),
+        global_palette,
+        items,
+        ops,
+        max_chat_length: env_or("MAX_CHAT_LENGTH", MAX_CHAT_CHARS),
+        // replaced by the loaded world once it's ready
+        world: tokio::sync::RwLock::new(World::new()),
+        online_players: AtomicUsize::new(0),
+        pause_when_empty: env::var("PAUSE_WHEN_EMPTY").is_ok_and(|s| s == "true"),
+        ready: AtomicBool::new(false),
+        connections: Arc::new(tokio::sync::RwLock::new(vec![])),
+        entity_ids: EntityIds::new(),
+        login_limiter: LoginLimiter::from_env(),
     };
     let assets = Arc::new(assets);
 
-
-    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], PORT)))
-        .await
-        .unwrap();
-
     // We need an async RwLock here due to axum's state management
-    let connection_handles: Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>> = Arc::new(tokio::sync::RwLock::new(vec![]));
-    let connection_handles_clone = connection_handles.clone();
+    let connection_handles_clone = assets.connections.clone();
     tokio::spawn(async move {
         web::serve::init(connection_handles_clone).await;
     });
 
+    match env::var("RUSTCRAFT_RCON_PASSWORD") {
+        Ok(password) if !password.is_empty() => {
+            let port = env_or("RUSTCRAFT_RCON_PORT", rcon::RCON_PORT);
+            let connections = assets.connections.clone();
+            tokio::spawn(async move { rcon::serve(port, password, connections).await });
+        }
+        Ok(_) => warn!("RUSTCRAFT_RCON_PASSWORD is empty, not starting RCON"),
+        Err(_) => {}
+    }
+
+    if env::var("RUSTCRAFT_QUERY").is_ok_and(|s| s == "true") {
+        let assets_clone = assets.clone();
+        tokio::spawn(async move { query::serve(port, assets_clone).await });
+    }
+
+    let assets_clone = assets.clone();
+    tokio::spawn(async move { run_day_cycle(assets_clone).await });
+
+    if let Some(max_columns) = assets.max_loaded_columns {
+        let assets_clone = assets.clone();
+        tokio::spawn(async move { run_chunk_unloader(assets_clone, max_columns).await });
+    }
+
+    let assets_clone = assets.clone();
+    tokio::spawn(async move {
+        let world = load_world(&assets_clone.global_palette).await;
+        *assets_clone.world.write().await = world;
+        assets_clone.ready.store(true, Ordering::Release);
+        info!("Server is ready.");
+    });
+
     // For every incoming connection on the listener, we spawn a new task with a reference to the assets (possibly an arc or sth else), and the stream
     loop {
-        let (stream, addr) = listener.accept().await.unwrap();
-        let assets = assets.clone();
-        let handle = ConnectionActorHandle::new(stream, assets);
-        connection_handles.write().await.push(handle);
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, addr) = accepted.unwrap();
+                let assets = assets.clone();
+                let handle = ConnectionActorHandle::new(stream, assets.clone());
+                assets.connections.write().await.push(handle);
+            }
+            _ = tokio::signal::ctrl_c() => {
+                // the world hasn't been loaded yet, there's nothing to save
+                if !assets.ready.load(Ordering::Acquire) {
+                    break;
+                }
+                info!("Saving world to {REGION_DIR}...");
+                let world = assets.world.read().await;
+                if let Err(e) = world.save_region(Path::new(REGION_DIR), &assets.global_palette).await {
+                    error!("Couldn't save world: {e}");
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::{AIR, STONE};
+    use crate::protocol_types::compound::Position;
+
+    #[tokio::test]
+    async fn teleport_id_mismatch_disconnects_with_reason() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let teleport_id = connection.write().unwrap().begin_teleport();
+        let err = connection.write().unwrap().confirm_teleport((teleport_id.value + 1).into()).unwrap_err();
+        assert!(err.is_fatal());
+
+        let (sender, mut receiver) = mpsc::channel(8);
+        disconnect(&connection, &sender, err.disconnect_reason()).await;
+        match receiver.recv().await {
+            Some(SendPacket(ClientPackets::PlayDisconnect(packet))) => {
+                let reason = format!("{packet:?}");
+                assert!(reason.contains("Invalid teleport confirmation"));
+                assert!(reason.contains(&teleport_id.to_string()));
+            }
+            _ => panic!("Expected a disconnect packet"),
+        }
+        assert!(matches!(receiver.recv().await, Some(Close)));
+    }
+
+    // counts the writes that would each be a syscall on a socket
+    struct CountingWriter {
+        writes: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(mut self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, buf: &[u8]) -> std::task::Poll<std::io::Result<usize>> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_packets_written_in_one_go() {
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let packets: Vec<ClientPackets> = (0..49)
+            .map(|i| ClientPackets::SetCenterChunk(client::SetCenterChunk::new(i.into(), 0.into())))
+            .collect();
+
+        let mut unbuffered = CountingWriter { writes: 0, bytes: vec![] };
+        for packet in &packets {
+            packet.write(&mut unbuffered, connection.clone()).await.unwrap();
+        }
+
+        let (sender, receiver) = mpsc::channel(64);
+        for packet in packets {
+            sender.send(SendPacket(packet)).await.unwrap();
+        }
+        sender.send(Close).await.unwrap();
+        let mut actor = ConnectionActor::new(receiver, connection);
+        let mut buffered = CountingWriter { writes: 0, bytes: vec![] };
+        actor.run_msg_handler(&mut buffered).await;
+
+        assert_eq!(buffered.bytes, unbuffered.bytes);
+        assert_eq!(unbuffered.writes, 49);
+        assert_eq!(buffered.writes, 1);
+    }
+
+    #[tokio::test]
+    async fn saturated_queue_is_slow_client() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let timeout = Duration::from_millis(50);
+        // nobody reads from the queue, like a message handler stuck writing to a client that doesn't read
+        enqueue(&sender, Close, timeout).await.unwrap();
+        enqueue(&sender, Close, timeout).await.unwrap();
+        match enqueue(&sender, Close, timeout).await {
+            Err(e) => assert!(matches!(e, ProtError::SlowClient) && e.is_fatal()),
+            Ok(_) => panic!("Expected the client to be considered slow"),
+        }
+
+        // once the writer catches up there's room again
+        receiver.recv().await.unwrap();
+        assert!(enqueue(&sender, Close, timeout).await.is_ok());
+    }
+
+    #[test]
+    fn settings_fall_back_to_defaults() {
+        assert_eq!(parse_or("RUSTCRAFT_PORT", None, PORT), 25565);
+        assert_eq!(parse_or("RUSTCRAFT_PORT", Some("25570".into()), PORT), 25570);
+        assert_eq!(parse_or("RUSTCRAFT_PORT", Some("70000".into()), PORT), 25565);
+        assert_eq!(parse_or("RUSTCRAFT_PORT", Some("".into()), PORT), 25565);
+        assert!(!parse_or("RUSTCRAFT_ONLINE", Some(" false ".into()), true));
+        assert!(parse_or("RUSTCRAFT_ONLINE", Some("no".into()), true));
+    }
+
+    #[tokio::test]
+    async fn day_cycle_paused_while_empty() {
+        use crate::protocol_types::traits::ServerPacket;
+        let mut assets = Assets::for_test().await;
+        assets.pause_when_empty = true;
+        let assets = Arc::new(assets);
+        tick_day_cycle(&assets).await;
+        assert_eq!(assets.world.read().await.world_age, 0);
+
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().set_state(ConnectionState::Configuration);
+        crate::packets::server::ConfigurationFinish {}.handle(connection.clone(), assets.clone()).await.unwrap();
+        assert_eq!(assets.online_players.load(Ordering::Acquire), 1);
+        tick_day_cycle(&assets).await;
+        assert_eq!(assets.world.read().await.world_age, TICKS_PER_SECOND);
+
+        leave(&connection, &assets).await;
+        tick_day_cycle(&assets).await;
+        assert_eq!(assets.world.read().await.world_age, TICKS_PER_SECOND);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_block_updates() {
+        let assets = Arc::new(Assets::for_test().await);
+        let tasks: Vec<_> = [STONE, AIR]
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let assets = assets.clone();
+                tokio::spawn(async move {
+                    for _ in 0..100 {
+                        let mut world = assets.world.write().await;
+                        world.set_block(Position::new(i as i32, -20, 0), block, &assets.global_palette).await.unwrap();
+                        // yield while holding the lock, like handlers writing packets do
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+        }
+        let world = assets.world.read().await;
+        assert_eq!(world.block(Position::new(0, -20, 0)), Some(STONE));
+        assert_eq!(world.block(Position::new(1, -20, 0)), Some(AIR));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn broadcast_closes_slow_client() {
+        let assets = Arc::new(Assets::for_test().await);
+        let slow = Arc::new(RwLock::new(ConnectionInfo::new()));
+        // the receiver is kept but never drained, like an actor stuck writing to the client
+        let (handle, _receiver) = ConnectionActorHandle::detached(slow.clone());
+        while handle.sender.try_send(Close).is_ok() {}
+        assets.connections.write().await.push(handle);
+
+        let task = {
+            let assets = assets.clone();
+            tokio::spawn(async move { broadcast(&assets, None, ClientPackets::UpdateTime(client::UpdateTime::new(0, 0))).await })
+        };
+        tokio::task::yield_now().await;
+        // the registry stays available while the broadcast waits for the full queue
+        drop(tokio::time::timeout(Duration::from_secs(1), assets.connections.write()).await.expect("registry is locked"));
+        task.await.unwrap();
+        assert!(slow.read().unwrap().closed());
+    }
+
+    #[tokio::test]
+    async fn shut_down_fails_pending_sends() {
+        let assets = Assets::for_test().await;
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let (sender, receiver) = mpsc::channel(8);
+        assets.connections.write().await.push(ConnectionActorHandle { sender: sender.clone(), connection: connection.clone() });
+        while sender.try_send(Close).is_ok() {}
+        let blocked = tokio::spawn(async move { sender.send(Close).await.is_err() });
+        tokio::task::yield_now().await;
+
+        let mut actor = ConnectionActor::new(receiver, connection);
+        actor.shut_down(&assets).await;
+        let failed = tokio::time::timeout(Duration::from_secs(1), blocked).await.expect("sender still blocked");
+        assert!(failed.unwrap());
+        assert!(assets.connections.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaving_removes_entity() {
+        let assets = Assets::for_test().await;
+        let other = Arc::new(RwLock::new(ConnectionInfo::new()));
+        other.write().unwrap().set_state(ConnectionState::Play);
+        let (handle, mut receiver) = ConnectionActorHandle::detached(other);
+        assets.connections.write().await.push(handle);
+
+        let connection = Arc::new(RwLock::new(ConnectionInfo::new()));
+        let entity_id = {
+            let mut connection = connection.write().unwrap();
+            connection.username = "alice".into();
+            connection.uuid = crate::protocol_util::name_uuid("alice".into());
+            connection.set_state(ConnectionState::Play);
+            connection.entity_id = 42;
+            connection.close();
+            connection.entity_id
+        };
+        assets.connections.write().await.push(ConnectionActorHandle::detached(connection.clone()).0);
+
+        leave(&connection, &assets).await;
+        assert_eq!(assets.connections.read().await.len(), 1);
+        match receiver.recv().await {
+            Some(Broadcast(_, ClientPackets::RemoveEntities(packet))) => {
+                assert!(format!("{packet:?}").contains(&format!("{entity_id}")));
+            }
+            _ => panic!("Expected a RemoveEntities packet"),
+        }
+        assert!(matches!(receiver.recv().await, Some(Broadcast(_, ClientPackets::PlayerInfoRemove(_)))));
     }
 }
@@ -0,0 +1,113 @@
+use std::env;
+
+use base64::Engine;
+use base64::engine::general_purpose;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::protocol_util::name_uuid;
+
+// replaced by the base64 encoded server icon in the status response template
+const FAVICON_PLACEHOLDER: &str = "§§§";
+
+/**
+ * Puts the server icon into the favicon of the status response template `motd`. Without an icon the favicon is
+ * removed instead, as some clients reject a status whose favicon isn't a valid PNG data URL.
+ */
+pub(crate) fn with_favicon(motd: &str, icon: Option<&[u8]>) -> Result<String, serde_json::Error> {
+    if let Some(icon) = icon {
+        return Ok(motd.replacen(FAVICON_PLACEHOLDER, &general_purpose::STANDARD.encode(icon), 1));
+    }
+    let mut status: Value = serde_json::from_str(motd)?;
+    if let Some(status) = status.as_object_mut() {
+        status.remove("favicon");
+    }
+    serde_json::to_string(&status)
+}
+
+/**
+ * Reads the static player sample shown in the server list from the STATUS_SAMPLE environment variable,
+ * a comma separated list of names. The sample is empty if the variable isn't set.
+ */
+pub(crate) fn static_sample_from_env() -> Vec<String> {
+    env::var("STATUS_SAMPLE")
+        .map(|names| {
+            names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/**
+ * Fills the player count and sample of the status response template `motd`.
+ * The sample lists the configured static entries followed by the players that are currently online,
+ * unless `hide_sample` is set, in which case only the player count is reported.
+ */
+pub(crate) fn status_json(motd: &str, static_sample: &[String], online: &[(String, Uuid)], hide_sample: bool) -> Result<String, serde_json::Error> {
+    let mut status: Value = serde_json::from_str(motd)?;
+    if hide_sample {
+        status["players"]["online"] = json!(online.len());
+        status["players"]["sample"] = json!([]);
+        return serde_json::to_string(&status);
+    }
+    let sample: Vec<Value> = static_sample
+        .iter()
+        .map(|name| (name.clone(), name_uuid(format!("OfflinePlayer:{name}"))))
+        .chain(online.iter().cloned())
+        .map(|(name, id)| json!({ "name": name, "id": id.to_string() }))
+        .collect();
+    status["players"]["online"] = json!(online.len());
+    status["players"]["sample"] = Value::Array(sample);
+    serde_json::to_string(&status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MSG;
+
+    #[test]
+    fn static_sample_in_status() {
+        let online = [("steve".to_string(), Uuid::nil())];
+        let status = status_json(MSG, &["alex".into(), "notch".into()], &online, false).unwrap();
+        let status: Value = serde_json::from_str(&status).unwrap();
+        let names: Vec<&str> = status["players"]["sample"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["alex", "notch", "steve"]);
+        assert_eq!(status["players"]["online"], 1);
+    }
+
+    #[test]
+    fn empty_sample() {
+        let status = status_json(MSG, &[], &[], false).unwrap();
+        let status: Value = serde_json::from_str(&status).unwrap();
+        assert_eq!(status["players"]["sample"], json!([]));
+        assert_eq!(status["players"]["online"], 0);
+    }
+
+    #[test]
+    fn favicon_without_icon() {
+        let status: Value = serde_json::from_str(&with_favicon(MSG, None).unwrap()).unwrap();
+        assert!(status.get("favicon").is_none());
+        assert_eq!(status["version"]["protocol"], 764);
+
+        let status: Value = serde_json::from_str(&with_favicon(MSG, Some(&[1, 2, 3])).unwrap()).unwrap();
+        assert_eq!(status["favicon"], "data:image/png;base64,AQID");
+    }
+
+    #[test]
+    fn hidden_sample() {
+        let online = [("steve".to_string(), Uuid::nil()), ("alex".to_string(), Uuid::nil())];
+        let status = status_json(MSG, &["notch".into()], &online, true).unwrap();
+        let status: Value = serde_json::from_str(&status).unwrap();
+        assert_eq!(status["players"]["sample"], json!([]));
+        assert_eq!(status["players"]["online"], 2);
+    }
+}
@@ -1,27 +1,32 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{http::StatusCode, Json, response::IntoResponse, Router, routing::get};
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
 use axum::http::{HeaderValue, Method};
-use axum::routing::put;
+use axum::routing::{post, put};
 use log::info;
 use tokio::net::TcpListener;
-use tokio::sync::oneshot;
 use tower_http::cors::CorsLayer;
+use uuid::Uuid;
 
 use rustcraft_lib::web::dto::Player;
+use crate::connection::ConnectionState;
 use crate::packets::client::{ClientPackets, DisguisedChatMessage};
-use crate::protocol_types::compound::Chat;
+use crate::protocol_types::compound::{BossBarAction, Chat, BOSS_BAR_DARKEN_SKY, BOSS_BAR_DRAGON_MUSIC, BOSS_BAR_FOG};
 
 use crate::serve::ConnectionActorHandle;
 use crate::serve::ConnectionActorMessage;
 use crate::web::PORT;
 
+// time a connection has to answer a request for its player info before it's left out of the player list
+const PLAYER_INFO_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub(crate) async fn init(connections: Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>) {
     let cors = CorsLayer::new()
-        // allow `GET` and `POST` when accessing the resource
-        .allow_methods([Method::GET, Method::PUT])
+        // allow `GET`, `PUT`, `POST` and `DELETE` when accessing the resource
+        .allow_methods([Method::GET, Method::PUT, Method::POST, Method::DELETE])
         // allow requests from any origin
         .allow_origin("http://127.0.0.1:8000".parse::<HeaderValue>().unwrap());
 
@@ -29,6 +34,8 @@ pub(crate) async fn init(connections: Arc<tokio::sync::RwLock<Vec<ConnectionActo
         .route("/", get(|| async { "Hello, World!" }))
         .route("/players", get(players))
         .route("/chat", put(send_chat_message))
+        .route("/players/:uuid/title", post(send_title))
+        .route("/players/:uuid/bossbars/:id", put(show_bossbar).delete(remove_bossbar))
         .with_state(connections)
         .layer(cors);
 
@@ -43,12 +50,14 @@ pub(crate) async fn init(connections: Arc<tokio::sync::RwLock<Vec<ConnectionActo
 
 async fn players(State(connections): State<Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>>) -> impl IntoResponse {
     let mut players: Vec<Player> = vec![];
-    let connections = connections.read().await;
+    // the handles are copied out so connections can join or leave while waiting for the actors to respond
+    let connections = connections.read().await.clone();
     for connection in connections.iter() {
-        let (sender, receiver) = oneshot::channel();
-        connection.send(ConnectionActorMessage::PlayerInfo(sender)).await;
-        let player = receiver.await.unwrap();
-        players.push(player);
+        // connections that have shut down or are too busy to answer are left out
+        if let Some(mut player) = connection.player_info(PLAYER_INFO_TIMEOUT).await {
+            player.send_queue_depth = connection.queue_depth();
+            players.push(player);
+        }
     }
     (StatusCode::OK, Json(players))
 }
@@ -62,7 +71,7 @@ async fn send_chat_message(
     State(connections): State<Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>>,
     query: Query<SendChatQuery>,
 ) -> impl IntoResponse {
-    let connections = connections.read().await;
+    let connections = connections.read().await.clone();
     for connection in connections.iter() {
         connection.send(ConnectionActorMessage::SendPacket(
             ClientPackets::DisguisedChatMessage(DisguisedChatMessage::new(
@@ -76,3 +85,259 @@ async fn send_chat_message(
     }
     (StatusCode::OK, Json(()))
 }
+
+#[derive(serde::Deserialize)]
+struct SendTitleBody {
+    title: String,
+    subtitle: Option<String>,
+    // in ticks, the client's defaults are used if not given
+    fade_in: Option<i32>,
+    stay: Option<i32>,
+    fade_out: Option<i32>,
+}
+
+async fn send_title(
+    State(connections): State<Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>>,
+    Path(uuid): Path<String>,
+    Json(body): Json<SendTitleBody>,
+) -> impl IntoResponse {
+    let connection = match connection_of(&connections, &uuid).await {
+        Ok(connection) => connection,
+        Err(status) => return status,
+    };
+    connection.send_title(
+        Chat::new_text(body.title),
+        body.subtitle.map(Chat::new_text),
+        body.fade_in.unwrap_or(10),
+        body.stay.unwrap_or(70),
+        body.fade_out.unwrap_or(20),
+    ).await;
+    StatusCode::OK
+}
+
+#[derive(serde::Deserialize)]
+struct ShowBossBarBody {
+    title: String,
+    // from 0 to 1
+    health: f32,
+    // indexes into the protocol's lists of colors and divisions (number of notches), pink without notches if not given
+    color: Option<i32>,
+    division: Option<i32>,
+    #[serde(default)]
+    darken_sky: bool,
+    #[serde(default)]
+    dragon_music: bool,
+    #[serde(default)]
+    fog: bool,
+}
+
+/**
+ * Shows the boss bar with the given id to a player, replacing the one they are shown with the same id.
+ */
+async fn show_bossbar(
+    State(connections): State<Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>>,
+    Path((uuid, id)): Path<(String, String)>,
+    Json(body): Json<ShowBossBarBody>,
+) -> impl IntoResponse {
+    let connection = match connection_of(&connections, &uuid).await {
+        Ok(connection) => connection,
+        Err(status) => return status,
+    };
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let flags = [(body.darken_sky, BOSS_BAR_DARKEN_SKY), (body.dragon_music, BOSS_BAR_DRAGON_MUSIC), (body.fog, BOSS_BAR_FOG)]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag);
+    connection.show_bossbar(id, BossBarAction::Add {
+        title: Chat::new_text(body.title),
+        health: body.health.clamp(0.0, 1.0),
+        color: body.color.unwrap_or(0).into(),
+        division: body.division.unwrap_or(0).into(),
+        flags,
+    }).await;
+    StatusCode::OK
+}
+
+async fn remove_bossbar(
+    State(connections): State<Arc<tokio::sync::RwLock<Vec<ConnectionActorHandle>>>>,
+    Path((uuid, id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let connection = match connection_of(&connections, &uuid).await {
+        Ok(connection) => connection,
+        Err(status) => return status,
+    };
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    connection.show_bossbar(id, BossBarAction::Remove).await;
+    StatusCode::OK
+}
+
+// the connection of the online player with the given uuid, or the status to respond with if there is none.
+// Players that are still logging in or configuring can't be sent titles or boss bars yet.
+async fn connection_of(connections: &tokio::sync::RwLock<Vec<ConnectionActorHandle>>, uuid: &str) -> Result<ConnectionActorHandle, StatusCode> {
+    let uuid = Uuid::parse_str(uuid).or(Err(StatusCode::BAD_REQUEST))?;
+    connections
+        .read()
+        .await
+        .iter()
+        .find(|handle| {
+            let connection = handle.connection().read().unwrap();
+            connection.uuid == uuid && !connection.closed() && matches!(connection.state(), ConnectionState::Play)
+        })
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod test {
+    use rustcraft_lib::web::dto::Position;
+    use tokio::sync::mpsc::Receiver;
+
+    use super::*;
+    use crate::connection::ConnectionInfo;
+    use crate::packets::client;
+
+    // answers player info requests like the actor's message handler
+    async fn answer_player_info(mut receiver: Receiver<ConnectionActorMessage>) {
+        while let Some(msg) = receiver.recv().await {
+            if let ConnectionActorMessage::PlayerInfo(sender) = msg {
+                let _ = sender.send(Player {
+                    username: "alice".into(),
+                    uuid: String::new(),
+                    brand: None,
+                    send_queue_depth: 0,
+                    position: Position { x: 0.0, y: 0.0, z: 0.0, pitch: 0.0, yaw: 0.0, on_ground: true },
+                });
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn players_while_connections_change() {
+        let connections = Arc::new(tokio::sync::RwLock::new(vec![]));
+        let writer = {
+            let connections = connections.clone();
+            tokio::spawn(async move {
+                for _ in 0..100 {
+                    let (handle, receiver) = ConnectionActorHandle::detached(Arc::new(std::sync::RwLock::new(ConnectionInfo::new())));
+                    let actor = tokio::spawn(answer_player_info(receiver));
+                    connections.write().await.push(handle);
+                    tokio::task::yield_now().await;
+                    connections.write().await.clear();
+                    actor.abort();
+                }
+            })
+        };
+        let response = tokio::time::timeout(Duration::from_secs(5), players(State(connections)))
+            .await
+            .expect("players didn't respond")
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let players: Vec<Player> = serde_json::from_slice(&body).unwrap();
+        assert!(players.len() <= 1);
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn players_lists_live_connections() {
+        let connection = Arc::new(std::sync::RwLock::new(ConnectionInfo::new()));
+        connection.write().unwrap().username = "alice".into();
+        let (live, receiver) = ConnectionActorHandle::detached(connection);
+        let actor = tokio::spawn(answer_player_info(receiver));
+        // the actor of this one has shut down
+        let (closed, receiver) = ConnectionActorHandle::detached(Arc::new(std::sync::RwLock::new(ConnectionInfo::new())));
+        drop(receiver);
+        // and this one's queue is full because its actor is stuck
+        let (full, _stuck) = ConnectionActorHandle::detached(Arc::new(std::sync::RwLock::new(ConnectionInfo::new())));
+        while full.try_send(ConnectionActorMessage::Close) {}
+        let connections = Arc::new(tokio::sync::RwLock::new(vec![closed, full, live]));
+
+        let response = tokio::time::timeout(Duration::from_secs(5), players(State(connections.clone())))
+            .await
+            .expect("players didn't respond")
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let players: Vec<Player> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].username, "alice");
+        drop(connections);
+        actor.abort();
+    }
+
+    #[tokio::test]
+    async fn title_sent_to_player() {
+        let connection = Arc::new(std::sync::RwLock::new(ConnectionInfo::new()));
+        let uuid = crate::protocol_util::name_uuid("alice".into());
+        connection.write().unwrap().uuid = uuid;
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let (handle, mut receiver) = ConnectionActorHandle::detached(connection);
+        let connections = Arc::new(tokio::sync::RwLock::new(vec![handle]));
+
+        let body = SendTitleBody { title: "Hello".into(), subtitle: Some("there".into()), fade_in: None, stay: Some(40), fade_out: None };
+        let status = send_title(State(connections.clone()), Path(uuid.to_string()), Json(body)).await.into_response().status();
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(receiver.recv().await, Some(ConnectionActorMessage::SendPacket(ClientPackets::SetTitleAnimationTimes(_)))));
+        assert!(matches!(receiver.recv().await, Some(ConnectionActorMessage::SendPacket(ClientPackets::SetSubtitleText(_)))));
+        assert!(matches!(receiver.recv().await, Some(ConnectionActorMessage::SendPacket(ClientPackets::SetTitleText(_)))));
+
+        let body = SendTitleBody { title: "Hello".into(), subtitle: None, fade_in: None, stay: None, fade_out: None };
+        let status = send_title(State(connections), Path(Uuid::nil().to_string()), Json(body)).await.into_response().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn title_not_sent_before_play() {
+        let connection = Arc::new(std::sync::RwLock::new(ConnectionInfo::new()));
+        let uuid = crate::protocol_util::name_uuid("alice".into());
+        connection.write().unwrap().uuid = uuid;
+        connection.write().unwrap().set_state(ConnectionState::Configuration);
+        let (handle, mut receiver) = ConnectionActorHandle::detached(connection);
+        let connections = Arc::new(tokio::sync::RwLock::new(vec![handle]));
+
+        let body = SendTitleBody { title: "Hello".into(), subtitle: None, fade_in: None, stay: None, fade_out: None };
+        let status = send_title(State(connections.clone()), Path(uuid.to_string()), Json(body)).await.into_response().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let status = remove_bossbar(State(connections), Path((uuid.to_string(), Uuid::from_u128(7).to_string()))).await.into_response().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn bossbar_shown_and_removed() {
+        let connection = Arc::new(std::sync::RwLock::new(ConnectionInfo::new()));
+        let uuid = crate::protocol_util::name_uuid("alice".into());
+        connection.write().unwrap().uuid = uuid;
+        connection.write().unwrap().set_state(ConnectionState::Play);
+        let (handle, mut receiver) = ConnectionActorHandle::detached(connection);
+        let connections = Arc::new(tokio::sync::RwLock::new(vec![handle]));
+        let id = Uuid::from_u128(7);
+
+        let body = ShowBossBarBody { title: "Boss".into(), health: 1.5, color: None, division: Some(2), darken_sky: true, dragon_music: false, fog: true };
+        let status = show_bossbar(State(connections.clone()), Path((uuid.to_string(), id.to_string())), Json(body)).await.into_response().status();
+        assert_eq!(status, StatusCode::OK);
+        let expected = client::BossBar::new(id, BossBarAction::Add {
+            title: Chat::new_text("Boss".into()),
+            health: 1.0,
+            color: 0.into(),
+            division: 2.into(),
+            flags: BOSS_BAR_DARKEN_SKY | BOSS_BAR_FOG,
+        });
+        match receiver.recv().await {
+            Some(ConnectionActorMessage::SendPacket(ClientPackets::BossBar(bossbar))) => {
+                assert_eq!(format!("{bossbar:?}"), format!("{expected:?}"));
+            }
+            _ => panic!("Expected a boss bar"),
+        }
+
+        let status = remove_bossbar(State(connections.clone()), Path((uuid.to_string(), id.to_string()))).await.into_response().status();
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(receiver.recv().await, Some(ConnectionActorMessage::SendPacket(ClientPackets::BossBar(_)))));
+        let status = remove_bossbar(State(connections), Path((uuid.to_string(), "boss".into()))).await.into_response().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}